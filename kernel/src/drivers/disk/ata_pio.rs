@@ -1,11 +1,82 @@
+use crate::{
+    drivers::{
+        disk::dma::{self, BusMaster, DMA_BUFFER_SIZE},
+        interrupts::interrupts::{ticks, unmask_irq},
+    },
+    log_error, log_warn,
+};
+use alloc::string::String;
+use core::{
+    fmt::Write as _,
+    sync::atomic::{AtomicBool, Ordering},
+};
 use fatfs::{IoBase, Read, Seek, SeekFrom, Write};
-use x86_64::instructions::port::Port;
+use x86_64::instructions::{interrupts, port::Port};
+#[cfg(feature = "async-disk")]
+use {
+    core::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    futures_util::task::AtomicWaker,
+};
+
+/// IRQ line of the first channel, the second one uses the next line.
+const FIRST_CHANNEL_IRQ: u8 = 14;
+
+/// Timer ticks to wait for an interrupt before polling the drive instead,
+/// about half a second.
+const IRQ_TIMEOUT_TICKS: u64 = 10;
+
+/// Set by the ATA interrupt handlers, indexed by channel.
+static IRQ_RECEIVED: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+/// Woken by the ATA interrupt handlers, indexed by channel.
+#[cfg(feature = "async-disk")]
+static IRQ_WAKERS: [AtomicWaker; 2] = [AtomicWaker::new(), AtomicWaker::new()];
+
+/// Called by the ATA interrupt handlers, must not block or allocate.
+pub(crate) fn irq_received(channel: usize) {
+    IRQ_RECEIVED[channel].store(true, Ordering::Release);
+    #[cfg(feature = "async-disk")]
+    IRQ_WAKERS[channel].wake();
+}
+
+/// Completes once the drive on the given channel raised an interrupt.
+#[cfg(feature = "async-disk")]
+struct IrqFuture {
+    channel: usize,
+}
+
+#[cfg(feature = "async-disk")]
+impl Future for IrqFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let flag = &IRQ_RECEIVED[self.channel];
+        // fast path
+        if flag.swap(false, Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        IRQ_WAKERS[self.channel].register(&cx.waker());
+        if flag.swap(false, Ordering::Acquire) {
+            IRQ_WAKERS[self.channel].take();
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
 
 #[repr(u8)]
 #[derive(Copy, Clone)]
 enum StatusBits {
     Busy = 0x80,
+    DriveFault = 0x20,
     RwReady = 0x08,
+    Error = 0x01,
 }
 
 impl StatusBits {
@@ -18,7 +89,10 @@ impl StatusBits {
 enum Command {
     Read = 0x20,
     Write = 0x30,
+    ReadDma = 0xC8,
+    WriteDma = 0xCA,
     CacheFlush = 0xE7,
+    Identify = 0xEC,
 }
 
 #[repr(C)]
@@ -39,26 +113,416 @@ enum ControlPort {
     Status,
 }
 
-type Sector = [u16; 256];
+/// Size of a sector in bytes. Drives with larger logical sectors are not supported.
+pub const SECTOR_SIZE: usize = 512;
+/// Size of a sector in 16-bit words, as transferred through the data port.
+const SECTOR_WORDS: usize = SECTOR_SIZE / 2;
+
+/// A sector as read from the data port.
+pub type Sector = [u16; SECTOR_WORDS];
+
+/// Highest sector addressable with 28-bit LBA.
+const MAX_LBA: u64 = (1 << 28) - 1;
+
+/// Most sectors transferred by a single command, as the sector count register is 8-bit.
+const MAX_COMMAND_SECTORS: usize = u8::MAX as usize;
+
+/// Most bytes transferred with a single PIO command; larger transfers are split.
+/// An unaligned transfer touches up to one sector more than it fills,
+/// which keeps it within `MAX_COMMAND_SECTORS`.
+const PIO_CHUNK_SIZE: usize = (MAX_COMMAND_SECTORS - 1) * SECTOR_SIZE;
+
+/// Errors of drive operations.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AtaError {
+    /// The sector is not addressable by the drive.
+    OutOfRange,
+    /// The checksum of the read sector did not match the expected one.
+    ChecksumMismatch { lba: u64 },
+    /// Tried to seek past the end of the drive.
+    SeekOutOfBounds,
+    /// The drive reports sectors of the given size in bytes,
+    /// but only `SECTOR_SIZE` is supported.
+    UnsupportedSectorSize(usize),
+
+    // Errors reported by the drive, decoded from its error register.
+    /// Bad block detected (BBK).
+    BadBlock,
+    /// Uncorrectable data error (UNC).
+    UncorrectableData,
+    /// The medium was changed (MC) or the user requested to change it (MCR).
+    /// Data read before might not match the medium anymore.
+    MediaChanged,
+    /// Sector ID not found (IDNF).
+    IdNotFound,
+    /// Command aborted (ABRT).
+    Aborted,
+    /// Track zero not found (TKZNF).
+    TrackZeroNotFound,
+    /// Address mark not found (AMNF).
+    AddressMarkNotFound,
+    /// The drive reported a fault or an error without giving a reason.
+    DriveFault,
+}
+
+impl AtaError {
+    /// Decode the error register, which is only valid if the status has the error bit set.
+    /// If multiple bits are set, the first in the order of the variants is returned.
+    pub fn from_register(register: u8) -> AtaError {
+        const BITS: [(u8, AtaError); 8] = [
+            (0x80, AtaError::BadBlock),
+            (0x40, AtaError::UncorrectableData),
+            (0x20, AtaError::MediaChanged),
+            (0x10, AtaError::IdNotFound),
+            (0x08, AtaError::MediaChanged),
+            (0x04, AtaError::Aborted),
+            (0x02, AtaError::TrackZeroNotFound),
+            (0x01, AtaError::AddressMarkNotFound),
+        ];
+        BITS.iter()
+            .find(|(bit, _)| register & bit != 0)
+            .map(|(_, error)| *error)
+            .unwrap_or(AtaError::DriveFault)
+    }
+}
 
 /// Represents an attached ATA PIO drive.
 /// The secondary drive of the main ATA controller is used.
+/// If a bus master was found by `dma::init` or set using `enable_dma`,
+/// transfers are done using DMA instead of PIO.
+/// If `enable_interrupts` was called, the CPU is halted while
+/// waiting for the drive instead of polling its status.
 pub struct AtaDrive {
     io_base: u16,
     control_base: u16,
     position: usize,
+    dma: Option<BusMaster>,
+    use_interrupts: bool,
+    /// Size of the drive in bytes, if the drive reported it
+    /// or it could be probed.
+    capacity: Option<u64>,
+    seek_mode: SeekMode,
+}
+
+/// What seeking past the end of the drive does.
+/// Only applies if the drive's capacity is known.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SeekMode {
+    /// Fail with `AtaError::SeekOutOfBounds`, leaving the position unchanged.
+    Error,
+    /// Move to the end of the drive instead.
+    Clamp,
 }
 
 impl AtaDrive {
-    /// Setup the controller to perform a read or write at the current position.
-    fn before_read_write(&self, sector_count: u8) {
+    /// Size of the drive in bytes, if the drive reported it.
+    /// Drives not answering IDENTIFY are probed instead, see `probe_capacity`.
+    pub fn capacity(&self) -> Option<u64> {
+        self.capacity
+    }
+
+    /// Set what seeking past the end of the drive does, the default is to error.
+    pub fn set_seek_mode(&mut self, mode: SeekMode) {
+        self.seek_mode = mode;
+    }
+
+    /// Returns the position to move to when seeking to `pos`, see `SeekMode`.
+    fn checked_position(&self, pos: u64) -> Result<u64, AtaError> {
+        match self.capacity {
+            Some(capacity) if pos > capacity => match self.seek_mode {
+                SeekMode::Error => Err(AtaError::SeekOutOfBounds),
+                SeekMode::Clamp => Ok(capacity),
+            },
+            _ => Ok(pos),
+        }
+    }
+
+    /// Ask the drive for its capacity in bytes using IDENTIFY.
+    /// Returns None if the drive does not support the command,
+    /// and an error if its sectors are not `SECTOR_SIZE` bytes.
+    fn identify(&self) -> Result<Option<u64>, AtaError> {
+        self.wait_status(StatusBits::Busy, false);
+        self.io_write(IoPort::DriveSel, 0xF0);
+        self.io_write(IoPort::SectorCount, 0);
+        self.io_write(IoPort::LbaLow, 0);
+        self.io_write(IoPort::LbaMid, 0);
+        self.io_write(IoPort::LbaHigh, 0);
+        self.send_command(Command::Identify);
+        // Status 0 = drive does not exist
+        if self.io_read(IoPort::Status) == 0 {
+            return Ok(None);
+        }
+        if self.wait_ready().is_err() {
+            return Ok(None);
+        }
+
+        let mut data_port = self.io_port_16(IoPort::Data);
+        let mut data = [0u16; SECTOR_WORDS];
+        for word in &mut data {
+            *word = unsafe { data_port.read() };
+        }
+
+        // Word 106: bit 14 set and 15 clear = valid, bit 12 = sectors larger than 512 bytes
+        if data[106] & 0xD000 == 0x5000 {
+            let words = data[117] as usize | (data[118] as usize) << 16;
+            if words != SECTOR_WORDS {
+                return Err(AtaError::UnsupportedSectorSize(words * 2));
+            }
+        }
+
+        // Word 83 bit 10 = 48-bit LBA supported, with the sector count
+        // in words 100-103; otherwise the 28-bit count is in words 60-61
+        let sectors = if data[83] & (1 << 10) != 0 {
+            (0..4).fold(0, |count, i| count | (data[100 + i] as u64) << (i * 16))
+        } else {
+            data[60] as u64 | (data[61] as u64) << 16
+        };
+        Ok(Some(sectors * SECTOR_SIZE as u64))
+    }
+
+    /// Estimate the capacity in bytes by reading sectors until a read fails,
+    /// for drives that do not answer IDENTIFY. The last readable sector is
+    /// found by doubling the LBA until a read fails and then binary searching.
+    /// Returns None if not even the first sector can be read.
+    fn probe_capacity(&mut self) -> Option<u64> {
+        // Status 0 = drive does not exist, reads would wait forever
+        if self.io_read(IoPort::Status) == 0 {
+            return None;
+        }
+        let mut readable = |lba| self.read_raw_sector(lba).is_ok();
+        if !readable(0) {
+            return None;
+        }
+
+        // Invariant: `good` can be read, `bad` cannot or is past MAX_LBA
+        let mut good = 0;
+        let mut bad = 1;
+        while bad <= MAX_LBA && readable(bad) {
+            good = bad;
+            bad *= 2;
+        }
+        bad = bad.min(MAX_LBA + 1);
+        while bad - good > 1 {
+            let mid = good + (bad - good) / 2;
+            if readable(mid) {
+                good = mid;
+            } else {
+                bad = mid;
+            }
+        }
+        Some((good + 1) * SECTOR_SIZE as u64)
+    }
+
+    /// Check if the drive is still attached, for example before retrying
+    /// after `AtaError::MediaChanged`. A floating bus reads as 0xFF.
+    pub fn is_present(&mut self) -> bool {
+        // The alternate status does not acknowledge pending interrupts
+        let status = unsafe { self.con_port(ControlPort::Status).read() };
+        status != 0xFF
+    }
+
+    /// Wait for the drive's completion interrupt instead of polling its status.
+    /// Returns false and keeps polling if the drive is not on one of
+    /// the standard channels or interrupts are currently disabled.
+    pub fn enable_interrupts(&mut self) -> bool {
+        self.use_interrupts = match self.channel() {
+            Some(channel) if interrupts::are_enabled() => {
+                unmask_irq(FIRST_CHANNEL_IRQ + channel as u8);
+                true
+            }
+            _ => false,
+        };
+        self.use_interrupts
+    }
+
+    /// Returns the index of the channel the drive is attached to,
+    /// if it is one of the two standard ones with a known IRQ.
+    fn channel(&self) -> Option<usize> {
+        match self.io_base {
+            0x1F0 => Some(0),
+            0x170 => Some(1),
+            _ => None,
+        }
+    }
+
+    /// Halt until the drive raises an interrupt, if interrupts are enabled.
+    /// The status should still be checked afterwards; this only avoids spinning.
+    /// If no interrupt arrives within `IRQ_TIMEOUT_TICKS`, the drive's
+    /// status is polled instead so a lost interrupt does not hang.
+    fn wait_irq(&self) {
+        let flag = match self.channel() {
+            Some(channel) if self.use_interrupts => &IRQ_RECEIVED[channel],
+            _ => return,
+        };
+
+        let deadline = ticks() + IRQ_TIMEOUT_TICKS;
+        loop {
+            // Disable interrupts while checking to not miss one
+            // arriving between the check and halting
+            interrupts::disable();
+            if flag.swap(false, Ordering::Acquire) {
+                interrupts::enable();
+                return;
+            }
+            if ticks() >= deadline {
+                interrupts::enable();
+                log_warn!("ATA: no interrupt from the drive, polling instead");
+                self.wait_status(StatusBits::Busy, false);
+                return;
+            }
+            // The timer interrupt wakes up the CPU to check the deadline
+            interrupts::enable_and_hlt();
+        }
+    }
+
+    /// Read the sector at `lba` using PIO, bypassing DMA and
+    /// leaving the current position unchanged.
+    pub fn read_raw_sector(&mut self, lba: u64) -> Result<Sector, AtaError> {
+        if lba > MAX_LBA {
+            return Err(AtaError::OutOfRange);
+        }
+        let position = self.position;
+        self.position = lba as usize * SECTOR_SIZE;
+        let sector = self.read_sector();
+        self.position = position;
+        sector
+    }
+
+    /// Read the sector at `lba` like `read_raw_sector`, returning its bytes
+    /// in the order they are stored on disk. See `hex_dump` for displaying them.
+    pub fn dump_sector(&mut self, lba: u64) -> Result<[u8; SECTOR_SIZE], AtaError> {
+        let sector = self.read_raw_sector(lba)?;
+        let mut bytes = [0; SECTOR_SIZE];
+        for (chunk, word) in bytes.chunks_exact_mut(2).zip(sector.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(bytes)
+    }
+
+    /// Read the sector at `lba` like `read_raw_sector`, comparing its
+    /// checksum (see `sector_checksum`) to the one at index `lba` in `checksums`.
+    /// Sectors without an entry in the table are not verified.
+    pub fn read_verified(&mut self, lba: u64, checksums: &[u32]) -> Result<Sector, AtaError> {
+        let sector = self.read_raw_sector(lba)?;
+        match checksums.get(lba as usize) {
+            Some(&expected) if sector_checksum(&sector) != expected => {
+                Err(AtaError::ChecksumMismatch { lba })
+            }
+            _ => Ok(sector),
+        }
+    }
+
+    /// Use the given bus master for all further transfers.
+    /// It must belong to the channel this drive is attached to.
+    pub fn enable_dma(&mut self, dma: BusMaster) {
+        self.dma = Some(dma);
+    }
+
+    /// Are transfers done using DMA?
+    pub fn dma_enabled(&self) -> bool {
+        self.dma.is_some()
+    }
+
+    /// Read into `buf` at the current position using DMA,
+    /// in chunks of at most the size of the DMA buffer.
+    fn read_dma(&mut self, dma: &mut BusMaster, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut done = 0;
+        while done < buf.len() {
+            let offset = self.position % SECTOR_SIZE;
+            let len = (buf.len() - done).min(DMA_BUFFER_SIZE - offset);
+            let sector_count = self.min_required_sector_count(len);
+
+            self.dma_transfer(dma, sector_count, true)?;
+            buf[done..(done + len)].copy_from_slice(&dma.buffer()[offset..(offset + len)]);
+
+            done += len;
+            self.position += len;
+        }
+        Ok(buf.len())
+    }
+
+    /// Write `buf` at the current position using DMA,
+    /// in chunks of at most the size of the DMA buffer.
+    /// Like PIO, DMA can only write entire sectors, see `get_partial_write_sectors`.
+    fn write_dma(&mut self, dma: &mut BusMaster, buf: &[u8]) -> Result<usize, ()> {
+        let mut done = 0;
+        while done < buf.len() {
+            let offset = self.position % SECTOR_SIZE;
+            let len = (buf.len() - done).min(DMA_BUFFER_SIZE - offset);
+            let sector_count = self.min_required_sector_count(len);
+
+            let (start_sector, end_sector) =
+                self.get_partial_write_sectors(len).map_err(Self::report)?;
+            let dma_buf = dma.buffer();
+            if let Some(sector) = start_sector {
+                Self::copy_sector(sector, &mut dma_buf[0..SECTOR_SIZE]);
+            }
+            if let Some(sector) = end_sector {
+                let end = (sector_count - 1) * SECTOR_SIZE;
+                Self::copy_sector(sector, &mut dma_buf[end..(end + SECTOR_SIZE)]);
+            }
+            dma_buf[offset..(offset + len)].copy_from_slice(&buf[done..(done + len)]);
+
+            self.dma_transfer(dma, sector_count, false)?;
+            done += len;
+            self.position += len;
+        }
+
+        self.send_command(Command::CacheFlush);
+        Ok(buf.len())
+    }
+
+    /// Transfer `sector_count` sectors at the current position
+    /// between the drive and the DMA buffer.
+    fn dma_transfer(&self, dma: &mut BusMaster, sector_count: usize, read: bool) -> Result<(), ()> {
+        dma.prepare(sector_count * SECTOR_SIZE, read);
+        self.before_read_write(sector_count).map_err(Self::report)?;
+        self.send_command(if read {
+            Command::ReadDma
+        } else {
+            Command::WriteDma
+        });
+        dma.start();
+
+        self.wait_irq();
+        let res = dma.wait();
+        // Reading the status register acknowledges the drive's interrupt
+        let status = self.io_read(IoPort::Status);
+        if StatusBits::Error.is_set(status) {
+            Self::report(AtaError::from_register(self.io_read(IoPort::ErrFeatures)));
+            return Err(());
+        }
+        res.map_err(|_| ())
+    }
+
+    /// Copy a sector read with PIO into a byte buffer.
+    fn copy_sector(sector: Sector, buf: &mut [u8]) {
+        for (word, bytes) in sector.iter().zip(buf.chunks_exact_mut(2)) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    /// Setup the controller to perform a read or write at the current position,
+    /// failing if any of the sectors is not addressable.
+    fn before_read_write(&self, sector_count: usize) -> Result<(), AtaError> {
+        assert!(sector_count <= MAX_COMMAND_SECTORS, "transfer not split");
         let lba = self.calc_lba();
+        let last = lba + sector_count.saturating_sub(1) as u64;
+        if last > MAX_LBA {
+            return Err(AtaError::OutOfRange);
+        }
+
         self.wait_status(StatusBits::Busy, false);
+        // Discard interrupts of previous commands
+        if let Some(channel) = self.channel() {
+            IRQ_RECEIVED[channel].store(false, Ordering::Release);
+        }
         self.io_write(IoPort::DriveSel, (0xF0 | ((lba >> 24) & 0xF)) as u8);
-        self.io_write(IoPort::SectorCount, sector_count);
+        self.io_write(IoPort::SectorCount, sector_count as u8);
         self.io_write(IoPort::LbaLow, lba as u8);
         self.io_write(IoPort::LbaMid, (lba >> 8) as u8);
         self.io_write(IoPort::LbaHigh, (lba >> 16) as u8);
+        Ok(())
     }
 
     /// Returns the start and end sectors if a write, starting at the
@@ -67,42 +531,135 @@ impl AtaDrive {
     /// This is required, since PIO only allows writing entire sectors at a time;
     /// we read the sectors affected and 'write' back that read data
     /// in places where it shouldn't change.
-    fn get_partial_write_sectors(&mut self, len: usize) -> (Option<Sector>, Option<Sector>) {
-        let start = self.read_sector_if_unaligned();
+    fn get_partial_write_sectors(
+        &mut self,
+        len: usize,
+    ) -> Result<(Option<Sector>, Option<Sector>), AtaError> {
+        let start = self.read_sector_if_unaligned()?;
         self.position += len;
         let end = self.read_sector_if_unaligned();
         self.position -= len;
-        (start, end)
+        Ok((start, end?))
     }
 
     /// Convenience function that reads the current sector if
     /// the current position is not aligned to the start of it, see above.
-    fn read_sector_if_unaligned(&self) -> Option<Sector> {
+    fn read_sector_if_unaligned(&self) -> Result<Option<Sector>, AtaError> {
         if !self.pos_aligned() {
-            Some(self.read_sector())
+            self.read_sector().map(Some)
         } else {
-            None
+            Ok(None)
         }
     }
 
     /// Read the current sector that contains `self.position`.
-    fn read_sector(&self) -> Sector {
-        self.before_read_write(1);
+    fn read_sector(&self) -> Result<Sector, AtaError> {
+        self.start_read_sector()?;
+        self.wait_irq();
+        self.finish_read_sector()
+    }
+
+    /// Send the command to read the sector that contains `self.position`.
+    fn start_read_sector(&self) -> Result<(), AtaError> {
+        self.before_read_write(1)?;
         self.send_command(Command::Read);
+        Ok(())
+    }
 
+    /// Transfer the sector requested by `start_read_sector`
+    /// once the drive is ready.
+    fn finish_read_sector(&self) -> Result<Sector, AtaError> {
         let mut data_port = self.io_port_16(IoPort::Data);
-        let mut buf = [0; 256];
-        self.wait_ready();
+        let mut buf = [0; SECTOR_WORDS];
+        self.wait_ready()?;
         for word in &mut buf {
             *word = unsafe { data_port.read() };
         }
-        buf
+        Ok(buf)
+    }
+
+    /// Like `wait_irq`, but yields to the executor instead of halting.
+    #[cfg(feature = "async-disk")]
+    async fn wait_irq_async(&self) {
+        match self.channel() {
+            Some(channel) if self.use_interrupts => IrqFuture { channel }.await,
+            _ => (),
+        }
+    }
+
+    /// Read into `buf` at the current position, advancing it.
+    /// Unlike `read`, this awaits the drive's interrupt for every sector
+    /// instead of blocking, if `enable_interrupts` was called;
+    /// otherwise it polls the drive like `read`. DMA is not used.
+    #[cfg(feature = "async-disk")]
+    pub async fn read_async(&mut self, buf: &mut [u8]) -> Result<usize, AtaError> {
+        let mut done = 0;
+        while done < buf.len() {
+            self.start_read_sector()?;
+            self.wait_irq_async().await;
+            let sector = self.finish_read_sector()?;
+
+            let offset = self.position % SECTOR_SIZE;
+            let len = (buf.len() - done).min(SECTOR_SIZE - offset);
+            let bytes = sector.iter().flat_map(|word| word.to_le_bytes());
+            for (dest, byte) in buf[done..(done + len)].iter_mut().zip(bytes.skip(offset)) {
+                *dest = byte;
+            }
+            done += len;
+            self.position += len;
+        }
+        Ok(done)
+    }
+
+    /// Wait until the drive is ready for a sector read/write,
+    /// returning the reason if it reports an error instead.
+    fn wait_ready(&self) -> Result<(), AtaError> {
+        self.wait_status(StatusBits::Busy, false);
+        let mut port = self.io_port(IoPort::Status);
+        loop {
+            let status = unsafe { port.read() };
+            if StatusBits::Error.is_set(status) {
+                return Err(AtaError::from_register(self.io_read(IoPort::ErrFeatures)));
+            }
+            if StatusBits::DriveFault.is_set(status) {
+                return Err(AtaError::DriveFault);
+            }
+            if StatusBits::RwReady.is_set(status) {
+                return Ok(());
+            }
+        }
     }
 
-    /// Wait until the drive is ready for a sector read/write.
-    fn wait_ready(&self) {
+    /// Flush the drive's write cache and wait for it to complete,
+    /// guaranteeing that all data written so far is on the medium.
+    pub fn sync(&mut self) -> Result<(), AtaError> {
         self.wait_status(StatusBits::Busy, false);
-        self.wait_status(StatusBits::RwReady, true);
+        if let Some(channel) = self.channel() {
+            IRQ_RECEIVED[channel].store(false, Ordering::Release);
+        }
+        self.io_write(IoPort::DriveSel, 0xF0);
+        self.send_command(Command::CacheFlush);
+        self.wait_irq();
+        self.wait_idle()
+    }
+
+    /// Wait until the drive is no longer busy with a command
+    /// that does not transfer data, returning its error if it failed.
+    fn wait_idle(&self) -> Result<(), AtaError> {
+        self.wait_status(StatusBits::Busy, false);
+        let status = self.io_read(IoPort::Status);
+        if StatusBits::Error.is_set(status) {
+            Err(AtaError::from_register(self.io_read(IoPort::ErrFeatures)))
+        } else if StatusBits::DriveFault.is_set(status) {
+            Err(AtaError::DriveFault)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Log an error before it is turned into the unit error fatfs expects.
+    fn report(error: AtaError) {
+        log_error!("ATA error: {:?}", error);
     }
 
     /// Wait until a status bit reaches the given state.
@@ -112,8 +669,8 @@ impl AtaDrive {
     }
 
     /// Calculate the value of `LBA` (sector index) for the current position.
-    fn calc_lba(&self) -> usize {
-        (self.position / 512) as usize
+    fn calc_lba(&self) -> u64 {
+        (self.position / SECTOR_SIZE) as u64
     }
 
     /// Send a command on the status/command IO port.
@@ -150,14 +707,14 @@ impl AtaDrive {
 
     /// Calculate the amount of sectors to be read/written when
     /// doing an operation starting at `self.current` of size `len`.
-    fn min_required_sector_count(&self, bytes: usize) -> u8 {
+    fn min_required_sector_count(&self, bytes: usize) -> usize {
         let sector_aligned = Self::is_sector_aligned(bytes) && bytes != 0;
         let bleeds_into_next = if sector_aligned {
             !self.pos_aligned()
         } else {
-            (self.position & 511) + (bytes & 511) > 512
+            (self.position % SECTOR_SIZE) + (bytes % SECTOR_SIZE) > SECTOR_SIZE
         };
-        ((bytes / 512) as u8) + bleeds_into_next as u8 + !sector_aligned as u8
+        (bytes / SECTOR_SIZE) + bleeds_into_next as usize + !sector_aligned as usize
     }
 
     /// Is `self.position` aligned on the start of a sector?
@@ -167,7 +724,7 @@ impl AtaDrive {
 
     /// Is `value` aligned on the start of a sector?
     fn is_sector_aligned(value: usize) -> bool {
-        value & 511 == 0
+        value % SECTOR_SIZE == 0
     }
 
     /// Create a new AtaDrive.
@@ -176,11 +733,18 @@ impl AtaDrive {
     /// The caller must ensure `io_base` and `control_base` are valid
     /// ports for an ATA controller.
     /// The ports for the primary controller are usually `0x1F0` and `0x3F6`.
+    ///
+    /// # Panics
+    /// If no drive is attached, or its sectors are not `SECTOR_SIZE` bytes.
     pub unsafe fn new(io_base: u16, control_base: u16) -> AtaDrive {
-        let bus = AtaDrive {
+        let mut bus = AtaDrive {
             io_base,
             control_base,
             position: 0,
+            dma: None,
+            use_interrupts: false,
+            capacity: None,
+            seek_mode: SeekMode::Error,
         };
 
         // 0xFF = illegal value / floating bus, no drive attached
@@ -188,29 +752,158 @@ impl AtaDrive {
         // Clear control/status register, should do on init
         // https://wiki.osdev.org/ATA_PIO_Mode#Device_Control_Register_.28Control_base_.2B_0.29
         bus.con_port(ControlPort::Status).write(0);
+        bus.capacity = match bus.identify() {
+            Ok(Some(capacity)) => Some(capacity),
+            Ok(None) => {
+                log_warn!("ATA: IDENTIFY failed, probing the capacity");
+                bus.probe_capacity()
+            }
+            // Every transfer would address the wrong bytes
+            Err(err) => panic!("ATA: refusing drive, {:?}", err),
+        };
+        // `dma::init` only looks for the bus master of the primary channel
+        if bus.channel() == Some(0) {
+            if let Some(dma) = dma::take() {
+                bus.enable_dma(dma);
+            }
+        }
 
         bus
     }
 }
 
+impl Drop for AtaDrive {
+    fn drop(&mut self) {
+        // Let the next drive on the channel use it
+        if let Some(dma) = self.dma.take() {
+            dma::release(dma);
+        }
+    }
+}
+
+/// Computes the CRC32 (IEEE) of a sector's bytes, for building checksum tables.
+pub fn sector_checksum(sector: &Sector) -> u32 {
+    let bytes = sector.iter().flat_map(|word| word.to_le_bytes());
+    crc32(bytes)
+}
+
+/// Bitwise CRC32 with the reflected IEEE polynomial;
+/// slow compared to a table-driven one, but only used for verification.
+fn crc32(bytes: impl Iterator<Item = u8>) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Format bytes as a classic hex dump: 16 bytes per line, prefixed with
+/// their offset and followed by their printable ASCII characters.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() / 16 * 78 + 78);
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        write!(out, "{:08x} ", line * 16).unwrap();
+        for i in 0..16 {
+            if i == 8 {
+                out.push(' ');
+            }
+            match chunk.get(i) {
+                Some(byte) => write!(out, " {:02x}", byte).unwrap(),
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        for &byte in chunk {
+            out.push(if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            });
+        }
+        out.push_str("|\n");
+    }
+    out
+}
+
+/// Compare two sectors in constant time, to not leak
+/// where they differ through timing.
+pub fn sectors_equal(a: &Sector, b: &Sector) -> bool {
+    a.iter()
+        .zip(b.iter())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
 impl IoBase for AtaDrive {
     type Error = ();
 }
 
 impl Read for AtaDrive {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        // Reads past the end of the drive are cut short
+        let buf = match self.capacity {
+            Some(capacity) => {
+                let remaining = capacity.saturating_sub(self.position as u64);
+                let len = (buf.len() as u64).min(remaining) as usize;
+                &mut buf[..len]
+            }
+            None => buf,
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if let Some(mut dma) = self.dma.take() {
+            let res = self.read_dma(&mut dma, buf);
+            self.dma = Some(dma);
+            return res;
+        }
+
+        self.read_pio_chunked(buf, PIO_CHUNK_SIZE)?;
+        Ok(buf.len())
+    }
+}
+
+impl AtaDrive {
+    /// Read into `buf` at the current position with one PIO command
+    /// per `chunk_size` bytes, which must be at most `PIO_CHUNK_SIZE`.
+    fn read_pio_chunked(&mut self, buf: &mut [u8], chunk_size: usize) -> Result<(), ()> {
+        for chunk in buf.chunks_mut(chunk_size) {
+            self.read_pio(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Write `buf` at the current position with one PIO command
+    /// per `chunk_size` bytes, which must be at most `PIO_CHUNK_SIZE`.
+    fn write_pio_chunked(&mut self, buf: &[u8], chunk_size: usize) -> Result<(), ()> {
+        for chunk in buf.chunks(chunk_size) {
+            self.write_pio(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Read into `buf` at the current position with a single PIO command,
+    /// so it must be at most `PIO_CHUNK_SIZE` long.
+    fn read_pio(&mut self, buf: &mut [u8]) -> Result<(), ()> {
         let sector_count = self.min_required_sector_count(buf.len());
-        self.before_read_write(sector_count);
+        self.before_read_write(sector_count).map_err(Self::report)?;
         self.send_command(Command::Read);
 
         let mut data_port = self.io_port_16(IoPort::Data);
-        let sector_offset = (self.position % 512) as i64;
+        let sector_offset = (self.position % SECTOR_SIZE) as i64;
         for sector in 0..sector_count {
-            self.wait_ready();
-            for word in 0..256 {
+            self.wait_irq();
+            self.wait_ready().map_err(Self::report)?;
+            for word in 0..SECTOR_WORDS as i64 {
                 let read = unsafe { data_port.read() };
 
-                let index: i64 = (((sector as i64 * 256) + word) * 2) - sector_offset;
+                let index: i64 =
+                    (((sector as i64 * SECTOR_WORDS as i64) + word) * 2) - sector_offset;
                 let i = index as usize;
                 let buf_len = buf.len() as i64;
 
@@ -228,23 +921,31 @@ impl Read for AtaDrive {
         }
 
         self.position += buf.len();
-        Ok(buf.len())
+        Ok(())
     }
-}
 
-impl Write for AtaDrive {
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+    /// Write `buf` at the current position with a single PIO command,
+    /// so it must be at most `PIO_CHUNK_SIZE` long.
+    fn write_pio(&mut self, buf: &[u8]) -> Result<(), ()> {
         let sector_count = self.min_required_sector_count(buf.len());
-        let (start_sector, end_sector) = self.get_partial_write_sectors(buf.len());
-        self.before_read_write(sector_count);
+        let (start_sector, end_sector) = self
+            .get_partial_write_sectors(buf.len())
+            .map_err(Self::report)?;
+        self.before_read_write(sector_count).map_err(Self::report)?;
         self.send_command(Command::Write);
 
         let mut data_port = self.io_port_16(IoPort::Data);
-        let sector_offset = (self.position % 512) as i64;
+        let sector_offset = (self.position % SECTOR_SIZE) as i64;
         for sector in 0..sector_count {
-            self.wait_ready();
-            for word in 0..256usize {
-                let index: i64 = (((sector as i64 * 256) + word as i64) * 2) - sector_offset;
+            // The drive only interrupts once a sector was written,
+            // the first one is requested without one
+            if sector != 0 {
+                self.wait_irq();
+            }
+            self.wait_ready().map_err(Self::report)?;
+            for word in 0..SECTOR_WORDS {
+                let index: i64 =
+                    (((sector as i64 * SECTOR_WORDS as i64) + word as i64) * 2) - sector_offset;
                 let i = index as usize;
                 let buf_len = buf.len() as i64;
 
@@ -263,40 +964,67 @@ impl Write for AtaDrive {
 
         self.send_command(Command::CacheFlush);
         self.position += buf.len();
+        Ok(())
+    }
+}
+
+impl Write for AtaDrive {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if let Some(mut dma) = self.dma.take() {
+            let res = self.write_dma(&mut dma, buf);
+            self.dma = Some(dma);
+            return res;
+        }
+
+        self.write_pio_chunked(buf, PIO_CHUNK_SIZE)?;
         Ok(buf.len())
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        self.sync().map_err(Self::report)
     }
 }
 
 impl Seek for AtaDrive {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
-        match pos {
-            SeekFrom::Start(pos) => {
-                self.position = pos as usize;
-                Ok(pos)
-            }
-
-            SeekFrom::Current(by) => {
-                let res = self.position as i64 + by;
-                if res >= 0 {
-                    self.position = res as usize;
-                    Ok(self.position as u64)
-                } else {
-                    Err(())
-                }
-            }
+        let position = self.position as u64;
+        // None if the position does not fit into u64
+        let target = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            // Negating i64::MIN wraps to itself, which is correct as u64
+            SeekFrom::Current(by) if by < 0 => match position.checked_sub(by.wrapping_neg() as u64)
+            {
+                Some(pos) => Some(pos),
+                None => return Err(()),
+            },
+            SeekFrom::Current(by) => position.checked_add(by as u64),
+            _ => return Err(()),
+        };
+        let pos = match target {
+            Some(pos) => pos,
+            // Past the end of any drive, clamped or rejected if the capacity is known
+            None if self.capacity.is_some() => u64::MAX,
+            None => return Err(Self::report(AtaError::SeekOutOfBounds)),
+        };
 
-            _ => Err(()),
-        }
+        let pos = self.checked_position(pos).map_err(Self::report)?;
+        self.position = pos as usize;
+        Ok(pos)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::AtaDrive;
+    use super::{
+        crc32, hex_dump, sector_checksum, sectors_equal, AtaDrive, AtaError, SeekMode,
+        IRQ_RECEIVED, IRQ_TIMEOUT_TICKS, MAX_COMMAND_SECTORS, PIO_CHUNK_SIZE, SECTOR_SIZE,
+    };
+    use crate::{
+        drivers::interrupts::interrupts::{ticks, ticks_to_millis},
+        kprint,
+    };
+    use alloc::vec;
+    use core::sync::atomic::Ordering;
     use fatfs::{Read, Seek, SeekFrom, Write};
     use lazy_static::lazy_static;
     use rand::{rngs::SmallRng, RngCore, SeedableRng};
@@ -325,6 +1053,71 @@ mod tests {
         assert_eq!(bus.seek(SeekFrom::End(0)), Err(()));
     }
 
+    #[test_case]
+    fn seek_past_end() {
+        let mut bus = init();
+        let capacity = bus.capacity().expect("test drive reports its capacity");
+        assert_eq!(capacity, ACTUAL.len() as u64);
+
+        bus.seek(SeekFrom::Start(100));
+        assert_eq!(bus.seek(SeekFrom::Start(capacity + 1)), Err(()));
+        assert_eq!(bus.position, 100);
+        assert_eq!(bus.seek(SeekFrom::Start(capacity)), Ok(capacity));
+
+        bus.set_seek_mode(SeekMode::Clamp);
+        assert_eq!(bus.seek(SeekFrom::Current(1000)), Ok(capacity));
+        bus.set_seek_mode(SeekMode::Error);
+    }
+
+    #[test_case]
+    fn read_past_end() {
+        let mut bus = init();
+        let capacity = bus.capacity().unwrap();
+        let mut buf = [0xAA; SECTOR_SIZE];
+
+        bus.seek(SeekFrom::Start(capacity - 100));
+        assert_eq!(bus.read(&mut buf), Ok(100));
+        assert_eq!(buf[..100], ACTUAL[(ACTUAL.len() - 100)..]);
+        assert!(buf[100..].iter().all(|b| *b == 0xAA));
+        assert_eq!(bus.position as u64, capacity);
+        assert_eq!(bus.read(&mut buf), Ok(0));
+        assert_eq!(bus.position as u64, capacity);
+    }
+
+    #[test_case]
+    fn seek_extreme_offsets() {
+        let mut bus = init();
+        let capacity = bus.capacity().unwrap();
+        bus.seek(SeekFrom::Start(100));
+        assert_eq!(bus.seek(SeekFrom::Current(i64::MAX)), Err(()));
+        assert_eq!(bus.seek(SeekFrom::Current(i64::MIN)), Err(()));
+        assert_eq!(bus.position, 100);
+        bus.set_seek_mode(SeekMode::Clamp);
+        assert_eq!(bus.seek(SeekFrom::Current(i64::MAX)), Ok(capacity));
+        bus.set_seek_mode(SeekMode::Error);
+
+        // Without a known capacity, only overflowing u64 is rejected
+        bus.capacity = None;
+        assert_eq!(bus.seek(SeekFrom::Start(u64::MAX - 10)), Ok(u64::MAX - 10));
+        assert_eq!(bus.seek(SeekFrom::Current(i64::MAX)), Err(()));
+        assert_eq!(bus.seek(SeekFrom::Current(10)), Ok(u64::MAX));
+        assert_eq!(
+            bus.seek(SeekFrom::Current(i64::MIN)),
+            Ok(u64::MAX - (1 << 63))
+        );
+        bus.capacity = Some(capacity);
+    }
+
+    #[test_case]
+    fn probe_capacity() {
+        let mut bus = init();
+        let capacity = bus.capacity().unwrap();
+        bus.seek(SeekFrom::Start(SECTOR_SIZE as u64 + 7));
+        assert_eq!(bus.probe_capacity(), Some(capacity));
+        // Probing does not move the position
+        assert_eq!(bus.position, SECTOR_SIZE + 7);
+    }
+
     #[test_case]
     fn correct_sector_count() {
         let mut bus = init();
@@ -342,6 +1135,24 @@ mod tests {
         assert_eq!(bus.min_required_sector_count(2000), 5);
     }
 
+    #[test_case]
+    fn large_transfers() {
+        let mut bus = init();
+        // Previously truncated to u8, wrapping to 144
+        assert_eq!(bus.min_required_sector_count(200 * 1024), 400);
+        for &offset in &[0, 1, 200, 511] {
+            bus.seek(SeekFrom::Start(offset));
+            assert!(bus.min_required_sector_count(PIO_CHUNK_SIZE) <= MAX_COMMAND_SECTORS);
+            assert!(bus.min_required_sector_count(PIO_CHUNK_SIZE - 1) <= MAX_COMMAND_SECTORS);
+        }
+
+        // The test drive is smaller than 200 KiB, so the read is cut short at its end
+        let mut buf = vec![0; 200 * 1024];
+        bus.seek(SeekFrom::Start(0));
+        assert_eq!(bus.read(&mut buf), Ok(ACTUAL.len()));
+        assert_eq!(buf[..ACTUAL.len()], ACTUAL[..]);
+    }
+
     #[test_case]
     fn read_first_sector() {
         read_count::<512>(1)
@@ -378,6 +1189,17 @@ mod tests {
         read_count::<201>(10)
     }
 
+    /// The test drive is smaller than `PIO_CHUNK_SIZE`, so smaller chunks are used instead.
+    #[test_case]
+    fn read_across_chunks() {
+        let mut bus = init();
+        // Unaligned at both ends, and so is every chunk boundary
+        let mut buf = vec![0; 7 * SECTOR_SIZE + 100];
+        bus.seek(SeekFrom::Start(300));
+        assert_eq!(bus.read_pio_chunked(&mut buf, 3 * SECTOR_SIZE), Ok(()));
+        assert_eq!(buf[..], ACTUAL[300..(300 + buf.len())]);
+    }
+
     fn read_count<const COUNT: usize>(repetitions: usize) {
         let mut bus = init();
         let mut buf = [0; COUNT];
@@ -389,6 +1211,78 @@ mod tests {
         }
     }
 
+    #[test_case]
+    fn read_raw_sector() {
+        let mut bus = init();
+        bus.seek(SeekFrom::Start(100));
+        let sector = bus.read_raw_sector(1).unwrap();
+        assert_eq!(bus.position, 100);
+        for (i, word) in sector.iter().enumerate() {
+            assert_eq!(word.to_le_bytes(), ACTUAL[(512 + i * 2)..(514 + i * 2)]);
+        }
+        assert_eq!(bus.read_raw_sector(1 << 28), Err(AtaError::OutOfRange));
+    }
+
+    #[test_case]
+    fn dump_sector() {
+        // Must be declared before the write tests, which change the sector
+        let mut bus = init();
+        let sector = bus.dump_sector(1).unwrap();
+        assert_eq!(sector[..], ACTUAL[512..1024]);
+    }
+
+    #[test_case]
+    fn lost_interrupt() {
+        let mut bus = init();
+        assert!(bus.enable_interrupts());
+        // No command was sent, so no interrupt will arrive
+        IRQ_RECEIVED[0].store(false, Ordering::Release);
+        let start = ticks();
+        bus.wait_irq();
+        assert!(ticks() - start >= IRQ_TIMEOUT_TICKS);
+
+        let mut buf = [0; SECTOR_SIZE];
+        assert_eq!(bus.read(&mut buf), Ok(SECTOR_SIZE));
+        assert_eq!(buf[..], ACTUAL[..SECTOR_SIZE]);
+        bus.use_interrupts = false;
+    }
+
+    #[test_case]
+    fn decode_error() {
+        assert_eq!(AtaError::from_register(0x04), AtaError::Aborted);
+        assert_eq!(AtaError::from_register(0x40), AtaError::UncorrectableData);
+        assert_eq!(AtaError::from_register(0x14), AtaError::IdNotFound);
+        assert_eq!(AtaError::from_register(0), AtaError::DriveFault);
+        assert_eq!(AtaError::from_register(0x20), AtaError::MediaChanged);
+        assert_eq!(AtaError::from_register(0x0C), AtaError::MediaChanged);
+    }
+
+    #[test_case]
+    fn drive_present() {
+        assert!(init().is_present());
+    }
+
+    #[test_case]
+    fn checksums() {
+        assert_eq!(crc32(b"123456789".iter().copied()), 0xCBF4_3926);
+
+        let mut bus = init();
+        let first = bus.read_raw_sector(0).unwrap();
+        let second = bus.read_raw_sector(1).unwrap();
+        assert!(sectors_equal(&first, &bus.read_raw_sector(0).unwrap()));
+        let table = [sector_checksum(&first), sector_checksum(&first)];
+        assert!(bus.read_verified(0, &table).is_ok());
+        // Second sector differs from the first on the test drive
+        if !sectors_equal(&first, &second) {
+            assert_eq!(
+                bus.read_verified(1, &table),
+                Err(AtaError::ChecksumMismatch { lba: 1 })
+            );
+        }
+        // No entry in the table, not verified
+        assert!(bus.read_verified(2, &table).is_ok());
+    }
+
     #[test_case]
     fn write_preserve() {
         let mut bus = init();
@@ -441,6 +1335,24 @@ mod tests {
         write_verify::<201>(10, 12123)
     }
 
+    #[test_case]
+    fn write_across_chunks() {
+        let mut bus = init();
+        let mut rng = SmallRng::seed_from_u64(3141);
+        let mut write_buf = vec![0; 7 * SECTOR_SIZE + 100];
+        rng.fill_bytes(&mut write_buf);
+        let mut verify_buf = vec![0; write_buf.len()];
+
+        bus.seek(SeekFrom::Start(300));
+        assert_eq!(bus.write_pio_chunked(&write_buf, 3 * SECTOR_SIZE), Ok(()));
+        bus.seek(SeekFrom::Start(300));
+        assert_eq!(
+            bus.read_pio_chunked(&mut verify_buf, 3 * SECTOR_SIZE),
+            Ok(())
+        );
+        assert_eq!(write_buf, verify_buf);
+    }
+
     fn write_verify<const COUNT: usize>(repetitions: usize, seed: u64) {
         let mut bus = init();
         let mut rng = SmallRng::seed_from_u64(seed);
@@ -464,4 +1376,65 @@ mod tests {
         bus.seek(SeekFrom::Start(0));
         bus
     }
+
+    #[test_case]
+    fn format_hex_dump() {
+        let dump = hex_dump(b"Hello, world!\n\0\xFFabc");
+        assert_eq!(
+            dump,
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|\n\
+             00000010  61 62 63                                          |abc|\n"
+        );
+        assert_eq!(hex_dump(&[]), "");
+    }
+
+    #[test_case]
+    fn write_sync() {
+        let mut bus = init();
+        let mut rng = SmallRng::seed_from_u64(152);
+        let mut write_buf = [0; 512];
+        rng.fill_bytes(&mut write_buf);
+
+        bus.seek(SeekFrom::Start(4 * 512));
+        bus.write(&write_buf);
+        assert_eq!(bus.sync(), Ok(()));
+        assert_eq!(bus.flush(), Ok(()));
+        assert_eq!(bus.dump_sector(4).unwrap()[..], write_buf[..]);
+    }
+
+    /// Not a correctness test: measures PIO throughput by writing and
+    /// reading back 1MiB, restoring the drive to its original contents.
+    #[test_case]
+    fn throughput() {
+        const PASSES: usize = 1024 * 1024 / ACTUAL.len();
+        let mut bus = init();
+        let mut read_buf = [0; 4096];
+
+        let start = ticks();
+        for _ in 0..PASSES {
+            bus.seek(SeekFrom::Start(0));
+            for chunk in ACTUAL.chunks(read_buf.len()) {
+                bus.write(chunk);
+            }
+        }
+        let written = ticks();
+        for _ in 0..PASSES {
+            bus.seek(SeekFrom::Start(0));
+            for chunk in ACTUAL.chunks(read_buf.len()) {
+                bus.read(&mut read_buf);
+                assert_eq!(read_buf[..], chunk[..]);
+            }
+        }
+        let read = ticks();
+
+        let rate = |ticks| match ticks_to_millis(ticks) {
+            0 => u64::MAX,
+            millis => ACTUAL.len() as u64 * PASSES as u64 * 1000 / millis,
+        };
+        kprint!(
+            "write {} KiB/s, read {} KiB/s\t",
+            rate(written - start) / 1024,
+            rate(read - written) / 1024
+        );
+    }
 }
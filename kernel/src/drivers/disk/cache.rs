@@ -0,0 +1,344 @@
+use crate::{drivers::disk::ata_pio::SECTOR_SIZE, log_warn};
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{mem::ManuallyDrop, ptr};
+use fatfs::{IoBase, Read, Seek, SeekFrom, Write};
+
+const SECTOR: u64 = SECTOR_SIZE as u64;
+
+/// Sectors kept in memory before the cache is flushed on its own, 32 KiB.
+pub const MAX_DIRTY_SECTORS: usize = 64;
+
+/// A write-back cache in front of a drive, meant for filesystems
+/// issuing many small writes to the same few sectors.
+/// Written sectors are kept in memory until `flush`, which writes
+/// every run of adjacent sectors with a single write to the drive.
+/// Sectors overwritten entirely are never read from the drive.
+/// The cache is also flushed once it holds `MAX_DIRTY_SECTORS`
+/// and a write needs another one, and when it is dropped.
+pub struct CachedDrive<D: Read + Write + Seek + IoBase<Error = ()>> {
+    inner: D,
+    position: u64,
+    /// Sectors written since the last flush, by LBA.
+    dirty: BTreeMap<u64, [u8; SECTOR_SIZE]>,
+}
+
+impl<D: Read + Write + Seek + IoBase<Error = ()>> CachedDrive<D> {
+    /// Returns the amount of sectors waiting to be flushed.
+    pub fn dirty_sectors(&self) -> usize {
+        self.dirty.len()
+    }
+
+    /// Returns the drive, dropping any writes not flushed yet.
+    pub fn into_inner(self) -> D {
+        let mut cache = ManuallyDrop::new(self);
+        // Moved out exactly once and the cache is never dropped, skipping its flush
+        unsafe {
+            ptr::drop_in_place(&mut cache.dirty);
+            ptr::read(&cache.inner)
+        }
+    }
+
+    pub fn new(inner: D) -> Self {
+        CachedDrive {
+            inner,
+            position: 0,
+            dirty: BTreeMap::new(),
+        }
+    }
+
+    /// Read a sector from the drive, with the part past its end zeroed.
+    fn read_sector(&mut self, lba: u64) -> Result<[u8; SECTOR_SIZE], ()> {
+        let mut sector = [0; SECTOR_SIZE];
+        self.inner.seek(SeekFrom::Start(lba * SECTOR))?;
+        let mut read = 0;
+        while read < SECTOR_SIZE {
+            match self.inner.read(&mut sector[read..])? {
+                0 => break,
+                len => read += len,
+            }
+        }
+        Ok(sector)
+    }
+}
+
+/// Write a run of sectors to the drive, starting at the given LBA.
+fn write_run<D: Write + Seek + IoBase<Error = ()>>(
+    drive: &mut D,
+    lba: u64,
+    mut bytes: &[u8],
+) -> Result<(), ()> {
+    drive.seek(SeekFrom::Start(lba * SECTOR))?;
+    while !bytes.is_empty() {
+        match drive.write(bytes)? {
+            0 => return Err(()),
+            len => bytes = &bytes[len..],
+        }
+    }
+    Ok(())
+}
+
+impl<D: Read + Write + Seek + IoBase<Error = ()>> IoBase for CachedDrive<D> {
+    type Error = ();
+}
+
+impl<D: Read + Write + Seek + IoBase<Error = ()>> Read for CachedDrive<D> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut done = 0;
+        while done < buf.len() {
+            let lba = self.position / SECTOR;
+            let offset = (self.position % SECTOR) as usize;
+            let remaining = buf.len() - done;
+
+            let len = if let Some(sector) = self.dirty.get(&lba) {
+                let len = (SECTOR_SIZE - offset).min(remaining);
+                buf[done..(done + len)].copy_from_slice(&sector[offset..(offset + len)]);
+                len
+            } else {
+                // Read everything up to the next cached sector from the drive at once
+                let end = match self.dirty.range(lba..).next() {
+                    Some((dirty, _)) => dirty * SECTOR,
+                    None => u64::MAX,
+                };
+                let len = (end - self.position).min(remaining as u64) as usize;
+                self.inner.seek(SeekFrom::Start(self.position))?;
+                match self.inner.read(&mut buf[done..(done + len)])? {
+                    0 => break,
+                    read => read,
+                }
+            };
+            done += len;
+            self.position += len as u64;
+        }
+        Ok(done)
+    }
+}
+
+impl<D: Read + Write + Seek + IoBase<Error = ()>> Write for CachedDrive<D> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let mut done = 0;
+        while done < buf.len() {
+            let lba = self.position / SECTOR;
+            let offset = (self.position % SECTOR) as usize;
+            let len = (SECTOR_SIZE - offset).min(buf.len() - done);
+
+            if !self.dirty.contains_key(&lba) {
+                if self.dirty.len() >= MAX_DIRTY_SECTORS {
+                    self.flush()?;
+                }
+                let sector = if len == SECTOR_SIZE {
+                    [0; SECTOR_SIZE]
+                } else {
+                    self.read_sector(lba)?
+                };
+                self.dirty.insert(lba, sector);
+            }
+            let sector = self.dirty.get_mut(&lba).unwrap();
+            sector[offset..(offset + len)].copy_from_slice(&buf[done..(done + len)]);
+            done += len;
+            self.position += len as u64;
+        }
+        Ok(done)
+    }
+
+    /// Write all cached sectors to the drive, one write per run of adjacent ones.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let mut run = Vec::new();
+        let mut run_start = 0;
+        for (lba, sector) in &self.dirty {
+            let run_end = run_start + (run.len() / SECTOR_SIZE) as u64;
+            if !run.is_empty() && *lba != run_end {
+                write_run(&mut self.inner, run_start, &run)?;
+                run.clear();
+            }
+            if run.is_empty() {
+                run_start = *lba;
+            }
+            run.extend_from_slice(sector);
+        }
+        if !run.is_empty() {
+            write_run(&mut self.inner, run_start, &run)?;
+        }
+
+        self.dirty.clear();
+        self.inner.flush()
+    }
+}
+
+impl<D: Read + Write + Seek + IoBase<Error = ()>> Seek for CachedDrive<D> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        self.position = match pos {
+            SeekFrom::Start(pos) => pos,
+            // Negating i64::MIN wraps to itself, which is correct as u64
+            SeekFrom::Current(by) if by < 0 => self
+                .position
+                .checked_sub(by.wrapping_neg() as u64)
+                .ok_or(())?,
+            SeekFrom::Current(by) => self.position.checked_add(by as u64).ok_or(())?,
+            // The cache never grows the drive, so its end is the drive's
+            SeekFrom::End(_) => self.inner.seek(pos)?,
+        };
+        Ok(self.position)
+    }
+}
+
+impl<D: Read + Write + Seek + IoBase<Error = ()>> Drop for CachedDrive<D> {
+    fn drop(&mut self) {
+        if self.flush().is_err() {
+            log_warn!(
+                "Disk cache: failed to write back {} sectors",
+                self.dirty.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachedDrive, MAX_DIRTY_SECTORS, SECTOR_SIZE};
+    use crate::drivers::disk::cursor::Cursor;
+    use alloc::{vec, vec::Vec};
+    use fatfs::{IoBase, Read, Seek, SeekFrom, Write};
+
+    /// A drive in memory counting the reads and writes it receives.
+    struct CountingDrive {
+        inner: Cursor<Vec<u8>>,
+        reads: usize,
+        writes: usize,
+    }
+
+    impl IoBase for CountingDrive {
+        type Error = ();
+    }
+
+    impl Read for CountingDrive {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+            self.reads += 1;
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for CountingDrive {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+            self.writes += 1;
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for CountingDrive {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64, ()> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn drive(sectors: usize) -> CachedDrive<CountingDrive> {
+        let data = (0..sectors * SECTOR_SIZE).map(|i| i as u8).collect();
+        CachedDrive::new(CountingDrive {
+            inner: Cursor::new(data),
+            reads: 0,
+            writes: 0,
+        })
+    }
+
+    #[test_case]
+    fn coalesce_writes() {
+        let mut drive = drive(8);
+        // Many small writes to sectors 1 to 3, and one to sector 6
+        for i in 0..(3 * SECTOR_SIZE / 16) {
+            drive
+                .seek(SeekFrom::Start((SECTOR_SIZE + i * 16) as u64))
+                .unwrap();
+            assert_eq!(drive.write(&[0xAB; 16]), Ok(16));
+        }
+        drive
+            .seek(SeekFrom::Start(6 * SECTOR_SIZE as u64 + 3))
+            .unwrap();
+        drive.write(&[0xCD; 2]).unwrap();
+        assert_eq!(drive.dirty_sectors(), 4);
+        assert_eq!(drive.inner.writes, 0);
+
+        assert_eq!(drive.flush(), Ok(()));
+        assert_eq!(drive.inner.writes, 2);
+        assert_eq!(drive.dirty_sectors(), 0);
+
+        let data = drive.into_inner().inner.into_inner();
+        assert!(data[SECTOR_SIZE..(4 * SECTOR_SIZE)]
+            .iter()
+            .all(|b| *b == 0xAB));
+        assert_eq!(data[SECTOR_SIZE - 1], (SECTOR_SIZE - 1) as u8);
+        assert_eq!(data[4 * SECTOR_SIZE], 0);
+        let sector = &data[(6 * SECTOR_SIZE)..(7 * SECTOR_SIZE)];
+        assert_eq!(sector[2..6], [2, 0xCD, 0xCD, 5]);
+    }
+
+    #[test_case]
+    fn full_sectors_not_read() {
+        let mut drive = drive(4);
+        drive.seek(SeekFrom::Start(SECTOR_SIZE as u64)).unwrap();
+        drive.write(&[1; 2 * SECTOR_SIZE]).unwrap();
+        assert_eq!(drive.inner.reads, 0);
+
+        // Partial writes read the rest of the sector first, but only once
+        drive.seek(SeekFrom::Start(5)).unwrap();
+        drive.write(&[2; 4]).unwrap();
+        drive.write(&[3; 4]).unwrap();
+        assert_eq!(drive.inner.reads, 1);
+    }
+
+    #[test_case]
+    fn read_through_cache() {
+        let mut drive = drive(4);
+        drive
+            .seek(SeekFrom::Start(SECTOR_SIZE as u64 + 10))
+            .unwrap();
+        drive.write(&[0xEE; 4]).unwrap();
+
+        let mut buf = vec![0; 3 * SECTOR_SIZE];
+        drive.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(drive.read(&mut buf), Ok(3 * SECTOR_SIZE));
+        assert_eq!(
+            buf[SECTOR_SIZE + 9..SECTOR_SIZE + 15],
+            [9, 0xEE, 0xEE, 0xEE, 0xEE, 14]
+        );
+        assert_eq!(buf[2 * SECTOR_SIZE], 0);
+        assert_eq!(buf[2 * SECTOR_SIZE + 1], 1);
+
+        // Reads stop at the end of the drive
+        drive
+            .seek(SeekFrom::Start(3 * SECTOR_SIZE as u64 + 2))
+            .unwrap();
+        assert_eq!(drive.read(&mut buf), Ok(SECTOR_SIZE - 2));
+        assert_eq!(drive.seek(SeekFrom::End(0)), Ok(4 * SECTOR_SIZE as u64));
+    }
+
+    #[test_case]
+    fn flush_when_full() {
+        let mut drive = drive(MAX_DIRTY_SECTORS + 2);
+        for lba in 0..=MAX_DIRTY_SECTORS {
+            drive
+                .seek(SeekFrom::Start((lba * SECTOR_SIZE) as u64))
+                .unwrap();
+            drive.write(&[0xAB]).unwrap();
+        }
+        // The write needing one sector more flushed the full cache first
+        assert_eq!(drive.dirty_sectors(), 1);
+        assert_eq!(drive.inner.writes, 1);
+
+        let data = drive.into_inner().inner.into_inner();
+        assert_eq!(data[(MAX_DIRTY_SECTORS - 1) * SECTOR_SIZE], 0xAB);
+        assert_eq!(data[MAX_DIRTY_SECTORS * SECTOR_SIZE], 0);
+    }
+
+    #[test_case]
+    fn flush_on_drop() {
+        let mut data = vec![0; 2 * SECTOR_SIZE];
+        let mut drive = CachedDrive::new(Cursor::new(&mut data[..]));
+        drive.seek(SeekFrom::Start(SECTOR_SIZE as u64 + 1)).unwrap();
+        drive.write(&[1, 2, 3]).unwrap();
+        drop(drive);
+        assert_eq!(data[SECTOR_SIZE..(SECTOR_SIZE + 5)], [0, 1, 2, 3, 0]);
+    }
+}
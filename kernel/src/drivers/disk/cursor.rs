@@ -0,0 +1,152 @@
+use alloc::vec::Vec;
+use fatfs::{IoBase, Read, Seek, SeekFrom, Write};
+
+/// An in-memory block device over a byte buffer, similar to `std::io::Cursor`.
+/// Allows code reading from drives to also read from memory,
+/// for example programs embedded into the kernel.
+pub struct Cursor<T> {
+    inner: T,
+    position: usize,
+}
+
+impl<T> Cursor<T> {
+    /// Returns the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub fn new(inner: T) -> Self {
+        Cursor { inner, position: 0 }
+    }
+}
+
+impl<T> IoBase for Cursor<T> {
+    type Error = ();
+}
+
+impl<T: AsRef<[u8]>> Read for Cursor<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let data = self.inner.as_ref();
+        let start = self.position.min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..(start + len)]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl Write for Cursor<&mut [u8]> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let start = self.position.min(self.inner.len());
+        let len = buf.len().min(self.inner.len() - start);
+        self.inner[start..(start + len)].copy_from_slice(&buf[..len]);
+        self.position += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl Write for Cursor<Vec<u8>> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let end = self.position.checked_add(buf.len()).ok_or(())?;
+        if end > self.inner.len() {
+            self.inner.resize(end, 0);
+        }
+        self.inner[self.position..end].copy_from_slice(buf);
+        self.position = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<T: AsRef<[u8]>> Seek for Cursor<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let len = self.inner.as_ref().len() as u64;
+        let pos = seek_target(self.position as u64, len, pos).ok_or(())?;
+        self.position = pos as usize;
+        Ok(pos)
+    }
+}
+
+/// Returns the position a seek moves to, given the current position
+/// and the length of the device. None if it would be negative
+/// or does not fit into u64.
+pub fn seek_target(position: u64, len: u64, pos: SeekFrom) -> Option<u64> {
+    let (base, by) = match pos {
+        SeekFrom::Start(pos) => return Some(pos),
+        SeekFrom::Current(by) => (position, by),
+        SeekFrom::End(by) => (len, by),
+    };
+    if by < 0 {
+        // Negating i64::MIN wraps to itself, which is correct as u64
+        base.checked_sub(by.wrapping_neg() as u64)
+    } else {
+        base.checked_add(by as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cursor;
+    use alloc::vec::Vec;
+    use fatfs::{Read, Seek, SeekFrom, Write};
+
+    #[test_case]
+    fn read_slice() {
+        let mut cursor = Cursor::new(&[1u8, 2, 3, 4, 5][..]);
+        let mut buf = [0; 3];
+        assert_eq!(cursor.read(&mut buf), Ok(3));
+        assert_eq!(buf, [1, 2, 3]);
+        assert_eq!(cursor.read(&mut buf), Ok(2));
+        assert_eq!(buf[..2], [4, 5]);
+        assert_eq!(cursor.read(&mut buf), Ok(0));
+    }
+
+    #[test_case]
+    fn seek() {
+        let mut cursor = Cursor::new([0u8; 10]);
+        assert_eq!(cursor.seek(SeekFrom::Start(4)), Ok(4));
+        assert_eq!(cursor.seek(SeekFrom::Current(-2)), Ok(2));
+        assert_eq!(cursor.seek(SeekFrom::End(-1)), Ok(9));
+        assert_eq!(cursor.seek(SeekFrom::Current(-10)), Err(()));
+
+        // Positions past i64::MAX are valid, but not past u64::MAX
+        let far = i64::MAX as u64 + 1;
+        assert_eq!(cursor.seek(SeekFrom::Start(far)), Ok(far));
+        assert_eq!(cursor.read(&mut [0; 4]), Ok(0));
+        assert_eq!(cursor.seek(SeekFrom::Current(i64::MAX)), Ok(u64::MAX));
+        assert_eq!(cursor.seek(SeekFrom::Current(1)), Err(()));
+        assert_eq!(
+            cursor.seek(SeekFrom::Current(i64::MIN)),
+            Ok(i64::MAX as u64)
+        );
+    }
+
+    #[test_case]
+    fn write_slice() {
+        let mut data = [0u8; 4];
+        let mut cursor = Cursor::new(&mut data[..]);
+        cursor.seek(SeekFrom::Start(2));
+        assert_eq!(cursor.write(&[7, 8, 9]), Ok(2));
+        assert_eq!(data, [0, 0, 7, 8]);
+    }
+
+    #[test_case]
+    fn write_vec_grows() {
+        let mut cursor = Cursor::new(Vec::new());
+        cursor.write(&[1, 2]);
+        cursor.seek(SeekFrom::Start(4));
+        cursor.write(&[3]);
+        assert_eq!(cursor.into_inner(), [1, 2, 0, 0, 3]);
+
+        let mut cursor = Cursor::new(Vec::new());
+        cursor.seek(SeekFrom::Start(u64::MAX));
+        assert_eq!(cursor.write(&[1]), Err(()));
+    }
+}
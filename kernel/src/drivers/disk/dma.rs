@@ -0,0 +1,210 @@
+use crate::drivers::pci;
+use core::slice;
+use spin::Mutex;
+use x86_64::{
+    instructions::port::Port,
+    structures::paging::{FrameAllocator, PhysFrame, Size4KiB},
+    VirtAddr,
+};
+
+/// Size of the buffer used for DMA transfers.
+pub const DMA_BUFFER_SIZE: usize = 4096;
+
+/// The bus master found by `init`, while no drive is using it.
+static BUS_MASTER: Mutex<Option<BusMaster>> = Mutex::new(None);
+
+/// Look for a bus master on the PCI bus, which drives on the primary
+/// channel then use for their transfers. Returns false if there is none,
+/// in which case drives keep using PIO.
+///
+/// # Safety
+/// The complete physical memory must be mapped at `phys_mem_offset`.
+pub unsafe fn init(
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    phys_mem_offset: VirtAddr,
+) -> bool {
+    let dma = BusMaster::find(frame_allocator, phys_mem_offset);
+    let found = dma.is_some();
+    *BUS_MASTER.lock() = dma;
+    found
+}
+
+/// Take the bus master found by `init`, if no other drive is using it.
+pub fn take() -> Option<BusMaster> {
+    BUS_MASTER.lock().take()
+}
+
+/// Give back a bus master obtained from `take`, once the drive using it is done.
+pub fn release(dma: BusMaster) {
+    *BUS_MASTER.lock() = Some(dma);
+}
+
+/// Bus master registers, relative to the channel's base port.
+#[repr(u16)]
+enum BmPort {
+    Command = 0,
+    Status = 2,
+    PrdTable = 4,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone)]
+enum CommandBits {
+    Start = 0x01,
+    DeviceToMemory = 0x08,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone)]
+enum StatusBits {
+    Active = 0x01,
+    Error = 0x02,
+    Interrupt = 0x04,
+}
+
+/// A physical region descriptor, telling the controller
+/// where to transfer data from/to.
+#[repr(C, packed)]
+struct Prd {
+    address: u32,
+    byte_count: u16,
+    flags: u16,
+}
+
+/// Marks the last descriptor in the table.
+const PRD_END_OF_TABLE: u16 = 0x8000;
+
+/// A bus master IDE channel, used for DMA transfers.
+/// Transfers are done into/from a buffer of `DMA_BUFFER_SIZE`,
+/// which is located in a single physical frame.
+pub struct BusMaster {
+    base: u16,
+    prd: *mut Prd,
+    prd_phys: u32,
+    buffer: *mut u8,
+    buffer_phys: u32,
+}
+
+impl BusMaster {
+    /// Find the bus master of the first IDE controller on the PCI bus,
+    /// returning its primary channel.
+    ///
+    /// # Safety
+    /// The complete physical memory must be mapped at `phys_mem_offset`.
+    pub unsafe fn find(
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+        phys_mem_offset: VirtAddr,
+    ) -> Option<BusMaster> {
+        let ide = pci::find_function(0x01, 0x01)?;
+        // Bit 0 = I/O space BAR, which bus master registers always are
+        let bar = ide.bar(4);
+        if bar & 1 == 0 {
+            return None;
+        }
+        ide.enable_bus_master();
+        BusMaster::new((bar & 0xFFFC) as u16, frame_allocator, phys_mem_offset)
+    }
+
+    /// Create a new bus master at the given base port.
+    /// Returns None if no frames usable for DMA could be allocated.
+    ///
+    /// # Safety
+    /// `base` must be the base port of a bus master IDE channel.
+    /// The complete physical memory must be mapped at `phys_mem_offset`.
+    pub unsafe fn new(
+        base: u16,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+        phys_mem_offset: VirtAddr,
+    ) -> Option<BusMaster> {
+        let prd = frame_allocator.allocate_frame()?;
+        let buffer = frame_allocator.allocate_frame()?;
+        let to_virt = |frame: PhysFrame| phys_mem_offset + frame.start_address().as_u64();
+
+        Some(BusMaster {
+            base,
+            prd: to_virt(prd).as_mut_ptr(),
+            prd_phys: Self::dma_address(prd)?,
+            buffer: to_virt(buffer).as_mut_ptr(),
+            buffer_phys: Self::dma_address(buffer)?,
+        })
+    }
+
+    /// The buffer transfers are done from/to.
+    pub fn buffer(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.buffer, DMA_BUFFER_SIZE) }
+    }
+
+    /// Prepare a transfer of `bytes` bytes, which must fit into the buffer.
+    /// After calling this, the ATA command should be sent to the drive,
+    /// followed by `start`.
+    pub fn prepare(&mut self, bytes: usize, device_to_memory: bool) {
+        assert!(bytes <= DMA_BUFFER_SIZE);
+        unsafe {
+            self.prd.write_volatile(Prd {
+                address: self.buffer_phys,
+                byte_count: bytes as u16,
+                flags: PRD_END_OF_TABLE,
+            });
+            Port::<u32>::new(self.base + BmPort::PrdTable as u16).write(self.prd_phys);
+        }
+
+        let direction = if device_to_memory {
+            CommandBits::DeviceToMemory as u8
+        } else {
+            0
+        };
+        self.write(BmPort::Command, direction);
+        // Error and interrupt bits are cleared by writing 1 to them
+        self.write(
+            BmPort::Status,
+            StatusBits::Error as u8 | StatusBits::Interrupt as u8,
+        );
+    }
+
+    /// Start the prepared transfer.
+    pub fn start(&mut self) {
+        let command = self.read(BmPort::Command);
+        self.write(BmPort::Command, command | CommandBits::Start as u8);
+    }
+
+    /// Wait for the transfer to complete and stop the bus master.
+    /// Returns the status register as error if the transfer failed.
+    pub fn wait(&mut self) -> Result<(), u8> {
+        let status = loop {
+            let status = self.read(BmPort::Status);
+            if status & StatusBits::Active as u8 == 0 || status & StatusBits::Error as u8 != 0 {
+                break status;
+            }
+        };
+
+        let command = self.read(BmPort::Command);
+        self.write(BmPort::Command, command & !(CommandBits::Start as u8));
+
+        if status & StatusBits::Error as u8 != 0 {
+            Err(status)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read(&self, port: BmPort) -> u8 {
+        unsafe { Port::<u8>::new(self.base + port as u16).read() }
+    }
+
+    fn write(&self, port: BmPort, value: u8) {
+        unsafe { Port::<u8>::new(self.base + port as u16).write(value) }
+    }
+
+    /// The controller is only able to address the first 4GiB of physical memory.
+    fn dma_address(frame: PhysFrame) -> Option<u32> {
+        let address = frame.start_address().as_u64();
+        if address + DMA_BUFFER_SIZE as u64 <= u32::MAX as u64 {
+            Some(address as u32)
+        } else {
+            None
+        }
+    }
+}
+
+// The raw pointers point into frames owned exclusively by the bus master.
+unsafe impl Send for BusMaster {}
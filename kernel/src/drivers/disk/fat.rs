@@ -1,10 +1,29 @@
-use crate::drivers::disk::ata_pio::AtaDrive;
+use crate::drivers::disk::{
+    ata_pio::AtaDrive,
+    cache::CachedDrive,
+    mbr::{self, Partition},
+};
 use fatfs::{DefaultTimeProvider, Dir, DirEntry, File, FileSystem, LossyOemCpConverter};
 
-pub type FatFs = FileSystem<AtaDrive, DefaultTimeProvider, LossyOemCpConverter>;
-pub type FatDir<'d> = Dir<'d, AtaDrive, DefaultTimeProvider, LossyOemCpConverter>;
-pub type FatFile<'d> = File<'d, AtaDrive, DefaultTimeProvider, LossyOemCpConverter>;
-pub type FatEntry<'d> = DirEntry<'d, AtaDrive, DefaultTimeProvider, LossyOemCpConverter>;
+/// A drive as mounted, with a write cache in front of it.
+pub type Drive = CachedDrive<AtaDrive>;
+
+pub type FatFs = FileSystem<Drive, DefaultTimeProvider, LossyOemCpConverter>;
+pub type FatDir<'d> = Dir<'d, Drive, DefaultTimeProvider, LossyOemCpConverter>;
+pub type FatFile<'d> = File<'d, Drive, DefaultTimeProvider, LossyOemCpConverter>;
+pub type FatEntry<'d> = DirEntry<'d, Drive, DefaultTimeProvider, LossyOemCpConverter>;
+pub type PartitionFs = FileSystem<Partition<Drive>, DefaultTimeProvider, LossyOemCpConverter>;
+
+/// Errors when mounting a partition.
+#[derive(Debug)]
+pub enum MountError {
+    /// The drive has no valid master boot record.
+    NoPartitionTable,
+    /// None of the partitions are FAT-formatted.
+    NoFatPartition,
+    /// The partition could not be opened by fatfs.
+    Fat(fatfs::Error<()>),
+}
 
 /// Treat a given block device as a FAT filesystem.
 ///
@@ -12,11 +31,23 @@ pub type FatEntry<'d> = DirEntry<'d, AtaDrive, DefaultTimeProvider, LossyOemCpCo
 /// This function will panic if the given block device is not FAT-formatted.
 /// It should only be called once.
 fn fat_from_ata(ata: AtaDrive) -> FatFs {
-    FatFs::new(ata, fatfs::FsOptions::new()).expect("Failed to create FAT fs")
+    FatFs::new(CachedDrive::new(ata), fatfs::FsOptions::new()).expect("Failed to create FAT fs")
+}
+
+/// Find the first FAT partition in the drive's partition table and mount it.
+pub fn mount_first_fat(mut drive: AtaDrive) -> Result<PartitionFs, MountError> {
+    let entry = mbr::read_partitions(&mut drive)
+        .ok_or(MountError::NoPartitionTable)?
+        .into_iter()
+        .find(|entry| entry.is_fat())
+        .ok_or(MountError::NoFatPartition)?;
+    let partition = Partition::new(CachedDrive::new(drive), &entry);
+    PartitionFs::new(partition, fatfs::FsOptions::new()).map_err(MountError::Fat)
 }
 
 /// Treat the secondary block device attached to the primary controller as a FAT filesystem.
 pub fn fat_from_secondary() -> FatFs {
-    let secondary = unsafe { AtaDrive::new(0x1F0, 0x3F6) };
+    let mut secondary = unsafe { AtaDrive::new(0x1F0, 0x3F6) };
+    secondary.enable_interrupts();
     fat_from_ata(secondary)
 }
@@ -0,0 +1,197 @@
+use crate::drivers::disk::cursor::seek_target;
+use alloc::vec::Vec;
+use fatfs::{IoBase, Read, Seek, SeekFrom, Write};
+
+/// Offset of the partition table in the first sector.
+const TABLE_OFFSET: usize = 446;
+/// Offset of the boot signature, which must be 0x55 0xAA.
+const SIGNATURE_OFFSET: usize = 510;
+
+/// An entry in the partition table of a master boot record.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PartitionEntry {
+    pub bootable: bool,
+    pub kind: u8,
+    /// First sector of the partition.
+    pub start_lba: u32,
+    pub sector_count: u32,
+}
+
+impl PartitionEntry {
+    /// Is this partition of one of the FAT12/16/32 types?
+    pub fn is_fat(&self) -> bool {
+        matches!(self.kind, 0x01 | 0x04 | 0x06 | 0x0B | 0x0C | 0x0E)
+    }
+
+    fn parse(entry: &[u8]) -> PartitionEntry {
+        let u32_at =
+            |i: usize| u32::from_le_bytes([entry[i], entry[i + 1], entry[i + 2], entry[i + 3]]);
+        PartitionEntry {
+            bootable: entry[0] == 0x80,
+            kind: entry[4],
+            start_lba: u32_at(8),
+            sector_count: u32_at(12),
+        }
+    }
+}
+
+/// Read the partition table from the first sector of the given device.
+/// Returns None if the device does not have a valid MBR; entries not in
+/// use (type 0) are skipped.
+pub fn read_partitions<D: Read + Seek + IoBase<Error = ()>>(
+    device: &mut D,
+) -> Option<Vec<PartitionEntry>> {
+    let mut sector = [0; 512];
+    device.seek(SeekFrom::Start(0)).ok()?;
+    if device.read(&mut sector).ok()? != sector.len() {
+        return None;
+    }
+    if sector[SIGNATURE_OFFSET..] != [0x55, 0xAA] {
+        return None;
+    }
+
+    let entries = sector[TABLE_OFFSET..SIGNATURE_OFFSET]
+        .chunks_exact(16)
+        .map(PartitionEntry::parse)
+        .filter(|entry| entry.kind != 0)
+        .collect();
+    Some(entries)
+}
+
+/// A block device restricted to a single partition of another one.
+/// Positions are relative to the start of the partition; reads and writes
+/// past its end are cut short.
+pub struct Partition<D> {
+    inner: D,
+    start: u64,
+    len: u64,
+    position: u64,
+}
+
+impl<D: Seek + IoBase<Error = ()>> Partition<D> {
+    /// Wrap the partition described by `entry`.
+    pub fn new(inner: D, entry: &PartitionEntry) -> Self {
+        Partition {
+            inner,
+            start: entry.start_lba as u64 * 512,
+            len: entry.sector_count as u64 * 512,
+            position: 0,
+        }
+    }
+
+    /// Returns the underlying device.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Seek the underlying device to the current position, returning
+    /// how many of `len` bytes fit into the partition from there.
+    fn prepare(&mut self, len: usize) -> Result<usize, ()> {
+        let remaining = self.len.saturating_sub(self.position);
+        if remaining == 0 {
+            // Past the end, where the absolute position might overflow
+            return Ok(0);
+        }
+        self.inner
+            .seek(SeekFrom::Start(self.start + self.position))?;
+        Ok((len as u64).min(remaining) as usize)
+    }
+}
+
+impl<D> IoBase for Partition<D> {
+    type Error = ();
+}
+
+impl<D: Read + Seek + IoBase<Error = ()>> Read for Partition<D> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = self.prepare(buf.len())?;
+        let read = self.inner.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<D: Write + Seek + IoBase<Error = ()>> Write for Partition<D> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let len = self.prepare(buf.len())?;
+        let written = self.inner.write(&buf[..len])?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+impl<D> Seek for Partition<D> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        self.position = seek_target(self.position, self.len, pos).ok_or(())?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_partitions, Partition, PartitionEntry};
+    use crate::drivers::disk::cursor::Cursor;
+    use alloc::{vec, vec::Vec};
+    use fatfs::{Read, Seek, SeekFrom, Write};
+
+    /// A 4-sector disk with a FAT32 partition covering sectors 2 and 3.
+    fn disk() -> Cursor<Vec<u8>> {
+        let mut disk = vec![0; 512 * 4];
+        disk[446..462].copy_from_slice(&[0x80, 0, 0, 0, 0x0C, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0]);
+        disk[510] = 0x55;
+        disk[511] = 0xAA;
+        for (i, byte) in disk[1024..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        Cursor::new(disk)
+    }
+
+    #[test_case]
+    fn parse_table() {
+        let mut disk = disk();
+        let entries = read_partitions(&mut disk).unwrap();
+        assert_eq!(
+            entries,
+            [PartitionEntry {
+                bootable: true,
+                kind: 0x0C,
+                start_lba: 2,
+                sector_count: 2,
+            }]
+        );
+        assert!(entries[0].is_fat());
+
+        let mut empty = Cursor::new(vec![0u8; 512]);
+        assert!(read_partitions(&mut empty).is_none());
+    }
+
+    #[test_case]
+    fn partition_bounds() {
+        let mut disk = disk();
+        let entry = read_partitions(&mut disk).unwrap()[0];
+        let mut part = Partition::new(disk, &entry);
+
+        let mut buf = [0; 4];
+        part.seek(SeekFrom::Start(3)).unwrap();
+        assert_eq!(part.read(&mut buf), Ok(4));
+        assert_eq!(buf, [3, 4, 5, 6]);
+
+        assert_eq!(part.seek(SeekFrom::End(-2)), Ok(1022));
+        assert_eq!(part.read(&mut buf), Ok(2));
+        assert_eq!(part.write(&[1, 2]), Ok(0));
+
+        // Seeking far past the end is allowed, overflowing u64 is not
+        assert_eq!(part.seek(SeekFrom::Start(u64::MAX)), Ok(u64::MAX));
+        assert_eq!(part.read(&mut buf), Ok(0));
+        assert_eq!(part.seek(SeekFrom::Current(1)), Err(()));
+        assert_eq!(part.seek(SeekFrom::End(i64::MIN)), Err(()));
+
+        part.seek(SeekFrom::Start(0)).unwrap();
+        part.write(&[42]).unwrap();
+        assert_eq!(part.into_inner().into_inner()[1024], 42);
+    }
+}
@@ -1,7 +1,4 @@
-use crate::{
-    drivers::disk::fat::{FatDir, FatFile},
-    kprintln,
-};
+use crate::{drivers::disk::fat::FatDir, kprintln};
 use alloc::{string::String, vec::Vec};
 use fatfs::{Read, Seek, SeekFrom};
 use spin::{RwLock, RwLockReadGuard};
@@ -11,7 +8,11 @@ use yacari::{
 };
 
 pub mod ata_pio;
+pub mod cache;
+pub mod cursor;
+pub mod dma;
 pub mod fat;
+pub mod mbr;
 
 static FS_LOCK: RwLock<()> = RwLock::new(());
 
@@ -59,7 +60,10 @@ fn walk_dir<T: FnMut(File)>(entry: FatDir, path_buf: &mut Vec<SmolStr>, cls: &mu
     }
 }
 
-fn read_file(mut file: FatFile) -> Option<String> {
+/// Read the entire contents of a file or any other seekable source,
+/// for example an in-memory `cursor::Cursor`.
+/// Returns None if the contents are not valid UTF-8.
+pub fn read_file<R: Read + Seek>(mut file: R) -> Option<String> {
     let size = file.seek(SeekFrom::End(0)).unwrap();
     let mut buf = Vec::with_capacity(size as usize);
     unsafe {
@@ -1,7 +1,8 @@
 use crate::{
-    drivers::{interrupts::gdt, keyboard},
+    drivers::{disk::ata_pio, interrupts::gdt, keyboard},
     hlt_loop, kprintln,
 };
+use core::sync::atomic::{AtomicU64, Ordering};
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
 use spin::Mutex;
@@ -16,6 +17,50 @@ pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 pub static PICS: spin::Mutex<ChainedPics> =
     Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
+/// Input clock of the programmable interval timer in Hz.
+const PIT_FREQUENCY: u64 = 1_193_182;
+/// The timer is left at its default divisor, firing about 18.2 times a second.
+const PIT_DIVISOR: u64 = 65536;
+
+/// Timer interrupts received since they were enabled.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of timer interrupts since boot, for coarse time measurements.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Convert a number of timer ticks into milliseconds.
+pub fn ticks_to_millis(ticks: u64) -> u64 {
+    ticks * PIT_DIVISOR * 1000 / PIT_FREQUENCY
+}
+
+/// Data ports of the PICs, holding their interrupt masks.
+const PIC_1_DATA: u16 = 0x21;
+const PIC_2_DATA: u16 = 0xA1;
+/// The line the secondary PIC is chained to on the primary one.
+const PIC_CASCADE_IRQ: u8 = 2;
+
+/// Unmask the given IRQ line (0-15) instead of relying on the masks
+/// left by the firmware. Lines of the secondary PIC also unmask its cascade.
+pub fn unmask_irq(irq: u8) {
+    let unmask = |port: u16, line: u8| unsafe {
+        let mut port = Port::<u8>::new(port);
+        let mask = port.read();
+        port.write(mask & !(1 << line));
+    };
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _pics = PICS.lock();
+        if irq < 8 {
+            unmask(PIC_1_DATA, irq);
+        } else {
+            unmask(PIC_2_DATA, irq - 8);
+            unmask(PIC_1_DATA, PIC_CASCADE_IRQ);
+        }
+    });
+}
+
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
@@ -30,6 +75,8 @@ lazy_static! {
 
         idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        idt[InterruptIndex::PrimaryAta.as_usize()].set_handler_fn(ata_interrupt_handler::<0>);
+        idt[InterruptIndex::SecondaryAta.as_usize()].set_handler_fn(ata_interrupt_handler::<1>);
 
         idt.breakpoint.set_handler_fn(generic_fault::<"BREAKPOINT">);
         idt.divide_error
@@ -70,6 +117,8 @@ lazy_static! {
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    PrimaryAta = PIC_1_OFFSET + 14,
+    SecondaryAta,
 }
 
 impl InterruptIndex {
@@ -119,6 +168,7 @@ extern "x86-interrupt" fn double_fault_handler(
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
     end_interrupt(InterruptIndex::Timer)
 }
 
@@ -129,6 +179,17 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStac
     end_interrupt(InterruptIndex::Keyboard)
 }
 
+extern "x86-interrupt" fn ata_interrupt_handler<const CHANNEL: usize>(
+    _stack_frame: InterruptStackFrame,
+) {
+    ata_pio::irq_received(CHANNEL);
+    end_interrupt(if CHANNEL == 0 {
+        InterruptIndex::PrimaryAta
+    } else {
+        InterruptIndex::SecondaryAta
+    })
+}
+
 fn end_interrupt(id: InterruptIndex) {
     unsafe {
         PICS.lock().notify_end_of_interrupt(id.as_u8());
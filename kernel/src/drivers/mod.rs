@@ -1,5 +1,6 @@
 pub mod disk;
 pub mod interrupts;
 pub mod keyboard;
+pub mod pci;
 pub mod serial;
 pub mod vga_buffer;
@@ -0,0 +1,75 @@
+use x86_64::instructions::port::Port;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+/// Location of a function on the PCI bus.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PciFunction {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciFunction {
+    /// Read a 32-bit register from the configuration space.
+    pub fn read(&self, offset: u8) -> u32 {
+        unsafe {
+            Port::<u32>::new(CONFIG_ADDRESS).write(self.address(offset));
+            Port::<u32>::new(CONFIG_DATA).read()
+        }
+    }
+
+    /// Write a 32-bit register in the configuration space.
+    pub fn write(&self, offset: u8, value: u32) {
+        unsafe {
+            Port::<u32>::new(CONFIG_ADDRESS).write(self.address(offset));
+            Port::<u32>::new(CONFIG_DATA).write(value)
+        }
+    }
+
+    /// Returns the given base address register.
+    pub fn bar(&self, index: u8) -> u32 {
+        self.read(0x10 + index * 4)
+    }
+
+    /// Allow the function to act as a bus master, required for DMA.
+    pub fn enable_bus_master(&self) {
+        let command = self.read(0x04);
+        self.write(0x04, command | 0x04);
+    }
+
+    fn address(&self, offset: u8) -> u32 {
+        0x8000_0000
+            | (self.bus as u32) << 16
+            | (self.device as u32) << 11
+            | (self.function as u32) << 8
+            | (offset as u32 & 0xFC)
+    }
+}
+
+/// Find the first function on the bus with the given class and subclass.
+/// This brute-forces all possible locations, which is slow but simple.
+pub fn find_function(class: u8, subclass: u8) -> Option<PciFunction> {
+    for bus in 0..=255 {
+        for device in 0..32 {
+            for function in 0..8 {
+                let func = PciFunction {
+                    bus,
+                    device,
+                    function,
+                };
+                // Vendor 0xFFFF = nothing attached
+                if func.read(0x00) & 0xFFFF == 0xFFFF {
+                    continue;
+                }
+
+                let class_reg = func.read(0x08);
+                if (class_reg >> 24) as u8 == class && (class_reg >> 16) as u8 == subclass {
+                    return Some(func);
+                }
+            }
+        }
+    }
+    None
+}
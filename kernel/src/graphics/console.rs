@@ -0,0 +1,240 @@
+use crate::graphics::{
+    obtain_buffer, set_pixel,
+    text::{glyph, GLYPH_SIZE},
+    Color,
+};
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+/// Default distance between tab stops, in characters.
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// Where lines are broken when text reaches the right edge of the screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Break after the last character that fits.
+    Char,
+    /// Move the word that does not fit to the next line, breaking at spaces.
+    /// Words longer than a line are broken like with `Char`.
+    Word,
+}
+
+/// A text console drawing onto the entire screen using an 8x8 font.
+/// When the bottom is reached, the screen scrolls up by one line.
+/// Characters not in the font are drawn as '?'.
+/// Tabs advance to the next tab stop; if it is past the right edge,
+/// the tab wraps to the start of the next line instead.
+/// Lines are wrapped according to the `WrapMode`, `Char` by default.
+pub struct Console {
+    column: usize,
+    row: usize,
+    columns: usize,
+    rows: usize,
+    foreground: Color,
+    background: Color,
+    tab_width: usize,
+    wrap: WrapMode,
+    /// The text of the current line, wrapped again by `wrap_words` with every
+    /// character to find the words that do not fit. Only used with `WrapMode::Word`.
+    line: String,
+}
+
+impl Console {
+    /// Create a new console, starting in the top left corner.
+    /// Graphics must be initialized.
+    pub fn new(foreground: Color, background: Color) -> Console {
+        let (width, height) = super::dimensions();
+        Console {
+            column: 0,
+            row: 0,
+            columns: width / GLYPH_SIZE,
+            rows: height / GLYPH_SIZE,
+            foreground,
+            background,
+            tab_width: DEFAULT_TAB_WIDTH,
+            wrap: WrapMode::Char,
+            line: String::new(),
+        }
+    }
+
+    /// Set the distance between tab stops, in characters.
+    /// A width of 0 is treated as 1.
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width.max(1);
+        self
+    }
+
+    /// Set how lines are broken at the right edge of the screen.
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Set the color used for further text.
+    pub fn set_foreground(&mut self, foreground: Color) {
+        self.foreground = foreground;
+    }
+
+    /// Write a single character, wrapping to the next line at the edge of the screen.
+    pub fn write_char(&mut self, c: char) {
+        match c {
+            '\n' => self.new_line(),
+            '\r' => {
+                self.column = 0;
+                self.line.clear();
+            }
+            '\t' => {
+                let stop = (self.column / self.tab_width + 1) * self.tab_width;
+                if stop >= self.columns {
+                    self.new_line();
+                } else {
+                    // Overwrite skipped cells, they might contain old text
+                    while self.column < stop {
+                        self.draw_char(' ');
+                        self.column += 1;
+                        if self.wrap == WrapMode::Word {
+                            self.line.push(' ');
+                        }
+                    }
+                }
+            }
+            c if self.wrap == WrapMode::Word => self.write_word_char(c),
+            c => {
+                if self.column >= self.columns {
+                    self.new_line();
+                }
+                self.draw_char(c);
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Write a character with `WrapMode::Word`, moving the end of the line
+    /// to the next one if the character does not fit.
+    fn write_word_char(&mut self, c: char) {
+        self.line.push(c);
+        let wrapped = match wrap_words(&self.line, self.columns)[..] {
+            [kept, moved] => Some((kept.chars().count(), String::from(moved))),
+            _ => None,
+        };
+        let (kept, moved) = match wrapped {
+            Some(wrapped) => wrapped,
+            None => {
+                self.draw_char(c);
+                self.column += 1;
+                return;
+            }
+        };
+
+        // Clear the moved part from the end of this line
+        let end = self.column;
+        self.column = kept;
+        while self.column < end {
+            self.draw_char(' ');
+            self.column += 1;
+        }
+        self.new_line();
+        for c in moved.chars() {
+            self.draw_char(c);
+            self.column += 1;
+        }
+        self.line = moved;
+    }
+
+    fn draw_char(&self, c: char) {
+        let (x, y) = (self.column * GLYPH_SIZE, self.row * GLYPH_SIZE);
+        obtain_buffer().draw_glyph(x, y, glyph(c), self.foreground, Some(self.background));
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        self.line.clear();
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    /// Move all lines up by one, clearing the last line.
+    fn scroll(&mut self) {
+        let mut buf = obtain_buffer();
+        let line = buf.stride * GLYPH_SIZE;
+        let end = line * self.rows;
+        buf.buffer.copy_within(line..end, 0);
+
+        let row_len = buf.width * buf.bytes_per_pixel;
+        for y in ((self.rows - 1) * GLYPH_SIZE)..(self.rows * GLYPH_SIZE) {
+            let start = y * buf.stride;
+            for offset in (start..(start + row_len)).step_by(buf.bytes_per_pixel) {
+                set_pixel(buf.buffer, offset, self.background);
+            }
+        }
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+        Ok(())
+    }
+}
+
+/// Split `text` into the lines shown by a console `columns` characters wide
+/// with `WrapMode::Word`. Lines end at newlines, otherwise at the last space
+/// that fits, which is dropped. Words longer than a line are broken
+/// after the last character that fits, like with `WrapMode::Char`.
+fn wrap_words(text: &str, columns: usize) -> Vec<&str> {
+    let columns = columns.max(1);
+    let mut lines = Vec::new();
+    for mut line in text.split('\n') {
+        // The first character past the edge, a space there still ends the line
+        while let Some((edge, c)) = line.char_indices().nth(columns) {
+            match line[..(edge + c.len_utf8())].rfind(' ') {
+                Some(space) => {
+                    lines.push(&line[..space]);
+                    line = &line[(space + 1)..];
+                }
+                None => {
+                    lines.push(&line[..edge]);
+                    line = &line[edge..];
+                }
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::wrap_words;
+
+    #[test_case]
+    fn wrap_at_spaces() {
+        assert_eq!(wrap_words("one two three", 9), ["one two", "three"]);
+        assert_eq!(wrap_words("", 9), [""]);
+    }
+
+    #[test_case]
+    fn wrap_long_word() {
+        assert_eq!(wrap_words("abcdefghij xy", 4), ["abcd", "efgh", "ij", "xy"]);
+        assert_eq!(wrap_words("ab cdefghij", 4), ["ab", "cdef", "ghij"]);
+    }
+
+    #[test_case]
+    fn wrap_at_last_column() {
+        // The word ends exactly at the edge, the space after it breaks the line
+        assert_eq!(wrap_words("abc def ghi", 7), ["abc def", "ghi"]);
+        assert_eq!(wrap_words("abc def ", 7), ["abc def", ""]);
+        assert_eq!(wrap_words("abc défg", 7), ["abc", "défg"]);
+    }
+
+    #[test_case]
+    fn wrap_newline_in_word() {
+        assert_eq!(wrap_words("ab\ncd ef", 5), ["ab", "cd ef"]);
+        assert_eq!(wrap_words("abcdef\ngh", 4), ["abcd", "ef", "gh"]);
+    }
+}
@@ -0,0 +1,346 @@
+use crate::{
+    drivers::disk::{cursor::Cursor, fat::FatDir},
+    graphics::{blit, dimensions, Color},
+};
+use alloc::{vec, vec::Vec};
+use fatfs::Read;
+
+/// Most bytes of pixel data kept in memory, so images cannot exhaust the heap.
+/// An `Image` holds all of its pixels, `load_splash` only a row of them.
+const MAX_IMAGE_BYTES: usize = 512 * 1024;
+
+/// Largest width or height accepted, so a row fits into `MAX_IMAGE_BYTES`
+/// and computing the size of scaled images cannot overflow.
+const MAX_DIMENSION: usize = MAX_IMAGE_BYTES / 3;
+
+/// Longest field of a PPM header, enough for any number of pixels.
+const MAX_TOKEN_LEN: usize = 20;
+
+/// An image in memory, with its pixels stored row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+/// Errors when loading an image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageError {
+    /// The file could not be opened or read.
+    Io,
+    /// The file is not a binary PPM with 8-bit channels, it is wider
+    /// or higher than `MAX_DIMENSION`, or its pixels take more than `MAX_IMAGE_BYTES`.
+    Format,
+    /// The file ends before all pixels its header announces.
+    Truncated,
+}
+
+impl Image {
+    /// Parse a binary PPM (`P6`) image with a maximum channel value of 255,
+    /// a format simple enough to be written by most image editors.
+    pub fn from_ppm(bytes: &[u8]) -> Result<Image, ImageError> {
+        Image::read_ppm(Cursor::new(bytes))
+    }
+
+    /// Read a PPM file, see `from_ppm`.
+    pub fn read_ppm<R: Read>(file: R) -> Result<Image, ImageError> {
+        let mut reader = PpmReader::new(file);
+        let (width, height) = reader.header()?;
+        match width.checked_mul(height) {
+            Some(len) if len <= MAX_IMAGE_BYTES / 3 => (),
+            _ => return Err(ImageError::Format),
+        }
+
+        let mut pixels = vec![Color::BLACK; width * height];
+        reader.pixels(&mut pixels)?;
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Returns a copy resized to the given dimensions,
+    /// picking the nearest pixel for each one.
+    pub fn scaled(&self, width: usize, height: usize) -> Image {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let start = (y * self.height / height) * self.width;
+            pixels.extend(scale_row(&self.pixels[start..(start + self.width)], width));
+        }
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Returns the largest dimensions not exceeding the given ones
+    /// while keeping the aspect ratio. Images are never enlarged.
+    pub fn fit_size(&self, max_width: usize, max_height: usize) -> (usize, usize) {
+        fit_size(self.width, self.height, max_width, max_height)
+    }
+}
+
+/// See `Image::fit_size`.
+fn fit_size(w: usize, h: usize, max_width: usize, max_height: usize) -> (usize, usize) {
+    if w <= max_width && h <= max_height {
+        (w, h)
+    } else if w * max_height > h * max_width {
+        // Limited by the width
+        (max_width, (h * max_width / w).max(1))
+    } else {
+        ((w * max_height / h).max(1), max_height)
+    }
+}
+
+/// Resize a row of pixels to the given width, picking the nearest pixel for each one.
+fn scale_row(row: &[Color], width: usize) -> impl Iterator<Item = Color> + '_ {
+    (0..width).map(move |x| row[x * row.len() / width])
+}
+
+/// Reads a PPM file front to back, buffering a sector of it at a time.
+struct PpmReader<R> {
+    file: R,
+    buf: [u8; 512],
+    pos: usize,
+    len: usize,
+}
+
+impl<R: Read> PpmReader<R> {
+    fn new(file: R) -> Self {
+        PpmReader {
+            file,
+            buf: [0; 512],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the next byte, None at the end of the file.
+    fn byte(&mut self) -> Result<Option<u8>, ImageError> {
+        if self.pos == self.len {
+            self.len = self.file.read(&mut self.buf).map_err(|_| ImageError::Io)?;
+            self.pos = 0;
+            if self.len == 0 {
+                return Ok(None);
+            }
+        }
+        self.pos += 1;
+        Ok(Some(self.buf[self.pos - 1]))
+    }
+
+    /// Parse the header, returning the width and height of the image.
+    /// The pixels follow it directly.
+    fn header(&mut self) -> Result<(usize, usize), ImageError> {
+        if self.token()?.as_deref() != Some(&b"P6"[..]) {
+            return Err(ImageError::Format);
+        }
+        let width = self.number()?;
+        let height = self.number()?;
+        if self.number()? != 255 || width == 0 || height == 0 {
+            return Err(ImageError::Format);
+        }
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(ImageError::Format);
+        }
+        Ok((width, height))
+    }
+
+    /// Returns the next field, skipping whitespace and `#` comments before it.
+    /// The single whitespace character ending the field is consumed with it,
+    /// which is what separates the header from the pixels.
+    fn token(&mut self) -> Result<Option<Vec<u8>>, ImageError> {
+        let mut byte = self.byte()?;
+        loop {
+            match byte {
+                Some(b'#') => {
+                    while !matches!(byte, Some(b'\n') | None) {
+                        byte = self.byte()?;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => byte = self.byte()?,
+                Some(_) => break,
+                None => return Ok(None),
+            }
+        }
+
+        let mut token = Vec::new();
+        while let Some(b) = byte.filter(|b| !b.is_ascii_whitespace()) {
+            if token.len() == MAX_TOKEN_LEN {
+                return Err(ImageError::Format);
+            }
+            token.push(b);
+            byte = self.byte()?;
+        }
+        Ok(Some(token))
+    }
+
+    fn number(&mut self) -> Result<usize, ImageError> {
+        let token = self.token()?.ok_or(ImageError::Truncated)?;
+        core::str::from_utf8(&token)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or(ImageError::Format)
+    }
+
+    /// Fill `pixels` with the next pixels of the image.
+    fn pixels(&mut self, pixels: &mut [Color]) -> Result<(), ImageError> {
+        for pixel in pixels {
+            let mut rgb = [0; 3];
+            for channel in &mut rgb {
+                *channel = self.byte()?.ok_or(ImageError::Truncated)?;
+            }
+            *pixel = Color::from(rgb[0], rgb[1], rgb[2]);
+        }
+        Ok(())
+    }
+}
+
+/// Read a PPM image a row at a time, scaled down to fit a screen of the given
+/// dimensions, and call `draw_row` with the screen position and pixels of every
+/// row to center it. Only a single row of the image is kept in memory.
+fn draw_centered<R: Read>(
+    file: R,
+    (screen_w, screen_h): (usize, usize),
+    mut draw_row: impl FnMut(usize, usize, &[Color]),
+) -> Result<(), ImageError> {
+    let mut reader = PpmReader::new(file);
+    let (width, height) = reader.header()?;
+    let (w, h) = fit_size(width, height, screen_w, screen_h);
+    let (x, y) = ((screen_w - w) / 2, (screen_h - h) / 2);
+
+    let mut row = vec![Color::BLACK; width];
+    let mut scaled = Vec::with_capacity(w);
+    let mut rows_read = 0;
+    for dy in 0..h {
+        // The rows in between are skipped when scaling down
+        let source = dy * height / h;
+        while rows_read <= source {
+            reader.pixels(&mut row)?;
+            rows_read += 1;
+        }
+        scaled.clear();
+        scaled.extend(scale_row(&row, w));
+        draw_row(x, y + dy, &scaled);
+    }
+    Ok(())
+}
+
+/// Show a PPM image as boot splash, centered on the screen.
+/// Images larger than the screen are scaled down to fit it;
+/// the screen around the image is left untouched.
+/// The image is drawn while it is read, so a truncated one is drawn up to where it ends.
+pub fn load_splash<R: Read>(file: R) -> Result<(), ImageError> {
+    draw_centered(file, dimensions(), |x, y, row| {
+        blit(x, y, row.len(), 1, row)
+    })
+}
+
+/// Show the PPM image at the given path in the directory as boot splash,
+/// see `load_splash`.
+pub fn load_splash_from(dir: &FatDir, path: &str) -> Result<(), ImageError> {
+    let file = dir.open_file(path).map_err(|_| ImageError::Io)?;
+    load_splash(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{draw_centered, Image, ImageError};
+    use crate::{drivers::disk::cursor::Cursor, graphics::Color};
+    use alloc::{vec, vec::Vec};
+
+    const RED: Color = Color::hex(0xFF0000);
+    const BLUE: Color = Color::hex(0x0000FF);
+
+    fn ppm(header: &[u8], pixels: &[Color]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        for color in pixels {
+            bytes.extend_from_slice(&[color.red(), color.green(), color.blue()]);
+        }
+        bytes
+    }
+
+    #[test_case]
+    fn parse_ppm() {
+        let bytes = ppm(b"P6\n# a comment\n2 1\n255\n", &[RED, BLUE]);
+        let image = Image::read_ppm(Cursor::new(bytes)).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, [RED, BLUE]);
+
+        let truncated = ppm(b"P6 2 2 255\n", &[RED, BLUE, RED]);
+        assert_eq!(Image::from_ppm(&truncated), Err(ImageError::Truncated));
+        assert_eq!(
+            Image::from_ppm(b"P3 1 1 255\n0 0 0"),
+            Err(ImageError::Format)
+        );
+        assert_eq!(Image::from_ppm(b"P6 1 1 65535\n"), Err(ImageError::Format));
+        assert_eq!(Image::from_ppm(b"P6 1"), Err(ImageError::Truncated));
+        // Too large to be kept in memory, checked before reading any pixels
+        assert_eq!(
+            Image::from_ppm(b"P6 1024 768 255\n"),
+            Err(ImageError::Format)
+        );
+        assert_eq!(
+            Image::from_ppm(b"P6 100000000000000000000000 1 255\n"),
+            Err(ImageError::Format)
+        );
+    }
+
+    #[test_case]
+    fn fit_and_scale() {
+        let image = Image {
+            width: 4,
+            height: 2,
+            pixels: vec![RED, RED, BLUE, BLUE, RED, RED, BLUE, BLUE],
+        };
+        assert_eq!(image.fit_size(10, 10), (4, 2));
+        assert_eq!(image.fit_size(2, 10), (2, 1));
+        assert_eq!(image.fit_size(10, 1), (2, 1));
+
+        let scaled = image.scaled(2, 1);
+        assert_eq!(scaled.pixels, [RED, BLUE]);
+    }
+
+    #[test_case]
+    fn draw_rows() {
+        let bytes = ppm(
+            b"P6 4 2 255\n",
+            &[RED, RED, BLUE, BLUE, BLUE, BLUE, BLUE, RED],
+        );
+        let draw = |screen| {
+            let mut rows = Vec::new();
+            let res = draw_centered(Cursor::new(&bytes), screen, |x, y, row| {
+                rows.push((x, y, row.to_vec()))
+            });
+            (res, rows)
+        };
+
+        let (res, rows) = draw((10, 5));
+        assert_eq!(res, Ok(()));
+        assert_eq!(
+            rows,
+            [
+                (3, 1, vec![RED, RED, BLUE, BLUE]),
+                (3, 2, vec![BLUE, BLUE, BLUE, RED])
+            ]
+        );
+
+        // Scaled down to 2x1, keeping the first row
+        let (res, rows) = draw((2, 2));
+        assert_eq!(res, Ok(()));
+        assert_eq!(rows, [(0, 0, vec![RED, BLUE])]);
+
+        // Dimensions are bounded before any size is computed from them
+        let tall = b"P6 1 18446744073709551615 255\n";
+        let res = draw_centered(Cursor::new(&tall[..]), (10, 5), |_, _, _| ());
+        assert_eq!(res, Err(ImageError::Format));
+
+        // Rows are drawn as they are read, up to where the file ends
+        let truncated = &bytes[..(bytes.len() - 3)];
+        let mut rows = 0;
+        let res = draw_centered(Cursor::new(truncated), (10, 5), |_, _, _| rows += 1);
+        assert_eq!((res, rows), (Err(ImageError::Truncated), 1));
+    }
+}
@@ -1,52 +1,642 @@
-use alloc::slice;
-use bootloader::boot_info::{FrameBuffer, FrameBufferInfo};
+use alloc::{slice, vec::Vec};
+use bootloader::boot_info::{FrameBuffer, FrameBufferInfo, PixelFormat};
 use conquer_once::spin::OnceCell;
+use core::{fmt::Write, mem, panic::PanicInfo};
 use spin::{Mutex, MutexGuard};
 
+mod console;
+mod image;
+mod text;
+
+pub use console::{Console, WrapMode};
+pub use image::{load_splash, load_splash_from, Image, ImageError};
+pub use text::{draw_string_vertical, text_width, GLYPH_SIZE};
+
 // TODO isn't this doubly syncronized?...
-static FRAMEBUFFER: OnceCell<Mutex<Framebuffer>> = OnceCell::uninit();
-
-pub fn init_graphics(mut buffer: &mut FrameBuffer) {
-    // Play with the borrow checker a bit to get a raw frame buffer
-    // with 'static lifetime
-    let FrameBufferInfo {
-        horizontal_resolution: width,
-        vertical_resolution: height,
-        stride,
-        bytes_per_pixel,
-        ..
-    } = buffer.info();
-    let buffer_ptr = buffer.buffer_mut().as_mut_ptr();
-    let buffer_len = buffer.buffer_mut().len();
-
-    FRAMEBUFFER.init_once(|| {
-        Mutex::new(Framebuffer {
-            buffer: unsafe { slice::from_raw_parts_mut(buffer_ptr, buffer_len) },
-            height,
-            width,
-            stride: stride * bytes_per_pixel,
-            bytes_per_pixel,
-        })
-    });
+static FRAMEBUFFER: OnceCell<Mutex<Framebuffer<'static>>> = OnceCell::uninit();
+
+/// The background color used on boot.
+pub const DEFAULT_BACKGROUND: Color = Color::hex(0x111111);
+
+/// The background color of the panic screen.
+pub const PANIC_BACKGROUND: Color = Color::hex(0xAA0000);
 
-    // Fill screen with very light grey
-    draw_rect(0, 0, width, height, Color::hex(0x111111))
+/// Setup the framebuffer for use by all drawing functions,
+/// filling it with the given background color.
+/// If no color is given, the screen is left untouched.
+pub fn init_graphics(buffer: &mut FrameBuffer, background: Option<Color>) {
+    // The bootloader's framebuffer is never freed
+    let framebuffer = unsafe { Framebuffer::from_raw(buffer) };
+    FRAMEBUFFER.init_once(|| Mutex::new(framebuffer));
+
+    if let Some(background) = background {
+        clear(background)
+    }
+}
+
+/// Replace the framebuffer used for drawing, for example after a mode switch.
+/// Sets up graphics if `init_graphics` was not called yet.
+/// The screen is not cleared; the new buffer is used as-is.
+pub fn reinit_graphics(buffer: &'static mut FrameBuffer) {
+    // Borrowed forever, so nothing else can access it anymore
+    let framebuffer = unsafe { Framebuffer::from_raw(buffer) };
+    match FRAMEBUFFER.get() {
+        Some(current) => *current.lock() = framebuffer,
+        None => FRAMEBUFFER.init_once(|| Mutex::new(framebuffer)),
+    }
 }
 
-pub struct Framebuffer {
+/// Replace the screen with the panic message and its location.
+/// Does nothing if graphics are not initialized.
+///
+/// # Safety
+/// The framebuffer is unlocked forcibly if it is held, since the panic
+/// might have happened while drawing. The caller must ensure nothing else
+/// draws anymore, for example by disabling interrupts before.
+pub unsafe fn draw_panic_screen(info: &PanicInfo) {
+    let framebuffer = match FRAMEBUFFER.get() {
+        Some(framebuffer) => framebuffer,
+        None => return,
+    };
+    if framebuffer.is_locked() {
+        framebuffer.force_unlock();
+    }
+
+    framebuffer.lock().set_clip(None);
+    clear(PANIC_BACKGROUND);
+    let mut console = Console::new(Color::WHITE, PANIC_BACKGROUND);
+    // Nothing left to do if this fails
+    let _ = write!(console, "KERNEL PANIC\n\n{}", info);
+}
+
+/// Fill the entire screen with the given color.
+pub fn clear(color: Color) {
+    obtain_buffer().clear(color)
+}
+
+pub struct Framebuffer<'b> {
     // the underlying buffer
-    buffer: &'static mut [u8],
+    buffer: &'b mut [u8],
     // height in pixels
     height: usize,
     // width in pixels
     width: usize,
-    // stride in bytes (!!)
+    // stride in bytes (!!), at least width * bytes_per_pixel
     stride: usize,
     // bytes per pixel
     bytes_per_pixel: usize,
+    // format of each pixel
+    pixel_format: PixelFormat,
+    // drawing outside of this rectangle is discarded, if set
+    clip: Option<Rect>,
+}
+
+/// A rectangle given as `(x, y, width, height)` in pixels.
+pub type Rect = (usize, usize, usize, usize);
+
+/// Layout of a framebuffer's memory, for code that needs to interpret
+/// its contents, like exporting screenshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferInfo {
+    /// Width in pixels
+    pub width: usize,
+    /// Height in pixels
+    pub height: usize,
+    /// Distance between the start of two rows in bytes
+    pub stride: usize,
+    pub bytes_per_pixel: usize,
+    pub format: PixelFormat,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl Framebuffer<'static> {
+    /// Draw into the given framebuffer for the rest of the kernel's runtime.
+    ///
+    /// # Safety
+    /// The framebuffer's memory must stay valid forever and must not be
+    /// accessed through `buffer` anymore after this call.
+    unsafe fn from_raw(buffer: &mut FrameBuffer) -> Framebuffer<'static> {
+        let FrameBufferInfo {
+            horizontal_resolution: width,
+            vertical_resolution: height,
+            stride,
+            bytes_per_pixel,
+            pixel_format,
+            ..
+        } = buffer.info();
+        let buffer_ptr = buffer.buffer_mut().as_mut_ptr();
+        let buffer_len = buffer.buffer_mut().len();
+
+        Framebuffer::from_slice(
+            slice::from_raw_parts_mut(buffer_ptr, buffer_len),
+            width,
+            height,
+            stride,
+            bytes_per_pixel,
+            pixel_format,
+        )
+    }
+}
+
+impl<'b> Framebuffer<'b> {
+    /// Use the given slice as framebuffer, for example to draw into memory.
+    /// `stride` is the distance between rows in pixels, like in `FrameBufferInfo`.
+    /// It may exceed `width` when the firmware pads rows; the padding
+    /// is skipped when drawing and never written to.
+    ///
+    /// Panics if `stride` is less than `width`, since rows would overlap
+    /// and everything drawn would appear sheared.
+    pub fn from_slice(
+        buffer: &'b mut [u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+        bytes_per_pixel: usize,
+        pixel_format: PixelFormat,
+    ) -> Framebuffer<'b> {
+        assert!(
+            stride >= width,
+            "framebuffer stride of {} pixels is less than its width of {}",
+            stride,
+            width
+        );
+        assert!(buffer.len() >= stride * bytes_per_pixel * height);
+        Framebuffer {
+            buffer,
+            height,
+            width,
+            stride: stride * bytes_per_pixel,
+            bytes_per_pixel,
+            pixel_format,
+            clip: None,
+        }
+    }
+
+    /// Set the clip rectangle; drawing outside of it is silently discarded.
+    /// `None` allows drawing to the entire buffer again.
+    pub fn set_clip(&mut self, clip: Option<Rect>) {
+        self.clip = clip;
+    }
+
+    /// Returns the area drawing is confined to as `(left, top, right, bottom)`,
+    /// with exclusive right and bottom edges.
+    fn bounds(&self) -> (usize, usize, usize, usize) {
+        match self.clip {
+            Some((x, y, w, h)) => (
+                x.min(self.width),
+                y.min(self.height),
+                x.saturating_add(w).min(self.width),
+                y.saturating_add(h).min(self.height),
+            ),
+            None => (0, 0, self.width, self.height),
+        }
+    }
+
+    /// Returns the width and height in pixels.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Returns the memory layout of the buffer.
+    pub fn info(&self) -> FramebufferInfo {
+        FramebufferInfo {
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+            bytes_per_pixel: self.bytes_per_pixel,
+            format: self.pixel_format,
+        }
+    }
+
+    /// Returns the color of the given pixel, or None if it is off-screen.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Color> {
+        let offset = self.offset_of(x as isize, y as isize)?;
+        Some(get_pixel(self.buffer, offset))
+    }
+
+    /// Returns the offset of the given pixel, or None if it is off-screen.
+    fn offset_of(&self, x: isize, y: isize) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            Some(y as usize * self.stride + (x as usize * self.bytes_per_pixel))
+        }
+    }
+
+    /// Returns the offset of the given pixel, or None if it is
+    /// off-screen or outside of the clip rectangle.
+    fn visible_offset_of(&self, x: isize, y: isize) -> Option<usize> {
+        let (left, top, right, bottom) = self.bounds();
+        let inside = |v: isize, min: usize, max: usize| v >= min as isize && v < max as isize;
+        if inside(x, left, right) && inside(y, top, bottom) {
+            self.offset_of(x, y)
+        } else {
+            None
+        }
+    }
+
+    /// Can existing pixels be read back as a color for blending?
+    fn supports_blending(&self) -> bool {
+        matches!(self.pixel_format, PixelFormat::RGB | PixelFormat::BGR)
+    }
+
+    /// Set the given pixel if it is on-screen.
+    fn plot(&mut self, x: isize, y: isize, color: Color) {
+        if let Some(offset) = self.visible_offset_of(x, y) {
+            set_pixel(self.buffer, offset, color)
+        }
+    }
+
+    /// Blend the given color onto the pixel with an opacity of `alpha`
+    /// (0 being transparent, 255 opaque).
+    /// Pixel formats that do not support blending get a solid pixel
+    /// if `alpha` is above half instead.
+    fn plot_blended(&mut self, x: isize, y: isize, color: Color, alpha: u8) {
+        if let Some(offset) = self.visible_offset_of(x, y) {
+            if self.supports_blending() {
+                let existing = get_pixel(self.buffer, offset);
+                set_pixel(self.buffer, offset, blend(existing, color, alpha))
+            } else if alpha >= 128 {
+                set_pixel(self.buffer, offset, color)
+            }
+        }
+    }
+
+    /// Fill the entire buffer with the given color, or only the clip rectangle if set.
+    pub fn clear(&mut self, color: Color) {
+        self.draw_rect(0, 0, self.width, self.height, color)
+    }
+
+    /// Returns a copy of the visible contents.
+    /// Rows are packed tightly, without the padding the framebuffer
+    /// might have at the end of each row; pixels keep their format.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let row_len = self.width * self.bytes_per_pixel;
+        let mut snapshot = Vec::with_capacity(row_len * self.height);
+        for row in 0..self.height {
+            let start = row * self.stride;
+            snapshot.extend_from_slice(&self.buffer[start..(start + row_len)]);
+        }
+        snapshot
+    }
+
+    /// Clamp the given X coordinate to the framebuffer, `width` being
+    /// the largest result to allow using it as an exclusive end.
+    fn clip_x(&self, x: isize) -> usize {
+        let (left, _, right, _) = self.bounds();
+        x.max(left as isize).min(right as isize) as usize
+    }
+
+    /// Clamp the given Y coordinate like `clip_x`.
+    fn clip_y(&self, y: isize) -> usize {
+        let (_, top, _, bottom) = self.bounds();
+        y.max(top as isize).min(bottom as isize) as usize
+    }
+
+    /// Clip the given rectangle to the framebuffer and clip rectangle, returning
+    /// None if nothing of it is visible.
+    fn clip_rect(
+        &self,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+    ) -> Option<(usize, usize, usize, usize)> {
+        let (left, top, right, bottom) = self.bounds();
+        let (x0, y0) = (x.max(left), y.max(top));
+        let x1 = x.saturating_add(w).min(right);
+        let y1 = y.saturating_add(h).min(bottom);
+        if x0 >= x1 || y0 >= y1 {
+            None
+        } else {
+            Some((x0, y0, x1 - x0, y1 - y0))
+        }
+    }
+
+    pub fn draw_hori_line(&mut self, x: usize, y: usize, len: usize, color: Color) {
+        let (x, y, len, _) = match self.clip_rect(x, y, len, 1) {
+            Some(rect) => rect,
+            None => return,
+        };
+        let mut offset = y * self.stride + (x * self.bytes_per_pixel);
+        for _ in 0..len {
+            set_pixel(self.buffer, offset, color);
+            offset += self.bytes_per_pixel;
+        }
+    }
+
+    pub fn draw_vert_line(&mut self, x: usize, y: usize, len: usize, color: Color) {
+        let (x, y, _, len) = match self.clip_rect(x, y, 1, len) {
+            Some(rect) => rect,
+            None => return,
+        };
+        let mut offset = y * self.stride + (x * self.bytes_per_pixel);
+        for _ in 0..len {
+            set_pixel(self.buffer, offset, color);
+            offset += self.stride;
+        }
+    }
+
+    /// See the function `draw_box`.
+    pub fn draw_box(
+        &mut self,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        border: Color,
+        fill: Option<Color>,
+    ) {
+        if w == 0 || h == 0 {
+            return;
+        }
+        // Edges past the end of the coordinate space are off-screen anyway
+        let (right, bottom) = (x.saturating_add(w - 1), y.saturating_add(h - 1));
+
+        self.draw_hori_line(x, y, w, border);
+        self.draw_hori_line(x, bottom, w, border);
+        self.draw_vert_line(x, y, h, border);
+        self.draw_vert_line(right, y, h, border);
+
+        match fill {
+            Some(fill) if w > 2 && h > 2 => {
+                self.draw_rect(x.saturating_add(1), y.saturating_add(1), w - 2, h - 2, fill)
+            }
+            _ => (),
+        }
+    }
+
+    /// See the function `draw_round_rect`.
+    pub fn draw_round_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        radius: usize,
+        color: Color,
+    ) {
+        let (left, top, right, bottom, r) = match round_rect_corners(x, y, w, h, radius) {
+            Some(corners) => corners,
+            None => return,
+        };
+
+        self.draw_hori_line(left, y, right - left + 1, color);
+        self.draw_hori_line(left, y.saturating_add(h - 1), right - left + 1, color);
+        self.draw_vert_line(x, top, bottom - top + 1, color);
+        self.draw_vert_line(x.saturating_add(w - 1), top, bottom - top + 1, color);
+
+        for_each_arc_point(r, |a, b| {
+            for &(dx, dy) in &[(a, b), (b, a)] {
+                let (right, bottom) = (right.saturating_add(dx), bottom.saturating_add(dy));
+                let points = [
+                    (left - dx, top - dy),
+                    (right, top - dy),
+                    (left - dx, bottom),
+                    (right, bottom),
+                ];
+                for &(px, py) in points.iter() {
+                    self.plot(px as isize, py as isize, color);
+                }
+            }
+        });
+    }
+
+    /// See the function `fill_round_rect`.
+    pub fn fill_round_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+        radius: usize,
+        color: Color,
+    ) {
+        let (left, top, right, bottom, r) = match round_rect_corners(x, y, w, h, radius) {
+            Some(corners) => corners,
+            None => return,
+        };
+
+        self.draw_rect(x, top, w, bottom - top + 1, color);
+        for_each_arc_point(r, |a, b| {
+            for &(dx, dy) in &[(a, b), (b, a)] {
+                let len = right - left + 2 * dx + 1;
+                self.draw_hori_line(left - dx, top - dy, len, color);
+                self.draw_hori_line(left - dx, bottom.saturating_add(dy), len, color);
+            }
+        });
+    }
+
+    /// See the function `draw_line`.
+    pub fn draw_line(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, color: Color) {
+        let (mut x, mut y) = (x0 as isize, y0 as isize);
+        let (x1, y1) = (x1 as isize, y1 as isize);
+
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let step_x = if x < x1 { 1 } else { -1 };
+        let step_y = if y < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            self.plot(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let err2 = 2 * err;
+            if err2 >= dy {
+                err += dy;
+                x += step_x;
+            }
+            if err2 <= dx {
+                err += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// See the function `draw_line_aa`.
+    pub fn draw_line_aa(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, color: Color) {
+        let (mut x0, mut y0) = (x0 as isize, y0 as isize);
+        let (mut x1, mut y1) = (x1 as isize, y1 as isize);
+
+        // Always walk along the major axis, left to right
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        if steep {
+            mem::swap(&mut x0, &mut y0);
+            mem::swap(&mut x1, &mut y1);
+        }
+        if x0 > x1 {
+            mem::swap(&mut x0, &mut x1);
+            mem::swap(&mut y0, &mut y1);
+        }
+
+        // Gradient and intersection are 16.16 fixed point
+        let dx = x1 - x0;
+        let dy = y1 - y0;
+        let gradient = if dx == 0 { 1 << 16 } else { (dy << 16) / dx };
+        let mut intery = y0 << 16;
+
+        for x in x0..=x1 {
+            let y = intery >> 16;
+            let coverage = ((intery >> 8) & 0xFF) as u8;
+            if steep {
+                self.plot_blended(y, x, color, 255 - coverage);
+                self.plot_blended(y + 1, x, color, coverage);
+            } else {
+                self.plot_blended(x, y, color, 255 - coverage);
+                self.plot_blended(x, y + 1, color, coverage);
+            }
+            intery += gradient;
+        }
+    }
+
+    /// See the function `fill_triangle`.
+    pub fn fill_triangle(
+        &mut self,
+        p0: (usize, usize),
+        p1: (usize, usize),
+        p2: (usize, usize),
+        color: Color,
+    ) {
+        let point = |(x, y): (usize, usize)| (x as isize, y as isize);
+        let mut points = [point(p0), point(p1), point(p2)];
+        let [(x0, y0), (x1, y1), (x2, y2)] = points;
+
+        // Collinear points have no area; draw them as a line
+        if (x1 - x0) * (y2 - y0) == (x2 - x0) * (y1 - y0) {
+            points.sort_unstable();
+            let ((xa, ya), (xb, yb)) = (points[0], points[2]);
+            return self.draw_line(xa as usize, ya as usize, xb as usize, yb as usize, color);
+        }
+
+        points.sort_unstable_by_key(|&(_, y)| y);
+        let [(x0, y0), (x1, y1), (x2, y2)] = points;
+
+        // X coordinate of the edge from a to b on scanline y, with integer math
+        let edge = |(xa, ya): (isize, isize), (xb, yb): (isize, isize), y: isize| {
+            if ya == yb {
+                xa
+            } else {
+                xa + (xb - xa) * (y - ya) / (yb - ya)
+            }
+        };
+
+        for y in self.clip_y(y0)..self.clip_y(y2 + 1) {
+            let y = y as isize;
+            // The long edge spans the whole triangle, the short one switches at y1
+            let long = edge((x0, y0), (x2, y2), y);
+            let short = if y < y1 {
+                edge((x0, y0), (x1, y1), y)
+            } else {
+                edge((x1, y1), (x2, y2), y)
+            };
+
+            let left = self.clip_x(long.min(short));
+            let right = self.clip_x(long.max(short) + 1);
+            if left < right {
+                self.draw_hori_line(left, y as usize, right - left, color);
+            }
+        }
+    }
+
+    /// See the function `flood_fill`.
+    pub fn flood_fill(&mut self, x: usize, y: usize, fill: Color) {
+        let target = match self.visible_offset_of(x as isize, y as isize) {
+            Some(offset) => get_pixel(self.buffer, offset),
+            None => return,
+        };
+        if target == fill {
+            return;
+        }
+
+        let (left, top, right, bottom) = self.bounds();
+        let mut seeds = Vec::new();
+        seeds.push((x, y));
+        while let Some((x, y)) = seeds.pop() {
+            // Already filled through another seed
+            if !self.has_color(x, y, target) {
+                continue;
+            }
+
+            let mut x0 = x;
+            while x0 > left && self.has_color(x0 - 1, y, target) {
+                x0 -= 1;
+            }
+            let mut x1 = x + 1;
+            while x1 < right && self.has_color(x1, y, target) {
+                x1 += 1;
+            }
+            self.draw_hori_line(x0, y, x1 - x0, fill);
+
+            // Seed every run of the target color next to the span
+            let rows = [(y > top, y.wrapping_sub(1)), (y + 1 < bottom, y + 1)];
+            for &(_, row) in rows.iter().filter(|(inside, _)| *inside) {
+                let mut in_run = false;
+                for x in x0..x1 {
+                    let matches = self.has_color(x, row, target);
+                    if matches && !in_run {
+                        seeds.push((x, row));
+                    }
+                    in_run = matches;
+                }
+            }
+        }
+    }
+
+    /// Is the given on-screen pixel of the given color?
+    fn has_color(&self, x: usize, y: usize, color: Color) -> bool {
+        self.offset_of(x as isize, y as isize)
+            .map_or(false, |offset| get_pixel(self.buffer, offset) == color)
+    }
+
+    /// Copy an image of `w` by `h` pixels, given row by row, to the given position.
+    /// Parts of the image that are off-screen are skipped.
+    pub fn blit(&mut self, x: usize, y: usize, w: usize, h: usize, pixels: &[Color]) {
+        assert!(
+            pixels.len() >= w * h,
+            "image has less than {}x{} pixels",
+            w,
+            h
+        );
+        let (x0, y0, visible_w, visible_h) = match self.clip_rect(x, y, w, h) {
+            Some(rect) => rect,
+            None => return,
+        };
+
+        for row in 0..visible_h {
+            let start = (y0 - y + row) * w + (x0 - x);
+            let mut offset = (y0 + row) * self.stride + (x0 * self.bytes_per_pixel);
+            for &color in &pixels[start..(start + visible_w)] {
+                set_pixel(self.buffer, offset, color);
+                offset += self.bytes_per_pixel;
+            }
+        }
+    }
+
+    pub fn draw_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: Color) {
+        let (x, y, w, h) = match self.clip_rect(x, y, w, h) {
+            Some(rect) => rect,
+            None => return,
+        };
+
+        // Draw the first row, then copy it into every other line
+        let first_row = y * self.stride + (x * self.bytes_per_pixel);
+        let mut offset = first_row;
+        for _ in 0..w {
+            set_pixel(self.buffer, offset, color);
+            offset += self.bytes_per_pixel;
+        }
+
+        let row = first_row..(first_row + w * self.bytes_per_pixel);
+        let mut line_offset = first_row;
+        for _ in 1..h {
+            line_offset += self.stride;
+            self.buffer.copy_within(row.clone(), line_offset);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Color {
     red: u8,
     green: u8,
@@ -54,59 +644,603 @@ pub struct Color {
 }
 
 impl Color {
-    pub fn from(red: u8, green: u8, blue: u8) -> Color {
+    pub const BLACK: Color = Color::hex(0x000000);
+    pub const WHITE: Color = Color::hex(0xFFFFFF);
+    pub const GRAY: Color = Color::hex(0x808080);
+    pub const RED: Color = Color::hex(0xFF0000);
+    pub const GREEN: Color = Color::hex(0x00FF00);
+    pub const BLUE: Color = Color::hex(0x0000FF);
+    pub const YELLOW: Color = Color::hex(0xFFFF00);
+    pub const CYAN: Color = Color::hex(0x00FFFF);
+    pub const MAGENTA: Color = Color::hex(0xFF00FF);
+
+    pub const fn from(red: u8, green: u8, blue: u8) -> Color {
         Color { red, green, blue }
     }
 
-    pub fn hex(hex: u32) -> Color {
+    pub const fn hex(hex: u32) -> Color {
         Color {
             red: (hex >> 16) as u8,
             green: (hex >> 8) as u8,
             blue: hex as u8,
         }
     }
+
+    /// Returns the color as `0xRRGGBB`, the inverse of `hex`.
+    pub const fn to_hex(&self) -> u32 {
+        (self.red as u32) << 16 | (self.green as u32) << 8 | self.blue as u32
+    }
+
+    pub const fn red(&self) -> u8 {
+        self.red
+    }
+
+    pub const fn green(&self) -> u8 {
+        self.green
+    }
+
+    pub const fn blue(&self) -> u8 {
+        self.blue
+    }
+
+    /// Linearly interpolate between `a` and `b` per channel,
+    /// `t` being clamped to 0 (all `a`) to 1 (all `b`).
+    pub fn lerp(a: Color, b: Color, t: f32) -> Color {
+        // NaN ends up as 0
+        let t = t.max(0.0).min(1.0);
+        blend(a, b, (t * 255.0 + 0.5) as u8)
+    }
 }
 
-fn obtain_buffer() -> MutexGuard<'static, Framebuffer> {
+/// Returns a copy of the visible screen contents, see `Framebuffer::snapshot`.
+pub fn snapshot() -> Vec<u8> {
+    obtain_buffer().snapshot()
+}
+
+/// Returns the width and height of the screen in pixels.
+pub fn dimensions() -> (usize, usize) {
+    obtain_buffer().dimensions()
+}
+
+/// Returns the memory layout of the screen, see `FramebufferInfo`.
+pub fn framebuffer_info() -> FramebufferInfo {
+    obtain_buffer().info()
+}
+
+fn obtain_buffer() -> MutexGuard<'static, Framebuffer<'static>> {
     FRAMEBUFFER.get().unwrap().lock()
 }
 
 fn draw_pixel(x: usize, y: usize, color: Color) {
     let mut buf = obtain_buffer();
-    let offset = y * buf.stride + (x * buf.bytes_per_pixel);
-    set_pixel(buf.buffer, offset, color)
+    // Coordinates too large for isize end up negative and are skipped as well
+    buf.plot(x as isize, y as isize, color)
 }
 
-fn draw_hori_line(x: usize, y: usize, len: usize, color: Color) {
-    let mut buf = obtain_buffer();
-    assert!((x + len) <= buf.width);
-    let mut offset = y * buf.stride + (x * buf.bytes_per_pixel);
-    for _ in 0..len {
-        set_pixel(buf.buffer, offset, color);
-        offset += buf.bytes_per_pixel;
-    }
+/// Confine all further drawing to the given rectangle, see `Framebuffer::set_clip`.
+pub fn set_clip(clip: Option<Rect>) {
+    obtain_buffer().set_clip(clip)
+}
+
+pub fn draw_hori_line(x: usize, y: usize, len: usize, color: Color) {
+    obtain_buffer().draw_hori_line(x, y, len, color)
+}
+
+pub fn draw_vert_line(x: usize, y: usize, len: usize, color: Color) {
+    obtain_buffer().draw_vert_line(x, y, len, color)
+}
+
+/// Draw a rectangle with a one pixel wide border,
+/// filling the interior if a fill color is given.
+/// Boxes smaller than 3 pixels in either dimension have no interior
+/// and consist only of their border.
+pub fn draw_box(x: usize, y: usize, w: usize, h: usize, border: Color, fill: Option<Color>) {
+    obtain_buffer().draw_box(x, y, w, h, border, fill)
+}
+
+/// Draw the outline of a rectangle with corners rounded by quarter circles
+/// of the given radius. The radius is clamped to fit the rectangle,
+/// so a square with a large radius becomes a circle.
+/// Parts that are off-screen are skipped.
+pub fn draw_round_rect(x: usize, y: usize, w: usize, h: usize, radius: usize, color: Color) {
+    obtain_buffer().draw_round_rect(x, y, w, h, radius, color)
+}
+
+/// Fill a rectangle with rounded corners, see `draw_round_rect`.
+pub fn fill_round_rect(x: usize, y: usize, w: usize, h: usize, radius: usize, color: Color) {
+    obtain_buffer().fill_round_rect(x, y, w, h, radius, color)
+}
+
+/// Draw a line between the two given points using Bresenham's algorithm.
+/// Parts of the line that are off-screen are skipped.
+pub fn draw_line(x0: usize, y0: usize, x1: usize, y1: usize, color: Color) {
+    obtain_buffer().draw_line(x0, y0, x1, y1, color)
+}
+
+/// Draw an anti-aliased line between the two given points using
+/// Xiaolin Wu's algorithm, with fixed-point math.
+/// Falls back to solid pixels if the pixel format does not support blending.
+pub fn draw_line_aa(x0: usize, y0: usize, x1: usize, y1: usize, color: Color) {
+    obtain_buffer().draw_line_aa(x0, y0, x1, y1, color)
+}
+
+/// Fill the triangle between the three given points using a scanline fill.
+/// Parts of the triangle that are off-screen are skipped;
+/// triangles with collinear points are drawn as a line.
+pub fn fill_triangle(p0: (usize, usize), p1: (usize, usize), p2: (usize, usize), color: Color) {
+    obtain_buffer().fill_triangle(p0, p1, p2, color)
+}
+
+/// Replace the contiguous area of pixels with the same color as the one at
+/// the given point with `fill`, using an iterative scanline fill.
+/// Pixels are connected horizontally and vertically; the area is bounded
+/// by the screen and the clip rectangle.
+pub fn flood_fill(x: usize, y: usize, fill: Color) {
+    obtain_buffer().flood_fill(x, y, fill)
 }
 
 pub fn draw_rect(x: usize, y: usize, w: usize, h: usize, color: Color) {
-    let mut buf = obtain_buffer();
-    assert!((x + w) <= buf.width);
-    assert!((y + h) <= buf.width);
+    obtain_buffer().draw_rect(x, y, w, h, color)
+}
 
-    let mut line_offset = y * buf.stride + (x * buf.bytes_per_pixel);
-    let mut offset = line_offset;
-    for _ in 0..h {
-        for _ in 0..w {
-            set_pixel(buf.buffer, offset, color);
-            offset += buf.bytes_per_pixel;
+/// Copy an image to the screen, see `Framebuffer::blit`.
+pub fn blit(x: usize, y: usize, w: usize, h: usize, pixels: &[Color]) {
+    obtain_buffer().blit(x, y, w, h, pixels)
+}
+
+/// Returns the centers of the corner circles of a rounded rectangle as
+/// `(left, top, right, bottom)`, along with the radius clamped to fit.
+/// None if the rectangle is empty. Coordinates past the end of the
+/// coordinate space saturate, as they are off-screen anyway.
+fn round_rect_corners(
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+    radius: usize,
+) -> Option<(usize, usize, usize, usize, usize)> {
+    if w == 0 || h == 0 {
+        return None;
+    }
+    let r = radius.min((w.min(h) - 1) / 2);
+    Some((
+        x.saturating_add(r),
+        y.saturating_add(r),
+        x.saturating_add(w - 1 - r),
+        y.saturating_add(h - 1 - r),
+        r,
+    ))
+}
+
+/// Call `point` with the offsets `(dx, dy)` from the center of a circle
+/// of the given radius for one octant, where `dx >= dy`,
+/// using the midpoint circle algorithm.
+fn for_each_arc_point(radius: usize, mut point: impl FnMut(usize, usize)) {
+    let (mut dx, mut dy) = (radius, 0);
+    let mut err = 1 - radius as isize;
+    while dx >= dy {
+        point(dx, dy);
+        dy += 1;
+        if err < 0 {
+            err += 2 * dy as isize + 1;
+        } else {
+            dx -= 1;
+            err += 2 * (dy as isize - dx as isize) + 1;
         }
-        line_offset += buf.stride;
-        offset = line_offset;
     }
 }
 
+/// Mix `over` onto `base` with an opacity of `alpha`.
+fn blend(base: Color, over: Color, alpha: u8) -> Color {
+    let mix = |base: u8, over: u8| {
+        ((over as u16 * alpha as u16 + base as u16 * (255 - alpha as u16)) / 255) as u8
+    };
+    Color {
+        red: mix(base.red, over.red),
+        green: mix(base.green, over.green),
+        blue: mix(base.blue, over.blue),
+    }
+}
+
+/// Read the pixel at `offset`, returning black if it is out of bounds.
+#[inline]
+fn get_pixel(buf: &[u8], offset: usize) -> Color {
+    let end = offset.checked_add(3).unwrap_or(usize::MAX);
+    match buf.get(offset..end) {
+        Some(&[blue, green, red]) => Color { red, green, blue },
+        _ => Color::BLACK,
+    }
+}
+
+/// Write the pixel at `offset`, doing nothing if it is out of bounds.
 #[inline]
 fn set_pixel(buf: &mut [u8], offset: usize, color: Color) {
-    buf[offset] = color.blue;
-    buf[offset + 1] = color.green;
-    buf[offset + 2] = color.red;
+    let end = offset.checked_add(3).unwrap_or(usize::MAX);
+    if let Some([blue, green, red]) = buf.get_mut(offset..end) {
+        *blue = color.blue;
+        *green = color.green;
+        *red = color.red;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        set_pixel, text::glyph, text_width, Color, Framebuffer, FramebufferInfo, GLYPH_SIZE,
+    };
+    use crate::{
+        drivers::interrupts::interrupts::{ticks, ticks_to_millis},
+        kprint,
+    };
+    use alloc::vec;
+    use bootloader::boot_info::PixelFormat;
+
+    const RED: Color = Color::hex(0xFF0000);
+    const BLUE: Color = Color::hex(0x0000FF);
+
+    #[test_case]
+    fn draw_into_slice() {
+        // 4x3 pixels with a stride of 5 pixels
+        let mut memory = vec![0; 5 * 4 * 3];
+        let mut buf = Framebuffer::from_slice(&mut memory, 4, 3, 5, 4, PixelFormat::BGR);
+        buf.clear(BLUE);
+        buf.draw_rect(1, 1, 2, 2, RED);
+
+        assert_eq!(buf.pixel(0, 0), Some(BLUE));
+        assert_eq!(buf.pixel(1, 1), Some(RED));
+        assert_eq!(buf.pixel(2, 2), Some(RED));
+        assert_eq!(buf.pixel(3, 2), Some(BLUE));
+        assert_eq!(buf.pixel(4, 0), None);
+        // Padding at the end of rows stays untouched
+        assert_eq!(memory[16..20], [0, 0, 0, 0]);
+    }
+
+    #[test_case]
+    fn blit_clipped() {
+        let mut memory = vec![0; 3 * 3 * 3];
+        let mut buf = Framebuffer::from_slice(&mut memory, 3, 3, 3, 3, PixelFormat::RGB);
+        // 2x2 image, with its bottom right corner off-screen
+        buf.blit(2, 2, 2, 2, &[RED, BLUE, BLUE, BLUE]);
+        assert_eq!(buf.pixel(2, 2), Some(RED));
+        assert_eq!(buf.pixel(1, 2), Some(Color::BLACK));
+        buf.blit(0, 0, 2, 1, &[BLUE, RED]);
+        assert_eq!(buf.pixel(1, 0), Some(RED));
+    }
+
+    #[test_case]
+    fn color_channels() {
+        let color = Color::hex(0x12AB3C);
+        assert_eq!(
+            (color.red(), color.green(), color.blue()),
+            (0x12, 0xAB, 0x3C)
+        );
+        assert_eq!(color.to_hex(), 0x12AB3C);
+        assert_eq!(Color::from(1, 2, 3).to_hex(), 0x010203);
+        assert_eq!(Color::hex(Color::MAGENTA.to_hex()), Color::MAGENTA);
+    }
+
+    #[test_case]
+    fn framebuffer_info() {
+        let mut memory = vec![0; 5 * 3 * 2];
+        let buf = Framebuffer::from_slice(&mut memory, 4, 2, 5, 3, PixelFormat::RGB);
+        let info = FramebufferInfo {
+            width: 4,
+            height: 2,
+            stride: 15,
+            bytes_per_pixel: 3,
+            format: PixelFormat::RGB,
+        };
+        assert_eq!(buf.info(), info);
+    }
+
+    #[test_case]
+    fn snapshot_skips_padding() {
+        let mut memory = vec![0; 3 * 3 * 2];
+        let mut buf = Framebuffer::from_slice(&mut memory, 2, 2, 3, 3, PixelFormat::RGB);
+        buf.draw_line(0, 0, 1, 1, RED);
+        assert_eq!(buf.snapshot().len(), 2 * 3 * 2);
+        assert_eq!(buf.pixel(1, 1), Some(RED));
+        assert_eq!(buf.pixel(1, 0), Some(Color::from(0, 0, 0)));
+    }
+
+    #[test_case]
+    fn fill_triangle() {
+        let mut memory = vec![0; 8 * 4 * 8];
+        let mut buf = Framebuffer::from_slice(&mut memory, 8, 8, 8, 4, PixelFormat::BGR);
+        let black = Color::from(0, 0, 0);
+
+        buf.fill_triangle((0, 0), (6, 0), (0, 6), RED);
+        assert_eq!(buf.pixel(0, 0), Some(RED));
+        assert_eq!(buf.pixel(2, 2), Some(RED));
+        assert_eq!(buf.pixel(0, 6), Some(RED));
+        assert_eq!(buf.pixel(5, 5), Some(black));
+
+        // Clipped against the right and bottom edges
+        buf.fill_triangle((4, 4), (20, 4), (4, 20), BLUE);
+        assert_eq!(buf.pixel(7, 4), Some(BLUE));
+        assert_eq!(buf.pixel(4, 7), Some(BLUE));
+
+        // Collinear points result in a line
+        buf.clear(black);
+        buf.fill_triangle((1, 1), (3, 3), (5, 5), RED);
+        assert_eq!(buf.pixel(1, 1), Some(RED));
+        assert_eq!(buf.pixel(4, 4), Some(RED));
+        assert_eq!(buf.pixel(1, 2), Some(black));
+    }
+
+    #[test_case]
+    fn clipping() {
+        let mut memory = vec![0; 4 * 4 * 4];
+        let mut buf = Framebuffer::from_slice(&mut memory, 4, 4, 4, 4, PixelFormat::BGR);
+        buf.draw_rect(2, 2, 10, 10, RED);
+        buf.draw_hori_line(3, 0, 5, BLUE);
+        buf.draw_vert_line(0, 3, 5, BLUE);
+        buf.draw_rect(4, 0, 1, 1, BLUE);
+        buf.draw_hori_line(0, 7, 2, BLUE);
+
+        assert_eq!(buf.pixel(3, 3), Some(RED));
+        assert_eq!(buf.pixel(1, 1), Some(Color::from(0, 0, 0)));
+        assert_eq!(buf.pixel(3, 0), Some(BLUE));
+        assert_eq!(buf.pixel(0, 3), Some(BLUE));
+
+        // Boxes reaching past the end of the coordinate space keep their visible edges
+        buf.draw_box(1, 1, usize::MAX, usize::MAX, BLUE, Some(RED));
+        assert_eq!(buf.pixel(3, 1), Some(BLUE));
+        assert_eq!(buf.pixel(1, 3), Some(BLUE));
+        assert_eq!(buf.pixel(2, 2), Some(RED));
+        // Empty boxes draw nothing, not even next to them
+        buf.draw_box(1, 0, 0, 3, RED, None);
+        assert_eq!(buf.pixel(0, 0), Some(Color::from(0, 0, 0)));
+    }
+
+    #[test_case]
+    fn clip_rectangle() {
+        let mut memory = vec![0; 4 * 4 * 4];
+        let mut buf = Framebuffer::from_slice(&mut memory, 4, 4, 4, 4, PixelFormat::BGR);
+        let black = Color::from(0, 0, 0);
+        buf.set_clip(Some((1, 1, 2, 2)));
+        buf.clear(RED);
+        buf.draw_line(0, 0, 3, 3, BLUE);
+
+        assert_eq!(buf.pixel(0, 0), Some(black));
+        assert_eq!(buf.pixel(1, 1), Some(BLUE));
+        assert_eq!(buf.pixel(2, 1), Some(RED));
+        assert_eq!(buf.pixel(3, 3), Some(black));
+        assert_eq!(buf.pixel(3, 1), Some(black));
+
+        buf.set_clip(None);
+        buf.draw_hori_line(0, 0, 4, BLUE);
+        assert_eq!(buf.pixel(3, 0), Some(BLUE));
+    }
+
+    #[test_case]
+    fn round_rect() {
+        let mut memory = vec![0; 12 * 4 * 12];
+        let mut buf = Framebuffer::from_slice(&mut memory, 12, 12, 12, 4, PixelFormat::BGR);
+        let black = Color::from(0, 0, 0);
+
+        buf.draw_round_rect(1, 1, 10, 8, 3, RED);
+        // Straight edges between the corners
+        assert_eq!(buf.pixel(4, 1), Some(RED));
+        assert_eq!(buf.pixel(7, 8), Some(RED));
+        assert_eq!(buf.pixel(1, 4), Some(RED));
+        assert_eq!(buf.pixel(10, 5), Some(RED));
+        // Corners are cut off, the interior stays empty
+        assert_eq!(buf.pixel(1, 1), Some(black));
+        assert_eq!(buf.pixel(10, 8), Some(black));
+        assert_eq!(buf.pixel(2, 2), Some(RED));
+        assert_eq!(buf.pixel(5, 4), Some(black));
+
+        buf.clear(black);
+        buf.fill_round_rect(1, 1, 10, 8, 3, RED);
+        assert_eq!(buf.pixel(5, 4), Some(RED));
+        assert_eq!(buf.pixel(2, 2), Some(RED));
+        assert_eq!(buf.pixel(1, 1), Some(black));
+        assert_eq!(buf.pixel(10, 1), Some(black));
+
+        // A radius too large for the rectangle is clamped, making a circle
+        buf.clear(black);
+        buf.fill_round_rect(0, 0, 7, 7, 100, BLUE);
+        assert_eq!(buf.pixel(3, 0), Some(BLUE));
+        assert_eq!(buf.pixel(6, 3), Some(BLUE));
+        assert_eq!(buf.pixel(3, 3), Some(BLUE));
+        assert_eq!(buf.pixel(0, 0), Some(black));
+        assert_eq!(buf.pixel(6, 6), Some(black));
+
+        // Clipped against the edges of the screen
+        buf.draw_round_rect(8, 8, 10, 10, 2, BLUE);
+        assert_eq!(buf.pixel(8, 11), Some(BLUE));
+        assert_eq!(buf.pixel(11, 8), Some(BLUE));
+        buf.fill_round_rect(5, 5, 0, 4, 2, RED);
+
+        // Rectangles reaching past the end of the coordinate space
+        buf.clear(black);
+        buf.draw_round_rect(4, 4, usize::MAX, usize::MAX, 2, RED);
+        assert_eq!(buf.pixel(8, 4), Some(RED));
+        assert_eq!(buf.pixel(4, 8), Some(RED));
+        assert_eq!(buf.pixel(4, 4), Some(black));
+        buf.fill_round_rect(4, 4, usize::MAX, usize::MAX, 2, BLUE);
+        assert_eq!(buf.pixel(11, 11), Some(BLUE));
+    }
+
+    #[test_case]
+    fn flood_fill() {
+        let mut memory = vec![0; 8 * 4 * 8];
+        let mut buf = Framebuffer::from_slice(&mut memory, 8, 8, 8, 4, PixelFormat::BGR);
+        let black = Color::from(0, 0, 0);
+        // A U-shaped wall, open at the top
+        buf.draw_vert_line(2, 2, 5, BLUE);
+        buf.draw_vert_line(5, 2, 5, BLUE);
+        buf.draw_hori_line(2, 6, 4, BLUE);
+
+        buf.flood_fill(3, 5, RED);
+        assert_eq!(buf.pixel(3, 5), Some(RED));
+        assert_eq!(buf.pixel(4, 2), Some(RED));
+        // Leaks out of the opening at the top, but not through the walls
+        assert_eq!(buf.pixel(0, 0), Some(RED));
+        assert_eq!(buf.pixel(7, 7), Some(RED));
+        assert_eq!(buf.pixel(2, 4), Some(BLUE));
+
+        // Only the wall is connected to the starting pixel
+        buf.flood_fill(2, 2, black);
+        assert_eq!(buf.pixel(5, 2), Some(black));
+        assert_eq!(buf.pixel(3, 5), Some(RED));
+
+        // Stops at the clip rectangle
+        buf.clear(black);
+        buf.set_clip(Some((0, 0, 4, 4)));
+        buf.flood_fill(1, 1, RED);
+        buf.set_clip(None);
+        assert_eq!(buf.pixel(3, 3), Some(RED));
+        assert_eq!(buf.pixel(4, 4), Some(black));
+        buf.flood_fill(7, 7, RED);
+        assert_eq!(buf.pixel(4, 4), Some(RED));
+    }
+
+    #[test_case]
+    fn draw_glyph() {
+        let mut memory = vec![0; 12 * 4 * 8];
+        let mut buf = Framebuffer::from_slice(&mut memory, 12, 8, 12, 4, PixelFormat::BGR);
+        let a = glyph('A');
+        // Entirely visible, and cut off by the right edge
+        buf.draw_glyph(0, 0, a, RED, Some(BLUE));
+        buf.draw_glyph(8, 0, a, RED, Some(BLUE));
+        for row in 0..GLYPH_SIZE {
+            for column in 0..GLYPH_SIZE {
+                let expected = if a[row] & (1 << column) != 0 {
+                    RED
+                } else {
+                    BLUE
+                };
+                assert_eq!(buf.pixel(column, row), Some(expected));
+                if column < 4 {
+                    assert_eq!(buf.pixel(8 + column, row), Some(expected));
+                }
+            }
+        }
+        assert_eq!(glyph('\u{1F600}'), glyph('?'));
+    }
+
+    #[test_case]
+    fn vertical_string() {
+        let mut memory = vec![0; 8 * 4 * 24];
+        let mut buf = Framebuffer::from_slice(&mut memory, 8, 24, 8, 4, PixelFormat::BGR);
+        let black = Color::from(0, 0, 0);
+        let a = glyph('A');
+        // The third character does not fit into the clip rectangle,
+        // the others are cut off at its right edge
+        buf.set_clip(Some((0, 0, 4, 20)));
+        buf.draw_string_vertical(0, 0, "AAA", RED);
+        buf.draw_string_vertical(0, usize::MAX - 4, "A", RED);
+        for row in 0..(3 * GLYPH_SIZE) {
+            for column in 0..GLYPH_SIZE {
+                let set = a[row % GLYPH_SIZE] & (1 << column) != 0;
+                let expected = if set && column < 4 && row < 16 {
+                    RED
+                } else {
+                    black
+                };
+                assert_eq!(buf.pixel(column, row), Some(expected));
+            }
+        }
+    }
+
+    #[test_case]
+    fn text_width_() {
+        assert_eq!(text_width("", 1), 0);
+        assert_eq!(text_width("hello", 1), 5 * GLYPH_SIZE);
+        assert_eq!(text_width("hello", 3), 15 * GLYPH_SIZE);
+        // Characters, not bytes
+        assert_eq!(text_width("äö", 1), 2 * GLYPH_SIZE);
+    }
+
+    #[test_case]
+    fn text_throughput() {
+        const PASSES: usize = 10;
+        let (width, height) = (640, 480);
+        let mut memory = vec![0; width * height * 4];
+        let mut buf =
+            Framebuffer::from_slice(&mut memory, width, height, width, 4, PixelFormat::BGR);
+        let (columns, rows) = (width / GLYPH_SIZE, height / GLYPH_SIZE);
+
+        let mut time = |checked: bool| {
+            let start = ticks();
+            for pass in 0..PASSES {
+                for row in 0..rows {
+                    for column in 0..columns {
+                        let c = (b'!' + ((row + column + pass) % 90) as u8) as char;
+                        let (x, y) = (column * GLYPH_SIZE, row * GLYPH_SIZE);
+                        if checked {
+                            buf.draw_glyph_clipped(x, y, glyph(c), RED, Some(BLUE));
+                        } else {
+                            buf.draw_glyph(x, y, glyph(c), RED, Some(BLUE));
+                        }
+                    }
+                }
+            }
+            ticks() - start
+        };
+        let (checked, fast) = (time(true), time(false));
+
+        let rate = |ticks| match ticks_to_millis(ticks) {
+            0 => u64::MAX,
+            millis => (columns * rows * PASSES) as u64 * 1000 / millis,
+        };
+        kprint!(
+            "checked {} glyphs/s, fast {} glyphs/s\t",
+            rate(checked),
+            rate(fast)
+        );
+    }
+
+    /// Full-screen clears with `draw_rect`, which copies its first row,
+    /// compared to setting every pixel like it did before.
+    #[test_case]
+    fn clear_throughput() {
+        const PASSES: usize = 20;
+        let (width, height) = (640, 480);
+        let mut memory = vec![0; width * height * 4];
+        let mut buf =
+            Framebuffer::from_slice(&mut memory, width, height, width, 4, PixelFormat::BGR);
+
+        let mut time = |copy_rows: bool| {
+            let start = ticks();
+            for pass in 0..PASSES {
+                let color = if pass % 2 == 0 { RED } else { BLUE };
+                if copy_rows {
+                    buf.draw_rect(0, 0, width, height, color);
+                } else {
+                    for offset in (0..(width * height * 4)).step_by(4) {
+                        set_pixel(buf.buffer, offset, color);
+                    }
+                }
+            }
+            ticks() - start
+        };
+        let (per_pixel, copied) = (time(false), time(true));
+        assert_eq!(buf.pixel(width - 1, height - 1), Some(BLUE));
+
+        let rate = |ticks| match ticks_to_millis(ticks) {
+            0 => u64::MAX,
+            millis => PASSES as u64 * 1000 / millis,
+        };
+        kprint!(
+            "per pixel {} clears/s, row copies {} clears/s\t",
+            rate(per_pixel),
+            rate(copied)
+        );
+    }
+
+    #[test_case]
+    fn lerp() {
+        assert_eq!(Color::lerp(Color::BLACK, Color::WHITE, 0.0), Color::BLACK);
+        assert_eq!(Color::lerp(Color::BLACK, Color::WHITE, 1.0), Color::WHITE);
+        assert_eq!(
+            Color::lerp(Color::BLACK, Color::WHITE, 0.5),
+            Color::hex(0x808080)
+        );
+        assert_eq!(Color::lerp(Color::RED, Color::BLUE, -3.0), Color::RED);
+        assert_eq!(Color::lerp(Color::RED, Color::BLUE, 7.5), Color::BLUE);
+        assert_eq!(Color::lerp(Color::RED, Color::BLUE, f32::NAN), Color::RED);
+    }
 }
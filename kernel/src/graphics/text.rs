@@ -0,0 +1,120 @@
+use crate::graphics::{obtain_buffer, set_pixel, Color, Framebuffer};
+use font8x8::{UnicodeFonts, BASIC_FONTS};
+use lazy_static::lazy_static;
+
+/// Width and height of a glyph in pixels.
+pub const GLYPH_SIZE: usize = 8;
+
+lazy_static! {
+    /// Bitmaps of all ASCII characters, looked up in the font once.
+    static ref ASCII_GLYPHS: [[u8; GLYPH_SIZE]; 128] = {
+        let mut glyphs = [[0; GLYPH_SIZE]; 128];
+        for (c, glyph) in glyphs.iter_mut().enumerate() {
+            *glyph = lookup(c as u8 as char);
+        }
+        glyphs
+    };
+}
+
+/// Returns the bitmap of the given character, or of '?' if it is not in the font.
+/// Each byte is a row, with bit 0 being the leftmost pixel.
+pub(super) fn glyph(c: char) -> [u8; GLYPH_SIZE] {
+    match ASCII_GLYPHS.get(c as usize) {
+        Some(glyph) => *glyph,
+        None => lookup(c),
+    }
+}
+
+fn lookup(c: char) -> [u8; GLYPH_SIZE] {
+    BASIC_FONTS.get(c).or_else(|| BASIC_FONTS.get('?')).unwrap()
+}
+
+impl Framebuffer<'_> {
+    /// Draw a glyph with its top left corner at the given position.
+    /// Pixels not set in the glyph are filled with the background color
+    /// if one is given and left untouched otherwise; off-screen pixels are skipped.
+    pub(super) fn draw_glyph(
+        &mut self,
+        x: usize,
+        y: usize,
+        glyph: [u8; GLYPH_SIZE],
+        foreground: Color,
+        background: Option<Color>,
+    ) {
+        let (left, top, right, bottom) = self.bounds();
+        let visible = x >= left
+            && y >= top
+            && x.saturating_add(GLYPH_SIZE) <= right
+            && y.saturating_add(GLYPH_SIZE) <= bottom;
+        if !visible {
+            return self.draw_glyph_clipped(x, y, glyph, foreground, background);
+        }
+
+        // Fast path for the common case, skipping bounds checks for every pixel
+        let mut row_offset = y * self.stride + x * self.bytes_per_pixel;
+        for bits in glyph.iter() {
+            let mut offset = row_offset;
+            for column in 0..GLYPH_SIZE {
+                if bits & (1 << column) != 0 {
+                    set_pixel(self.buffer, offset, foreground);
+                } else if let Some(background) = background {
+                    set_pixel(self.buffer, offset, background);
+                }
+                offset += self.bytes_per_pixel;
+            }
+            row_offset += self.stride;
+        }
+    }
+
+    /// Like `draw_glyph`, but checks every pixel.
+    pub(super) fn draw_glyph_clipped(
+        &mut self,
+        x: usize,
+        y: usize,
+        glyph: [u8; GLYPH_SIZE],
+        foreground: Color,
+        background: Option<Color>,
+    ) {
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_SIZE {
+                // Coordinates too large for isize end up negative and are skipped
+                let (px, py) = (x.saturating_add(column), y.saturating_add(row));
+                let (px, py) = (px as isize, py as isize);
+                if bits & (1 << column) != 0 {
+                    self.plot(px, py, foreground);
+                } else if let Some(background) = background {
+                    self.plot(px, py, background);
+                }
+            }
+        }
+    }
+
+    /// See the function `draw_string_vertical`.
+    pub fn draw_string_vertical(&mut self, x: usize, y: usize, s: &str, foreground: Color) {
+        let (_, _, _, bottom) = self.bounds();
+        let mut top = y;
+        for c in s.chars() {
+            match top.checked_add(GLYPH_SIZE) {
+                Some(end) if end <= bottom => (),
+                _ => break,
+            }
+            self.draw_glyph(x, top, glyph(c), foreground, None);
+            top += GLYPH_SIZE;
+        }
+    }
+}
+
+/// Returns the width in pixels of the string when drawn with glyphs
+/// scaled up by `scale`, for centering or aligning text before drawing it.
+/// Glyphs are drawn right next to each other without spacing,
+/// so there is no gap after the last one to account for.
+pub fn text_width(s: &str, scale: usize) -> usize {
+    s.chars().count() * GLYPH_SIZE * scale
+}
+
+/// Draw a string with each character below the previous one, for vertical labels.
+/// Characters that do not fit on screen or into the clip rectangle vertically
+/// are cut off, along with everything after them; horizontally they are clipped.
+pub fn draw_string_vertical(x: usize, y: usize, s: &str, foreground: Color) {
+    obtain_buffer().draw_string_vertical(x, y, s, foreground)
+}
@@ -17,6 +17,7 @@ use core::panic::PanicInfo;
 pub mod allocator;
 pub mod drivers;
 pub mod graphics;
+pub mod log;
 pub mod scheduling;
 pub mod shell;
 pub mod vm;
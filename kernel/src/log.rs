@@ -0,0 +1,83 @@
+use crate::graphics::{Color, Console, DEFAULT_BACKGROUND};
+use conquer_once::spin::OnceCell;
+use core::fmt::{self, Write};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// Severity of a log message, from most to least severe.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Level::Error => Color::hex(0xFF5555),
+            Level::Warn => Color::hex(0xFFCC55),
+            Level::Info => Color::hex(0xCCCCCC),
+        }
+    }
+}
+
+/// Messages less severe than this are discarded;
+/// since the level of each message is known at compile time,
+/// the calls are optimized out entirely.
+pub const MAX_LEVEL: Level = if cfg!(debug_assertions) {
+    Level::Info
+} else {
+    Level::Warn
+};
+
+static CONSOLE: OnceCell<Mutex<Console>> = OnceCell::uninit();
+
+/// Start showing log messages on screen, graphics must be initialized.
+/// Before this, messages are only sent to serial.
+pub fn init_console() {
+    CONSOLE.init_once(|| Mutex::new(Console::new(Level::Info.color(), DEFAULT_BACKGROUND)));
+}
+
+/// Logs a message with level INFO to serial and the screen.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Info, format_args!($($arg)*)));
+}
+
+/// Logs a message with level WARN to serial and the screen.
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Warn, format_args!($($arg)*)));
+}
+
+/// Logs a message with level ERROR to serial and the screen.
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => ($crate::log::_log($crate::log::Level::Error, format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) {
+    if level > MAX_LEVEL {
+        return;
+    }
+
+    crate::kprintln!("[{}] {}", level.name(), args);
+    if let Some(console) = CONSOLE.get() {
+        interrupts::without_interrupts(|| {
+            let mut console = console.lock();
+            console.set_foreground(level.color());
+            // Writing to the console cannot fail
+            writeln!(console, "[{}] {}", level.name(), args).ok();
+        });
+    }
+}
@@ -12,9 +12,9 @@ use x86_64::VirtAddr;
 use yacuri::{
     allocator,
     allocator::{memory, memory::BootInfoFrameAllocator},
-    drivers::keyboard,
-    graphics::init_graphics,
-    hlt_loop, kprintln, println,
+    drivers::{disk::dma, keyboard},
+    graphics::{dimensions, init_graphics, DEFAULT_BACKGROUND},
+    hlt_loop, kprintln, log, log_info, println,
     scheduling::{executor::Executor, task::Task},
     vm,
     vm::test_app,
@@ -26,8 +26,15 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     kprintln!("Hello World! rust says trans rights but with framebuffers now");
 
     yacuri::init();
-    init_graphics(boot_info.framebuffer.as_mut().unwrap());
+    init_graphics(
+        boot_info.framebuffer.as_mut().unwrap(),
+        Some(DEFAULT_BACKGROUND),
+    );
+    log::init_console();
+    let (width, height) = dimensions();
+    log_info!("graphics initialized at {}x{}", width, height);
     init_memory(boot_info);
+    log_info!("memory initialized");
 
     test_app();
 
@@ -45,12 +52,18 @@ fn init_memory(boot_info: &'static BootInfo) {
     let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_regions) };
     allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
     vm::init_code_heap(&mut mapper, &mut frame_allocator).expect("vm heap initialization failed");
+    if unsafe { dma::init(&mut frame_allocator, phys_mem_offset) } {
+        log_info!("found IDE bus master, using DMA for disk transfers");
+    }
 }
 
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    x86_64::instructions::interrupts::disable();
     kprintln!("{}", info);
+    // Interrupts are disabled, nothing else can draw anymore
+    unsafe { yacuri::graphics::draw_panic_screen(info) };
     hlt_loop()
 }
 
@@ -15,6 +15,7 @@ use alloc::{
 use core::cmp::min;
 use fatfs::{Read, Seek, SeekFrom, Write};
 use pc_keyboard::{DecodedKey, KeyCode};
+use yacari::Options;
 
 mod command;
 
@@ -138,7 +139,10 @@ impl Shell {
                 let file = self.read_file(&file);
                 if let Some(file) = file {
                     println!("executing {} ({} bytes)...", file, file.len());
-                    kprintln!("{:#?}", yacari::execute_module::<()>(&file, &[]))
+                    kprintln!(
+                        "{:#?}",
+                        yacari::execute_module::<()>(&file, &[], &Options::default())
+                    )
                 }
             }
 
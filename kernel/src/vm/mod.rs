@@ -6,12 +6,14 @@ use crate::{
     scheduling::task::Task,
 };
 pub use memory::init_code_heap;
+use yacari::Options;
 
 pub fn test_app() {
     yacari::execute_path::<_, ()>(
         FileSystem::new(),
         &["test_app", "system/yacuri"],
         &[("draw_rect", test_draw_rect as *const u8)],
+        &Options::default(),
     )
     .unwrap();
 }
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(yacuri::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use fatfs::{Read, Seek, SeekFrom, Write};
+use x86_64::VirtAddr;
+use yacuri::{
+    allocator,
+    allocator::{memory, memory::BootInfoFrameAllocator},
+    drivers::disk::{ata_pio::AtaDrive, dma},
+};
+
+// Contents of the test drive, see the ata_pio tests.
+static ACTUAL: &[u8; 1024 * 64] = include_bytes!("../src/drivers/disk/test_drive.bin");
+
+entry_point!(main);
+
+fn main(boot_info: &'static mut BootInfo) -> ! {
+    yacuri::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_regions) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    // The default QEMU machine has a PIIX IDE controller capable of DMA
+    assert!(unsafe { dma::init(&mut frame_allocator, phys_mem_offset) });
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    yacuri::test_panic_handler(info)
+}
+
+fn drive() -> AtaDrive {
+    let drive = unsafe { AtaDrive::new(0x1F0, 0x3F6) };
+    assert!(drive.dma_enabled());
+    drive
+}
+
+#[test_case]
+fn read() {
+    let mut drive = drive();
+    // Unaligned and larger than the DMA buffer, so it takes multiple transfers
+    let mut buf = vec![0; 10_000];
+    drive.seek(SeekFrom::Start(100)).unwrap();
+    assert_eq!(drive.read(&mut buf), Ok(buf.len()));
+    assert_eq!(buf[..], ACTUAL[100..10_100]);
+}
+
+#[test_case]
+fn write() {
+    let mut drive = drive();
+    let start = 3 * 512 + 7;
+    let changed = (0..5000).map(|i| i as u8).collect::<Vec<_>>();
+    let mut buf = vec![0; 5100];
+
+    drive.seek(SeekFrom::Start(start)).unwrap();
+    drive.write(&changed).unwrap();
+    drive.seek(SeekFrom::Start(start - 50)).unwrap();
+    drive.read(&mut buf).unwrap();
+    let start = start as usize;
+    assert_eq!(buf[..50], ACTUAL[(start - 50)..start]);
+    assert_eq!(buf[50..5050], changed[..]);
+    assert_eq!(buf[5050..], ACTUAL[(start + 5000)..(start + 5050)]);
+
+    // Restore the drive for other tests
+    drive.seek(SeekFrom::Start(start as u64)).unwrap();
+    drive.write(&ACTUAL[start..(start + 5000)]).unwrap();
+}
+
+#[test_case]
+fn shared_between_drives() {
+    let drive = drive();
+    // Taken by the drive until it is dropped
+    assert!(dma::take().is_none());
+    drop(drive);
+    let dma = dma::take().expect("bus master released by the dropped drive");
+    dma::release(dma);
+}
@@ -0,0 +1,65 @@
+//! Times lexing and parsing of a large generated source file.
+//! Run with `cargo bench --bench frontend`.
+
+use std::{fmt::Write, time::Duration};
+use yacari::parse_stats;
+
+const FUNCTIONS: usize = 5000;
+const ITERATIONS: usize = 20;
+
+fn main() {
+    let src = generate_source(FUNCTIONS);
+    let mut lex = Vec::with_capacity(ITERATIONS);
+    let mut parse = Vec::with_capacity(ITERATIONS);
+
+    let mut stats = None;
+    for _ in 0..ITERATIONS {
+        let run = parse_stats(&src).expect("Generated source failed to parse");
+        lex.push(run.lex_duration);
+        parse.push(run.duration);
+        stats = Some(run);
+    }
+
+    let stats = stats.unwrap();
+    println!(
+        "source: {} bytes, {} tokens, {} nodes",
+        src.len(),
+        stats.tokens,
+        stats.nodes
+    );
+    report("lex", &mut lex, src.len());
+    report("parse", &mut parse, src.len());
+}
+
+fn report(name: &str, times: &mut [Duration], bytes: usize) {
+    times.sort();
+    let median = times[times.len() / 2];
+    let mb_per_sec = bytes as f64 / median.as_secs_f64() / 1_000_000.0;
+    println!(
+        "{:<6} min {:>10.3?}  median {:>10.3?}  max {:>10.3?}  ({:.1} MB/s)",
+        name,
+        times[0],
+        median,
+        times[times.len() - 1],
+        mb_per_sec
+    );
+}
+
+fn generate_source(functions: usize) -> String {
+    let mut src = String::new();
+    for i in 0..functions {
+        writeln!(
+            src,
+            "fun func{i}(a: i64, b: i64) -> i64 {{
+    var c = a * {i} + b
+    while (c < 1000) {{
+        c = c + func{i}(b, 2)
+    }}
+    if (c >= 5 and a != b) c - 1 else {{ val d = c / 3 \n d }}
+}}",
+            i = i
+        )
+        .unwrap();
+    }
+    src
+}
@@ -0,0 +1,195 @@
+use crate::{
+    error::{
+        Error,
+        ErrorKind::{E500, E501, E511, E514, E515, E602, E604},
+        Res,
+    },
+    lexer::{TKind, Token},
+    parser::ast::{EExpr, Expr, Literal, Type},
+};
+use alloc::string::ToString;
+
+/// Fully evaluate a constant expression at compile time.
+/// Constant expressions consist of literals combined with operators and casts;
+/// anything else, like variables or calls, results in an error.
+/// Integer arithmetic is always checked, overflow is an error.
+pub fn eval_const(expr: &Expr) -> Res<Literal> {
+    match &*expr.ty {
+        EExpr::Literal(literal) => Ok(literal.clone()),
+        EExpr::Unary { op, right } => unary(op, eval_const(right)?),
+        EExpr::Binary { left, op, right } if op.kind != TKind::Equal => {
+            binary(eval_const(left)?, op, eval_const(right)?)
+        }
+        EExpr::Cast { expr, ty } => cast(eval_const(expr)?, ty),
+        _ => Err(Error::new(expr.start, E514)),
+    }
+}
+
+fn unary(op: &Token, right: Literal) -> Res<Literal> {
+    Ok(match (op.kind, right) {
+        (TKind::Minus, Literal::Int(int)) => Literal::Int(
+            int.checked_neg()
+                .ok_or_else(|| Error::new(op.start, E602))?,
+        ),
+        (TKind::Minus, Literal::Float(float)) => Literal::Float(-float),
+        (TKind::Bang, Literal::Bool(b)) => Literal::Bool(!b),
+        (_, right) => return Err(not_applicable(op, &right)),
+    })
+}
+
+fn binary(left: Literal, op: &Token, right: Literal) -> Res<Literal> {
+    let overflow = || Error::new(op.start, E602);
+    Ok(match (left, right) {
+        (Literal::Int(l), Literal::Int(r)) => match op.kind {
+            TKind::Plus => Literal::Int(l.checked_add(r).ok_or_else(overflow)?),
+            TKind::Minus => Literal::Int(l.checked_sub(r).ok_or_else(overflow)?),
+            TKind::Star => Literal::Int(l.checked_mul(r).ok_or_else(overflow)?),
+            TKind::Slash if r == 0 => return Err(Error::new(op.start, E515)),
+            TKind::Slash => Literal::Int(l.checked_div(r).ok_or_else(overflow)?),
+            TKind::StarStar if r < 0 => return Err(Error::new(op.start, E604)),
+            TKind::StarStar => {
+                let exp = if r > u32::MAX as i64 {
+                    u32::MAX
+                } else {
+                    r as u32
+                };
+                Literal::Int(l.checked_pow(exp).ok_or_else(overflow)?)
+            }
+            TKind::EqualEqual => Literal::Bool(l == r),
+            TKind::BangEqual => Literal::Bool(l != r),
+            TKind::Less => Literal::Bool(l < r),
+            TKind::LessEqual => Literal::Bool(l <= r),
+            TKind::Greater => Literal::Bool(l > r),
+            TKind::GreaterEqual => Literal::Bool(l >= r),
+            _ => return Err(not_applicable(op, &Literal::Int(l))),
+        },
+
+        (Literal::Float(l), Literal::Float(r)) => match op.kind {
+            TKind::Plus => Literal::Float(l + r),
+            TKind::Minus => Literal::Float(l - r),
+            TKind::Star => Literal::Float(l * r),
+            TKind::Slash => Literal::Float(l / r),
+            TKind::StarStar => Literal::Float(libm::pow(l, r)),
+            TKind::EqualEqual => Literal::Bool(l == r),
+            TKind::BangEqual => Literal::Bool(l != r),
+            TKind::Less => Literal::Bool(l < r),
+            TKind::LessEqual => Literal::Bool(l <= r),
+            TKind::Greater => Literal::Bool(l > r),
+            TKind::GreaterEqual => Literal::Bool(l >= r),
+            _ => return Err(not_applicable(op, &Literal::Float(l))),
+        },
+
+        (Literal::Bool(l), Literal::Bool(r)) => match op.kind {
+            TKind::And => Literal::Bool(l && r),
+            TKind::Or => Literal::Bool(l || r),
+            TKind::EqualEqual => Literal::Bool(l == r),
+            TKind::BangEqual => Literal::Bool(l != r),
+            _ => return Err(not_applicable(op, &Literal::Bool(l))),
+        },
+
+        (Literal::String(l), Literal::String(r)) => match op.kind {
+            TKind::EqualEqual => Literal::Bool(l == r),
+            TKind::BangEqual => Literal::Bool(l != r),
+            _ => return Err(not_applicable(op, &Literal::String(l))),
+        },
+
+        (left, right) => {
+            return Err(Error::new(
+                op.start,
+                E500 {
+                    left: type_name(&left).to_string(),
+                    right: type_name(&right).to_string(),
+                },
+            ))
+        }
+    })
+}
+
+fn cast(value: Literal, ty: &Type) -> Res<Literal> {
+    Ok(match (value, &*ty.name.lex) {
+        (Literal::Int(int), "f64") => Literal::Float(int as f64),
+        // Saturates on NaN and out-of-range values, like at runtime
+        (Literal::Float(float), "i64") => Literal::Int(float as i64),
+        (Literal::Bool(b), "i64") => Literal::Int(b as i64),
+        (value, name) if type_name(&value) == name => value,
+        (value, name) => {
+            return Err(Error::new(
+                ty.name.start,
+                E511 {
+                    from: type_name(&value).to_string(),
+                    to: name.to_string(),
+                },
+            ))
+        }
+    })
+}
+
+fn not_applicable(op: &Token, value: &Literal) -> Error {
+    Error::new(
+        op.start,
+        E501 {
+            op: op.lex.clone(),
+            ty: type_name(value).to_string(),
+        },
+    )
+}
+
+fn type_name(literal: &Literal) -> &'static str {
+    match literal {
+        Literal::Bool(_) => "bool",
+        Literal::Int(_) => "i64",
+        Literal::Float(_) => "f64",
+        Literal::String(_) => "str",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::eval_const;
+    use crate::{
+        error::ErrorKind,
+        parser::{
+            ast::{EExpr, Literal},
+            Parser,
+        },
+    };
+    use alloc::vec::Vec;
+    extern crate std;
+    use std::format;
+
+    fn eval(src: &str) -> Result<Literal, ErrorKind> {
+        let module = Parser::new(&format!("fun main() {{ {} }}", src))
+            .parse(Vec::new())
+            .unwrap();
+        let body = module.functions[0].body.as_ref().unwrap();
+        match &*body.ty {
+            EExpr::Block(exprs) => eval_const(&exprs[0]).map_err(|e| e.kind().clone()),
+            _ => panic!("function body is not a block"),
+        }
+    }
+
+    #[test]
+    fn constant() {
+        assert!(matches!(eval("1 + 2 * 3"), Ok(Literal::Int(7))));
+        assert!(matches!(eval("2 ** 10"), Ok(Literal::Int(1024))));
+        assert!(matches!(eval("-(4 / 2)"), Ok(Literal::Int(-2))));
+        assert!(matches!(eval("1.5 * 2.0"), Ok(Literal::Float(f)) if f == 3.0));
+        assert!(matches!(eval("3 > 2 and !false"), Ok(Literal::Bool(true))));
+        assert!(matches!(eval("(7 as f64) as i64"), Ok(Literal::Int(7))));
+        assert!(matches!(eval("\"a\" == \"a\""), Ok(Literal::Bool(true))));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(eval("a + 1"), Err(ErrorKind::E514)));
+        assert!(matches!(eval("f()"), Err(ErrorKind::E514)));
+        assert!(matches!(eval("1 / 0"), Err(ErrorKind::E515)));
+        assert!(matches!(
+            eval("9223372036854775807 + 1"),
+            Err(ErrorKind::E602)
+        ));
+        assert!(matches!(eval("1 + 1.0"), Err(ErrorKind::E500 { .. })));
+        assert!(matches!(eval("true + true"), Err(ErrorKind::E501 { .. })));
+        assert!(matches!(eval("true as f64"), Err(ErrorKind::E511 { .. })));
+    }
+}
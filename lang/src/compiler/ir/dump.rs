@@ -0,0 +1,288 @@
+use crate::compiler::ir::{Builtin, Constant, Expr, Function, IExpr, Module, Type};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+
+/// Dump the functions of a module in a three-address form with numbered
+/// temporaries and basic blocks, showing how expressions lower.
+/// Only meant for inspecting the compiler; the JIT translates the tree directly.
+/// Variables are shown as `$name.index`, temporaries as `%n`
+/// and blocks as `.Ln`, which receive the value of an `if` as parameter.
+pub fn module_to_ir(module: &Module) -> String {
+    let mut out = String::new();
+    for func in &module.funcs {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        Dumper::new(func, &mut out).function();
+    }
+    out
+}
+
+struct Dumper<'d> {
+    func: &'d Function,
+    out: &'d mut String,
+    temps: usize,
+    labels: usize,
+    /// Condition and exit label of the enclosing loops, innermost last.
+    loops: Vec<(String, String)>,
+}
+
+impl<'d> Dumper<'d> {
+    fn function(&mut self) {
+        let params = self
+            .func
+            .params
+            .iter()
+            .map(|p| format!("${}.{}: {}", p.name, p.index, type_name(&p.ty)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let ret = match self.func.ret_type {
+            Type::Void => String::new(),
+            ref ty => format!(" -> {}", type_name(ty)),
+        };
+
+        if self.func.ast.body.is_none() {
+            writeln!(self.out, "extern fun {}({}){}", self.func.name, params, ret).unwrap();
+            return;
+        }
+        writeln!(self.out, "fun {}({}){} {{", self.func.name, params, ret).unwrap();
+        let func = self.func;
+        let body = func.body.borrow();
+        match self.expr(&body) {
+            Some(value) if self.func.ret_type != Type::Void => {
+                self.ins(&format!("return {}", value))
+            }
+            _ => self.ins("return"),
+        }
+        self.out.push_str("}\n");
+    }
+
+    /// Lower the expression, returning the operand holding its value,
+    /// or None if it does not have one.
+    fn expr(&mut self, expr: &Expr) -> Option<String> {
+        let value = match &*expr.inner {
+            IExpr::Poison => "poison".to_string(),
+            IExpr::Constant(constant) => Self::constant(constant),
+            IExpr::Variable { index, .. } => self.variable(*index),
+
+            IExpr::Binary { left, op, right } => {
+                let left = self.operand(left);
+                let right = self.operand(right);
+                return self.result(expr, format!("{} {} {}", left, op.lex, right));
+            }
+
+            IExpr::Block(exprs) => {
+                let mut last = None;
+                for expr in exprs {
+                    last = self.expr(expr);
+                }
+                return last;
+            }
+
+            IExpr::If {
+                cond,
+                then,
+                els,
+                phi,
+            } => {
+                let cond = self.operand(cond);
+                let (els_label, end_label) = (self.label(), self.label());
+                self.ins(&format!("brz {}, {}", cond, els_label));
+
+                let then = self.expr(then);
+                self.jump(&end_label, if *phi { then } else { None });
+                self.block(&els_label, "");
+                let els = self.expr(els);
+                self.jump(&end_label, if *phi { els } else { None });
+
+                if *phi {
+                    let result = self.temp();
+                    self.block(&end_label, &format!("({})", result));
+                    return Some(result);
+                }
+                self.block(&end_label, "");
+                return None;
+            }
+
+            IExpr::While { cond, body } => {
+                let (head_label, end_label) = (self.label(), self.label());
+                self.jump(&head_label, None);
+                self.block(&head_label, "");
+                let cond = self.operand(cond);
+                self.ins(&format!("brz {}, {}", cond, end_label));
+                self.loops.push((head_label.clone(), end_label.clone()));
+                self.expr(body);
+                self.loops.pop();
+                self.jump(&head_label, None);
+                self.block(&end_label, "");
+                return None;
+            }
+
+            IExpr::Break { depth } => {
+                let (_, exit) = self.loops[self.loops.len() - 1 - depth].clone();
+                self.jump(&exit, None);
+                return None;
+            }
+
+            IExpr::Continue { depth } => {
+                let (head, _) = self.loops[self.loops.len() - 1 - depth].clone();
+                self.jump(&head, None);
+                return None;
+            }
+
+            IExpr::Assign { store, value } => {
+                let value = self.operand(value);
+                let store = self.operand(store);
+                self.ins(&format!("{} = {}", store, value));
+                return None;
+            }
+
+            IExpr::Call { callee, args, .. } => {
+                let callee = self.operand(callee);
+                let args = self.operands(args);
+                return self.result(expr, format!("call {}({})", callee, args));
+            }
+
+            IExpr::Assert { cond, .. } => {
+                let cond = self.operand(cond);
+                self.ins(&format!("assert {}", cond));
+                return None;
+            }
+
+            IExpr::Panic { msg, .. } => {
+                self.ins(&format!("panic {:?}", msg.as_str()));
+                return None;
+            }
+
+            IExpr::Cast { value } => {
+                let value = self.operand(value);
+                let ty = type_name(&expr.typ());
+                return self.result(expr, format!("{} as {}", value, ty));
+            }
+
+            IExpr::Builtin { builtin, args, .. } => {
+                let args = self.operands(args);
+                let name = builtin_name(*builtin);
+                return self.result(expr, format!("builtin {}({})", name, args));
+            }
+
+            IExpr::StructLit { fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(index, value)| format!("{}: {}", index, self.operand(value)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let ty = type_name(&expr.typ());
+                return self.result(expr, format!("{} {{ {} }}", ty, fields));
+            }
+        };
+        Some(value)
+    }
+
+    /// Lower an expression whose value is used by another one.
+    fn operand(&mut self, expr: &Expr) -> String {
+        self.expr(expr).unwrap_or_else(|| "void".to_string())
+    }
+
+    fn operands(&mut self, exprs: &[Expr]) -> String {
+        exprs
+            .iter()
+            .map(|e| self.operand(e))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Emit an instruction computing the value of `expr`,
+    /// assigning it to a new temporary unless it is void.
+    fn result(&mut self, expr: &Expr, ins: String) -> Option<String> {
+        match expr.typ() {
+            Type::Void => {
+                self.ins(&ins);
+                None
+            }
+            ty => {
+                let temp = self.temp();
+                self.ins(&format!("{}: {} = {}", temp, type_name(&ty), ins));
+                Some(temp)
+            }
+        }
+    }
+
+    fn constant(constant: &Constant) -> String {
+        match constant {
+            Constant::Bool(b) => b.to_string(),
+            Constant::Int(int) => int.to_string(),
+            Constant::Float(float) => format!("{:?}", float),
+            Constant::String(string) => format!("{:?}", string.as_str()),
+            Constant::Function(func) => format!("@{}", func.resolve().name),
+            Constant::Class(class) => format!("@{}", class.resolve().name),
+        }
+    }
+
+    fn variable(&self, index: usize) -> String {
+        let params = &self.func.params;
+        let var = match params.get(index) {
+            Some(param) => param,
+            None => &self.func.locals[index - params.len()],
+        };
+        format!("${}.{}", var.name, var.index)
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("%{}", self.temps - 1)
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels - 1)
+    }
+
+    fn jump(&mut self, label: &str, value: Option<String>) {
+        match value {
+            Some(value) => self.ins(&format!("jump {}({})", label, value)),
+            None => self.ins(&format!("jump {}", label)),
+        }
+    }
+
+    fn block(&mut self, label: &str, params: &str) {
+        writeln!(self.out, "{}{}:", label, params).unwrap();
+    }
+
+    fn ins(&mut self, ins: &str) {
+        writeln!(self.out, "    {}", ins).unwrap();
+    }
+
+    fn new(func: &'d Function, out: &'d mut String) -> Self {
+        Self {
+            func,
+            out,
+            temps: 0,
+            labels: 0,
+            loops: Vec::new(),
+        }
+    }
+}
+
+/// Returns the name of the type as written in source.
+pub fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Void => "void".to_string(),
+        Type::Poison => "poison".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::I64 => "i64".to_string(),
+        Type::F64 => "f64".to_string(),
+        Type::String => "str".to_string(),
+        Type::Handle => "handle".to_string(),
+        Type::Function(func) => format!("fun {}", func.resolve().name),
+        Type::Class(class) => class.resolve().name.to_string(),
+    }
+}
+
+fn builtin_name(builtin: Builtin) -> String {
+    format!("{:?}", builtin).to_ascii_lowercase()
+}
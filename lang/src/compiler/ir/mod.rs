@@ -1,6 +1,6 @@
 use crate::{
     compiler::{mutrc_new, MutRc},
-    error::{Error, ErrorKind::E201, Res},
+    error::{Error, ErrorKind::E201, Errors, Position, Res},
     lexer::Token,
     parser::{ast, ast::Literal},
     smol_str::SmolStr,
@@ -12,27 +12,48 @@ use core::{
     fmt::Display,
 };
 use cranelift_module::FuncId;
-use hashbrown::HashSet;
+use hashbrown::HashMap;
 use indexmap::map::IndexMap;
 use smallvec::{
     alloc::{fmt::Formatter, vec::Vec},
     SmallVec,
 };
 
+mod dump;
+mod symbols;
+
+pub use dump::{module_to_ir, type_name};
+pub use symbols::{symbols, SymbolInfo, SymbolKind};
+
 #[derive(Debug)]
 pub struct Module {
     pub funcs: Vec<Function>,
     pub classes: Vec<Class>,
-    pub reserved_names: HashSet<SmolStr>,
+    /// Functions with type parameters, instantiated into `funcs` when used.
+    pub generic_funcs: Vec<ast::Function>,
+    /// Classes with type parameters, instantiated into `classes` when used.
+    pub generic_classes: Vec<ast::Class>,
+    /// Names declared at module level, with the position of their declaration.
+    pub reserved_names: HashMap<SmolStr, Position>,
     pub ast: ast::Module,
+    /// Warnings found while compiling the module.
+    pub warnings: Errors,
 }
 
 impl Module {
-    pub fn try_reserve_name(&mut self, name: &SmolStr, pos: usize) -> Res<()> {
-        if !self.reserved_names.insert(name.clone()) {
-            Err(Error::new(pos, E201(name.clone())))
-        } else {
-            Ok(())
+    pub fn try_reserve_name(&mut self, name: &SmolStr, pos: Position) -> Res<()> {
+        match self.reserved_names.get(name) {
+            Some(&first) => Err(Error::new(
+                pos,
+                E201 {
+                    name: name.clone(),
+                    first,
+                },
+            )),
+            None => {
+                self.reserved_names.insert(name.clone(), pos);
+                Ok(())
+            }
         }
     }
 
@@ -40,8 +61,11 @@ impl Module {
         mutrc_new(Self {
             funcs: Vec::with_capacity(ast.functions.len()),
             classes: Vec::with_capacity(ast.classes.len()),
-            reserved_names: HashSet::with_capacity(ast.functions.len()),
+            generic_funcs: Vec::new(),
+            generic_classes: Vec::new(),
+            reserved_names: HashMap::with_capacity(ast.functions.len()),
             ast,
+            warnings: Vec::new(),
         })
     }
 }
@@ -51,8 +75,13 @@ pub struct Class {
     pub name: SmolStr,
     pub content: RefCell<IndexMap<SmolStr, ClassContent>>,
     pub ast: RefCell<ast::Class>,
+    /// The types substituted for type parameters, if this is a generic instance.
+    pub type_args: TypeArgs,
 }
 
+/// Type parameter names with the type they stand for.
+pub type TypeArgs = Vec<(SmolStr, Type)>;
+
 #[derive(Debug)]
 pub enum ClassContent {
     Member(VarStore),
@@ -69,6 +98,8 @@ pub struct Function {
     pub body: RefCell<Expr>,
     pub ir: RefCell<Option<FuncId>>,
     pub ast: ast::Function,
+    /// The types substituted for type parameters, if this is a generic instance.
+    pub type_args: TypeArgs,
 }
 
 impl Function {
@@ -76,7 +107,8 @@ impl Function {
         let local = VarStore {
             ty,
             name,
-            index: self.locals.len(),
+            // Parameters come first
+            index: self.params.len() + self.locals.len(),
             mutable,
         };
         unsafe {
@@ -160,6 +192,10 @@ pub enum Type {
     Bool,
     I64,
     F64,
+    /// An immutable string, made up of a pointer and its length in bytes.
+    String,
+    /// An opaque reference to a resource of the host, see `Handles`.
+    Handle,
 
     Function(FuncRef),
     Class(ClassRef),
@@ -182,6 +218,15 @@ impl Type {
         *self != Type::Void
     }
 
+    /// Allowed are casts between numbers, from bool to int and to the same type.
+    pub fn can_cast_to(&self, to: &Type) -> bool {
+        match (self, to) {
+            (Type::Poison, _) | (_, Type::Poison) => true,
+            (Type::I64, Type::F64) | (Type::F64, Type::I64) | (Type::Bool, Type::I64) => true,
+            (from, to) => from == to,
+        }
+    }
+
     pub fn into_fn(self) -> FuncRef {
         match self {
             Self::Function(r) => r,
@@ -236,6 +281,14 @@ impl Expr {
         Self::new(IExpr::While { cond, body })
     }
 
+    pub fn break_(depth: usize) -> Expr {
+        Self::new(IExpr::Break { depth })
+    }
+
+    pub fn continue_(depth: usize) -> Expr {
+        Self::new(IExpr::Continue { depth })
+    }
+
     pub fn local(variable: &VarStore) -> Expr {
         Self::new(IExpr::Variable {
             index: variable.index,
@@ -251,8 +304,28 @@ impl Expr {
         Self::assign(Self::local(variable), value)
     }
 
-    pub fn call(callee: Expr, args: SmallVec<[Expr; 4]>, ret_type: Type) -> Expr {
-        Self::with_typ(IExpr::Call { callee, args }, ret_type)
+    pub fn call(callee: Expr, args: SmallVec<[Expr; 4]>, ret_type: Type, pos: Position) -> Expr {
+        Self::with_typ(IExpr::Call { callee, args, pos }, ret_type)
+    }
+
+    pub fn assert(cond: Expr, pos: Position) -> Expr {
+        Self::new(IExpr::Assert { cond, pos })
+    }
+
+    pub fn panic(msg: SmolStr, pos: Position) -> Expr {
+        Self::new(IExpr::Panic { msg, pos })
+    }
+
+    pub fn cast(value: Expr, to: Type) -> Expr {
+        Self::with_typ(IExpr::Cast { value }, to)
+    }
+
+    pub fn struct_lit(fields: Vec<(usize, Expr)>, class: ClassRef) -> Expr {
+        Self::with_typ(IExpr::StructLit { fields }, Type::Class(class))
+    }
+
+    pub fn builtin(builtin: Builtin, args: SmallVec<[Expr; 4]>, pos: Position) -> Expr {
+        Self::with_typ(IExpr::Builtin { builtin, args, pos }, builtin.ret_type())
     }
 
     pub fn typ(&self) -> Type {
@@ -283,7 +356,7 @@ impl Expr {
             IExpr::Constant(Constant::Bool(_)) => Type::Bool,
             IExpr::Constant(Constant::Int(_)) => Type::I64,
             IExpr::Constant(Constant::Float(_)) => Type::F64,
-            IExpr::Constant(Constant::String(_)) => unimplemented!(),
+            IExpr::Constant(Constant::String(_)) => Type::String,
             IExpr::Constant(Constant::Function(f)) => Type::Function(f.clone()),
             IExpr::Constant(Constant::Class(c)) => Type::Class(c.clone()),
 
@@ -292,13 +365,17 @@ impl Expr {
             IExpr::If { phi, .. } if !phi => Type::Void,
             IExpr::If { then, .. } => then.typ(),
 
-            IExpr::While { .. } => Type::Void,
+            IExpr::While { .. } | IExpr::Break { .. } | IExpr::Continue { .. } => Type::Void,
 
             IExpr::Variable { typ, .. } => typ.clone(),
 
             IExpr::Assign { value, .. } => value.typ(),
 
             IExpr::Call { .. } => panic!(),
+
+            IExpr::Assert { .. } | IExpr::Panic { .. } => Type::Void,
+
+            IExpr::Cast { .. } | IExpr::Builtin { .. } | IExpr::StructLit { .. } => panic!(),
         }
     }
 
@@ -343,6 +420,17 @@ pub enum IExpr {
         body: Expr,
     },
 
+    /// Leave a loop; `depth` is the number of enclosing loops
+    /// to skip, 0 being the innermost one.
+    Break {
+        depth: usize,
+    },
+
+    /// Jump to the condition of a loop, with `depth` like `Break`.
+    Continue {
+        depth: usize,
+    },
+
     Variable {
         index: usize,
         typ: Type,
@@ -356,7 +444,127 @@ pub enum IExpr {
     Call {
         callee: Expr,
         args: SmallVec<[Expr; 4]>,
+        /// Where the call is, for backtraces of runtime errors.
+        pos: Position,
     },
+
+    Assert {
+        cond: Expr,
+        pos: Position,
+    },
+
+    Panic {
+        msg: SmolStr,
+        pos: Position,
+    },
+
+    /// The target type is the type of the expression.
+    Cast {
+        value: Expr,
+    },
+
+    Builtin {
+        builtin: Builtin,
+        args: SmallVec<[Expr; 4]>,
+        pos: Position,
+    },
+
+    /// The values of all members with their index, in source order.
+    /// The class is the type of the expression.
+    StructLit {
+        fields: Vec<(usize, Expr)>,
+    },
+}
+
+/// Functions implemented by the compiler itself, making up the prelude.
+/// They are available unless shadowed by a function of the same name
+/// or disabled with `Options::no_prelude`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Builtin {
+    /// `len(s: str) -> i64`, the length of a string in bytes.
+    Len,
+    /// `char_at(s: str, i: i64) -> i64`, the value of the byte at the given byte index.
+    /// Characters outside of ASCII take several bytes in UTF-8, which are returned
+    /// one at a time; indices inside them give continuation bytes, not characters.
+    CharAt,
+    /// `substring(s: str, start: i64, end: i64) -> str`, the bytes from start to end (exclusive).
+    Substring,
+    /// `print(s: str)`, output the string; see `Options::output`.
+    Print,
+    /// `println(s: str)`, output the string followed by a newline.
+    Println,
+    /// `str(i: i64) -> str`, the integer in decimal.
+    Str,
+    /// `abs(i: i64) -> i64`, the absolute value, overflowing for the minimum value.
+    Abs,
+    /// `min(a: i64, b: i64) -> i64`, the smaller value.
+    Min,
+    /// `max(a: i64, b: i64) -> i64`, the larger value.
+    Max,
+    /// `sqrt(f: f64) -> f64`, the square root, NaN for negative values.
+    Sqrt,
+    /// `seed(n: i64)`, restart the random numbers of `rand` and `rand_int` from the seed.
+    Seed,
+    /// `rand() -> f64`, a pseudo-random number in [0, 1), the same for every run with the same seed.
+    Rand,
+    /// `rand_int(lo: i64, hi: i64) -> i64`, a pseudo-random integer in [lo, hi).
+    RandInt,
+    /// `assert_eq(a: T, b: T)`, fails like `assert` unless the numbers are equal.
+    /// Generic over the number type, so it is lowered to `assert(a == b)` by the compiler.
+    AssertEq,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        match name {
+            "len" => Some(Builtin::Len),
+            "char_at" => Some(Builtin::CharAt),
+            "substring" => Some(Builtin::Substring),
+            "print" => Some(Builtin::Print),
+            "println" => Some(Builtin::Println),
+            "str" => Some(Builtin::Str),
+            "abs" => Some(Builtin::Abs),
+            "min" => Some(Builtin::Min),
+            "max" => Some(Builtin::Max),
+            "sqrt" => Some(Builtin::Sqrt),
+            "seed" => Some(Builtin::Seed),
+            "rand" => Some(Builtin::Rand),
+            "rand_int" => Some(Builtin::RandInt),
+            "assert_eq" => Some(Builtin::AssertEq),
+            _ => None,
+        }
+    }
+
+    pub fn params(self) -> SmallVec<[Type; 4]> {
+        match self {
+            Builtin::Len | Builtin::Print | Builtin::Println => {
+                SmallVec::from_slice(&[Type::String])
+            }
+            Builtin::CharAt => SmallVec::from_slice(&[Type::String, Type::I64]),
+            Builtin::Substring => SmallVec::from_slice(&[Type::String, Type::I64, Type::I64]),
+            Builtin::Str | Builtin::Abs | Builtin::Seed => SmallVec::from_slice(&[Type::I64]),
+            Builtin::Min | Builtin::Max | Builtin::RandInt => {
+                SmallVec::from_slice(&[Type::I64, Type::I64])
+            }
+            Builtin::Sqrt => SmallVec::from_slice(&[Type::F64]),
+            Builtin::Rand => SmallVec::new(),
+            Builtin::AssertEq => unreachable!("assert_eq is lowered by the compiler"),
+        }
+    }
+
+    pub fn ret_type(self) -> Type {
+        match self {
+            Builtin::Len
+            | Builtin::CharAt
+            | Builtin::Abs
+            | Builtin::Min
+            | Builtin::Max
+            | Builtin::RandInt => Type::I64,
+            Builtin::Substring | Builtin::Str => Type::String,
+            Builtin::Print | Builtin::Println | Builtin::Seed | Builtin::AssertEq => Type::Void,
+            Builtin::Sqrt | Builtin::Rand => Type::F64,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
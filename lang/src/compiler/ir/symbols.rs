@@ -0,0 +1,147 @@
+use crate::{
+    compiler::ir::{type_name, ClassContent, Function, Module},
+    lexer::Token,
+    parser::{ast, SCRIPT_FUNCTION},
+    smol_str::SmolStr,
+};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use hashbrown::HashSet;
+
+/// A symbol defined by a module, for tooling like completion in a REPL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolInfo {
+    /// The name of the symbol. Class contents are qualified
+    /// with the name of their class, like `Point.x`.
+    pub name: SmolStr,
+    pub kind: SymbolKind,
+    /// The type of the symbol in source syntax; the signature for functions.
+    pub ty: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Class,
+    Member,
+    Method,
+    /// A function inside a class, called without an instance.
+    ClassFunction,
+}
+
+/// List everything the given module defines, in declaration order.
+/// Functions come first, then each class followed by its contents.
+/// Generic functions and classes are listed once, not per instance.
+pub fn symbols(module: &Module) -> Vec<SymbolInfo> {
+    let mut symbols = Vec::new();
+
+    // Methods live among the functions of the module, skip them here
+    let mut in_class = HashSet::new();
+    for class in &module.classes {
+        for content in class.content.borrow().values() {
+            match content {
+                ClassContent::Method(func) | ClassContent::Function(func) => {
+                    in_class.insert(func.index);
+                }
+                ClassContent::Member(_) => (),
+            }
+        }
+    }
+    for (index, func) in module.funcs.iter().enumerate() {
+        let script = func.name == SCRIPT_FUNCTION;
+        if !in_class.contains(&index) && func.type_args.is_empty() && !script {
+            symbols.push(symbol(
+                func.name.clone(),
+                SymbolKind::Function,
+                signature(func),
+            ));
+        }
+    }
+    for func in &module.generic_funcs {
+        let name = func.name.lex.clone();
+        symbols.push(symbol(name, SymbolKind::Function, generic_signature(func)));
+    }
+
+    for class in module.classes.iter().filter(|c| c.type_args.is_empty()) {
+        symbols.push(symbol(
+            class.name.clone(),
+            SymbolKind::Class,
+            class.name.to_string(),
+        ));
+        for (name, content) in class.content.borrow().iter() {
+            let name = SmolStr::new(format!("{}.{}", class.name, name));
+            symbols.push(match content {
+                ClassContent::Member(store) => {
+                    symbol(name, SymbolKind::Member, type_name(&store.ty))
+                }
+                ClassContent::Method(func) => {
+                    symbol(name, SymbolKind::Method, signature(&func.resolve()))
+                }
+                ClassContent::Function(func) => {
+                    symbol(name, SymbolKind::ClassFunction, signature(&func.resolve()))
+                }
+            });
+        }
+    }
+    for class in &module.generic_classes {
+        let name = format!("{}<{}>", class.name.lex, params_list(&class.type_params));
+        symbols.push(symbol(class.name.lex.clone(), SymbolKind::Class, name));
+    }
+
+    symbols
+}
+
+fn symbol(name: SmolStr, kind: SymbolKind, ty: String) -> SymbolInfo {
+    SymbolInfo { name, kind, ty }
+}
+
+fn signature(func: &Function) -> String {
+    let params = func
+        .params
+        .iter()
+        .map(|p| type_name(&p.ty))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("fun({}) -> {}", params, type_name(&func.ret_type))
+}
+
+/// Signature of a function with type parameters, which only exists as AST.
+fn generic_signature(func: &ast::Function) -> String {
+    let params = func
+        .params
+        .iter()
+        .map(|p| ast_type(&p.ty))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let ret = func
+        .ret_type
+        .as_ref()
+        .map(ast_type)
+        .unwrap_or_else(|| String::from("void"));
+    format!(
+        "fun<{}>({}) -> {}",
+        params_list(&func.type_params),
+        params,
+        ret
+    )
+}
+
+fn ast_type(ty: &ast::Type) -> String {
+    if ty.args.is_empty() {
+        ty.name.lex.to_string()
+    } else {
+        let args = ty.args.iter().map(ast_type).collect::<Vec<_>>().join(", ");
+        format!("{}<{}>", ty.name.lex, args)
+    }
+}
+
+fn params_list(params: &[Token]) -> String {
+    params
+        .iter()
+        .map(|p| p.lex.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,111 @@
+use crate::{
+    compiler::{
+        ir::{ClassContent, Function, Module, Type},
+        MutRc,
+    },
+    error::{
+        Error,
+        ErrorKind::{E204, E205, E206},
+        Errors,
+    },
+    smol_str::SmolStr,
+    vm::SymbolTable,
+};
+use alloc::{vec, vec::Vec};
+use hashbrown::{HashMap, HashSet};
+
+/// Check that the modules of a program can be linked together:
+/// every function must be defined only once, and every extern function
+/// must be defined by a module or the host, matching its declaration.
+/// Methods and class functions are excluded, they belong to their class.
+/// Errors are grouped by module, like errors of the other passes.
+pub fn link(modules: &[MutRc<Module>], symbols: SymbolTable) -> Result<(), Vec<Errors>> {
+    let modules = modules.iter().map(|m| m.borrow()).collect::<Vec<_>>();
+    let mut errors = vec![Vec::new(); modules.len()];
+
+    // Generic instances are excluded, each module creates its own.
+    // They are named after their template, which has to be unique instead.
+    let mut definitions: HashMap<&SmolStr, &Function> = HashMap::new();
+    let mut templates: HashSet<&SmolStr> = HashSet::new();
+    for (module, errors) in modules.iter().zip(errors.iter_mut()) {
+        let members = class_functions(module);
+        let defined = module.funcs.iter().enumerate().filter(|(i, f)| {
+            f.ast.body.is_some() && f.type_args.is_empty() && !members.contains(i)
+        });
+        for (_, func) in defined {
+            if definitions.insert(&func.name, func).is_some() {
+                errors.push(Error::new(func.ast.name.start, E204(func.name.clone())));
+            }
+        }
+        for func in &module.generic_funcs {
+            if !templates.insert(&func.name.lex) {
+                errors.push(Error::new(func.name.start, E204(func.name.lex.clone())));
+            }
+        }
+    }
+    for (module, errors) in modules.iter().zip(errors.iter_mut()) {
+        for func in &module.generic_funcs {
+            if definitions.contains_key(&func.name.lex) {
+                errors.push(Error::new(func.name.start, E204(func.name.lex.clone())));
+            }
+        }
+    }
+
+    for (module, errors) in modules.iter().zip(errors.iter_mut()) {
+        for func in module.funcs.iter().filter(|f| f.ast.body.is_none()) {
+            let kind = match definitions.get(&func.name) {
+                Some(def) if !same_signature(func, def) => E206(func.name.clone()),
+                Some(_) => continue,
+                None if symbols.iter().any(|(name, _)| *name == func.name.as_str()) => continue,
+                None => E205(func.name.clone()),
+            };
+            errors.push(Error::new(func.ast.name.start, kind));
+        }
+    }
+
+    let errors = errors
+        .into_iter()
+        .filter(|e| !e.is_empty())
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Indices of the functions of a module declared inside of a class.
+fn class_functions(module: &Module) -> HashSet<usize> {
+    module
+        .classes
+        .iter()
+        .flat_map(|cls| {
+            cls.content
+                .borrow()
+                .values()
+                .filter_map(|content| match content {
+                    ClassContent::Method(func) | ClassContent::Function(func) => Some(func.index),
+                    ClassContent::Member(_) => None,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn same_signature(a: &Function, b: &Function) -> bool {
+    a.params.len() == b.params.len()
+        && a.params
+            .iter()
+            .zip(b.params.iter())
+            .all(|(a, b)| same_type(&a.ty, &b.ty))
+        && same_type(&a.ret_type, &b.ret_type)
+}
+
+/// Classes are declared by each module using them,
+/// making them the same if they have the same name.
+fn same_type(a: &Type, b: &Type) -> bool {
+    match (a, b) {
+        (Type::Class(a), Type::Class(b)) => a.resolve().name == b.resolve().name,
+        _ => a == b,
+    }
+}
@@ -6,7 +6,9 @@ use crate::{
 use alloc::{rc::Rc, vec::Vec};
 use core::cell::RefCell;
 
+pub mod consts;
 pub mod ir;
+pub mod linker;
 pub mod module;
 
 pub type MutRc<T> = Rc<RefCell<T>>;
@@ -54,4 +56,12 @@ impl Compiler {
             modules,
         }
     }
+
+    /// Set if the prelude of builtin functions is available to all modules.
+    pub fn with_prelude(mut self, prelude: bool) -> Self {
+        for compiler in self.compilers.iter_mut() {
+            compiler.prelude = prelude;
+        }
+        self
+    }
 }
@@ -1,23 +1,37 @@
 use crate::{
     compiler::{
-        ir::{Constant, Expr, FuncRef, Function, Type, VarStore},
+        ir::{Builtin, ClassContent, Constant, Expr, FuncRef, Function, Type, VarStore},
         module::ModuleCompiler,
     },
-    error::{ErrorKind, ErrorKind::*},
-    lexer::TKind,
-    parser::{ast, ast::EExpr},
+    error::{Error, ErrorKind, ErrorKind::*, Errors, Position},
+    lexer::{TKind, Token},
+    parser::{
+        ast,
+        ast::{EExpr, Literal},
+    },
     smol_str::SmolStr,
 };
 use alloc::{string::ToString, vec, vec::Vec};
 use hashbrown::HashMap;
 use smallvec::SmallVec;
 
-type Environment<'e> = HashMap<SmolStr, &'e VarStore>;
+type Environment<'e> = HashMap<SmolStr, Binding<'e>>;
+
+/// A variable in scope, tracked for lints.
+struct Binding<'e> {
+    var: &'e VarStore,
+    start: Position,
+    used: bool,
+}
 
 pub struct ExprCompiler<'e> {
     function: &'e Function,
     compiler: &'e ModuleCompiler,
     environments: Vec<Environment<'e>>,
+    /// Labels of the loops enclosing the current expression, innermost last.
+    loops: Vec<Option<SmolStr>>,
+    pub errors: Errors,
+    pub warnings: Errors,
 }
 
 impl<'e> ExprCompiler<'e> {
@@ -26,7 +40,21 @@ impl<'e> ExprCompiler<'e> {
             EExpr::Literal(lit) => Expr::constant(Constant::from_literal(lit)),
 
             EExpr::Binary { left, op, right } => {
-                let left = self.expr(left);
+                let left = match &*left.ty {
+                    // Assigning to a variable does not count as reading it
+                    EExpr::Identifier(ident) if op.kind == TKind::Equal => {
+                        match self.find_local(&ident.lex, false) {
+                            Some(local) => {
+                                if !local.mutable {
+                                    self.err(ident.start, E512(ident.lex.clone()));
+                                }
+                                Expr::local(local)
+                            }
+                            None => self.expr(left),
+                        }
+                    }
+                    _ => self.expr(left),
+                };
                 let right = self.expr(right);
                 let lty = left.typ();
                 let rty = right.typ();
@@ -81,17 +109,29 @@ impl<'e> ExprCompiler<'e> {
                 Expr::if_(condition, then, els)
             }
 
-            EExpr::While { cond, body } => {
+            EExpr::While { label, cond, body } => {
                 let condition = self.expr(cond);
                 if condition.typ() != Type::Bool {
                     self.err(cond.start, E502);
                 }
+                self.loops.push(label.as_ref().map(|l| l.lex.clone()));
                 let body = self.expr(body);
+                self.loops.pop();
                 Expr::while_(condition, body)
             }
 
+            EExpr::Break(label) => match self.loop_depth(label, expr.start) {
+                Some(depth) => Expr::break_(depth),
+                None => Expr::poison(),
+            },
+
+            EExpr::Continue(label) => match self.loop_depth(label, expr.start) {
+                Some(depth) => Expr::continue_(depth),
+                None => Expr::poison(),
+            },
+
             EExpr::Identifier(ident) => {
-                let local = self.find_local(&ident.lex);
+                let local = self.find_local(&ident.lex, true);
                 if let Some(local) = local {
                     return Expr::local(local);
                 }
@@ -120,13 +160,33 @@ impl<'e> ExprCompiler<'e> {
                     self.err(name.start, E504 { ty: ty.to_string() })
                 }
 
+                if self.find_local(&name.lex, false).is_some() {
+                    self.warn(name.start, W001(name.lex.clone()));
+                }
+
                 let local = self.function.add_local(name.lex.clone(), ty, !*final_);
-                self.add_to_scope(local);
+                self.add_to_scope(local, name.start);
                 Expr::assign_local(local, value)
             }
 
             EExpr::Call { callee, args } => {
                 let start = callee.start;
+                if let Some(builtin) = self.find_builtin(callee) {
+                    if builtin == Builtin::AssertEq {
+                        return self.assert_eq(args, start);
+                    }
+                    let ast_args = args;
+                    let args = args
+                        .iter()
+                        .map(|a| self.expr(a))
+                        .collect::<SmallVec<[Expr; 4]>>();
+                    self.check_args(&args, ast_args, builtin.params(), start);
+                    return Expr::builtin(builtin, args, start);
+                }
+                if let Some(template) = self.find_generic_fn(callee) {
+                    return self.generic_call(template, args, start);
+                }
+
                 let callee = self.expr(callee);
                 let fn_ref = if let Type::Function(fn_ref) = callee.typ() {
                     fn_ref
@@ -139,37 +199,64 @@ impl<'e> ExprCompiler<'e> {
                     );
                     return Expr::poison();
                 };
-                let func = fn_ref.resolve();
+                // Not kept borrowed, arguments might instantiate generic functions
+                let (params, ret_type) = {
+                    let func = fn_ref.resolve();
+                    let params = func.params.iter().map(|p| p.ty.clone()).collect();
+                    (params, func.ret_type.clone())
+                };
 
+                let ast_args = args;
                 let args = args
                     .iter()
                     .map(|a| self.expr(a))
                     .collect::<SmallVec<[Expr; 4]>>();
-                if args.len() != func.params.len() {
+                self.check_args(&args, ast_args, params, start);
+
+                Expr::call(callee, args, ret_type, start)
+            }
+
+            EExpr::Assert(cond) => {
+                let condition = self.expr(cond);
+                if condition.typ() != Type::Bool {
+                    self.err(cond.start, E502);
+                }
+                Expr::assert(condition, expr.start)
+            }
+
+            EExpr::Panic(msg) => match &*msg.ty {
+                EExpr::Literal(Literal::String(msg)) => Expr::panic(msg.clone(), expr.start),
+                _ => {
+                    self.err(msg.start, E509);
+                    Expr::poison()
+                }
+            },
+
+            EExpr::Cast { expr: value, ty } => {
+                let value = self.expr(value);
+                let to = match self.compiler.resolve_ty_in(ty, &self.function.type_args) {
+                    Ok(to) => to,
+                    Err(err) => {
+                        self.errors.push(err);
+                        return Expr::poison();
+                    }
+                };
+
+                let from = value.typ();
+                if !from.can_cast_to(&to) {
                     self.err(
-                        start,
-                        E507 {
-                            expected: func.params.len(),
-                            found: args.len(),
+                        expr.start,
+                        E511 {
+                            from: from.to_string(),
+                            to: to.to_string(),
                         },
                     );
                 }
-                for (i, (arg, param)) in args.iter().zip(func.params.iter()).enumerate() {
-                    if arg.typ() != param.ty {
-                        self.err(
-                            start,
-                            E508 {
-                                expected: param.ty.to_string(),
-                                found: arg.typ().to_string(),
-                                pos: i,
-                            },
-                        );
-                    }
-                }
-
-                Expr::call(callee, args, func.ret_type.clone())
+                Expr::cast(value, to)
             }
 
+            EExpr::StructLit { ty, fields } => self.struct_lit(ty, fields),
+
             /*
             EExpr::Unary { .. } => {}
             */
@@ -177,17 +264,261 @@ impl<'e> ExprCompiler<'e> {
         }
     }
 
-    fn err(&self, _pos: usize, _err: ErrorKind) {
-        // self.compiler.errors
+    /// Returns the builtin the callee refers to, if it is not shadowed.
+    fn find_builtin(&mut self, callee: &ast::Expr) -> Option<Builtin> {
+        match &*callee.ty {
+            EExpr::Identifier(ident)
+                if self.compiler.prelude
+                    && self.find_local(&ident.lex, false).is_none()
+                    && self.find_function(&ident.lex).is_none() =>
+            {
+                Builtin::from_name(&ident.lex)
+            }
+            _ => None,
+        }
     }
 
-    fn find_local(&self, name: &str) -> Option<&VarStore> {
-        self.environments
+    /// Compile `assert_eq(a, b)` into `assert(a == b)`, with the arguments
+    /// checked like the operands of `==`.
+    fn assert_eq(&mut self, args: &[ast::Expr], start: Position) -> Expr {
+        let args = args
             .iter()
+            .map(|a| self.expr(a))
+            .collect::<SmallVec<[Expr; 4]>>();
+        if args.len() != 2 {
+            self.err(
+                start,
+                E507 {
+                    expected: 2,
+                    found: args.len(),
+                },
+            );
+            return Expr::poison();
+        }
+
+        let mut args = args.into_iter();
+        let (left, right) = (args.next().unwrap(), args.next().unwrap());
+        let (lty, rty) = (left.typ(), right.typ());
+        if lty != rty {
+            self.err(
+                start,
+                E500 {
+                    left: lty.to_string(),
+                    right: rty.to_string(),
+                },
+            );
+        } else if !lty.allow_math() {
+            self.err(
+                start,
+                E501 {
+                    op: "==".into(),
+                    ty: lty.to_string(),
+                },
+            );
+        }
+
+        let op = Token {
+            kind: TKind::EqualEqual,
+            lex: "==".into(),
+            start,
+        };
+        Expr::assert(Expr::binary(left, op, right), start)
+    }
+
+    /// Returns the generic function the callee refers to, if any.
+    fn find_generic_fn(&mut self, callee: &ast::Expr) -> Option<usize> {
+        match &*callee.ty {
+            EExpr::Identifier(ident) if self.find_local(&ident.lex, false).is_none() => {
+                self.compiler.find_generic_fn(&ident.lex)
+            }
+            _ => None,
+        }
+    }
+
+    /// Call a generic function, instantiating it with type arguments inferred from `args`.
+    fn generic_call(&mut self, template: usize, args: &[ast::Expr], start: Position) -> Expr {
+        let ast_args = args;
+        let args = args
+            .iter()
+            .map(|a| self.expr(a))
+            .collect::<SmallVec<[Expr; 4]>>();
+        if args.iter().any(|a| a.typ() == Type::Poison) {
+            return Expr::poison();
+        }
+
+        let instance = self
+            .compiler
+            .infer_type_args(template, &args, start)
+            .and_then(|type_args| self.compiler.instantiate_fn(template, type_args));
+        match instance {
+            Ok(instance) => {
+                self.check_args(&args, ast_args, instance.params, start);
+                let callee = Expr::constant(Constant::Function(instance.func));
+                Expr::call(callee, args, instance.ret_type, start)
+            }
+            Err(err) => {
+                self.errors.push(err);
+                Expr::poison()
+            }
+        }
+    }
+
+    /// Compile a class literal, which must give every member exactly once.
+    fn struct_lit(&mut self, ty: &ast::Type, fields: &[(Token, ast::Expr)]) -> Expr {
+        let class_ref = match self.compiler.resolve_ty_in(ty, &self.function.type_args) {
+            Ok(Type::Class(class_ref)) => class_ref,
+            Ok(other) => {
+                self.err(ty.name.start, E516(other.to_string()));
+                return Expr::poison();
+            }
+            Err(err) => {
+                self.errors.push(err);
+                return Expr::poison();
+            }
+        };
+
+        // Copied out, compiling the fields might need to modify the module
+        let (class_name, members) = {
+            let class = class_ref.resolve();
+            let members = class
+                .content
+                .borrow()
+                .values()
+                .filter_map(|content| match content {
+                    ClassContent::Member(member) => Some(member.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            (class.name.clone(), members)
+        };
+
+        let mut given = HashMap::with_capacity(fields.len());
+        let mut values = Vec::with_capacity(fields.len());
+        for (name, value) in fields {
+            let value = self.expr(value);
+            let member = match members.iter().find(|m| m.name == name.lex) {
+                Some(member) => member,
+                None => {
+                    self.err(
+                        name.start,
+                        E517 {
+                            class: class_name.clone(),
+                            member: name.lex.clone(),
+                        },
+                    );
+                    continue;
+                }
+            };
+            if let Some(&first) = given.get(&name.lex) {
+                self.err(
+                    name.start,
+                    E201 {
+                        name: name.lex.clone(),
+                        first,
+                    },
+                );
+                continue;
+            }
+            given.insert(name.lex.clone(), name.start);
+
+            if value.typ() != member.ty {
+                self.err(
+                    name.start,
+                    E518 {
+                        member: name.lex.clone(),
+                        expected: member.ty.to_string(),
+                        found: value.typ().to_string(),
+                    },
+                );
+            }
+            values.push((member.index, value));
+        }
+
+        for member in members.iter().filter(|m| !given.contains_key(&m.name)) {
+            self.err(
+                ty.name.start,
+                E519 {
+                    class: class_name.clone(),
+                    member: member.name.clone(),
+                },
+            );
+        }
+        Expr::struct_lit(values, class_ref)
+    }
+
+    /// Check the number and types of arguments of a call starting at `start`.
+    /// Type errors are reported at the argument, skipping those that already failed.
+    fn check_args(
+        &mut self,
+        args: &[Expr],
+        ast_args: &[ast::Expr],
+        params: SmallVec<[Type; 4]>,
+        start: Position,
+    ) {
+        if args.len() != params.len() {
+            self.err(
+                start,
+                E507 {
+                    expected: params.len(),
+                    found: args.len(),
+                },
+            );
+        }
+        for (i, (arg, param)) in args.iter().zip(params.iter()).enumerate() {
+            if arg.typ() != *param && arg.typ() != Type::Poison {
+                self.err(
+                    ast_args[i].start,
+                    E508 {
+                        expected: param.to_string(),
+                        found: arg.typ().to_string(),
+                        pos: i,
+                    },
+                );
+            }
+        }
+    }
+
+    fn err(&mut self, pos: Position, err: ErrorKind) {
+        self.errors.push(Error::new(pos, err))
+    }
+
+    fn warn(&mut self, pos: Position, warning: ErrorKind) {
+        self.warnings.push(Error::new(pos, warning))
+    }
+
+    /// Returns the number of loops to skip to get to the loop targeted by
+    /// `break` or `continue` at `start`, reporting an error if there is none.
+    fn loop_depth(&mut self, label: &Option<Token>, start: Position) -> Option<usize> {
+        match label {
+            Some(label) => {
+                let depth = self
+                    .loops
+                    .iter()
+                    .rev()
+                    .position(|l| l.as_ref() == Some(&label.lex));
+                if depth.is_none() {
+                    self.err(label.start, E520(label.lex.clone()));
+                }
+                depth
+            }
+            None if self.loops.is_empty() => {
+                self.err(start, E521);
+                None
+            }
+            None => Some(0),
+        }
+    }
+
+    /// Find a variable in scope, marking it as read if `read` is set.
+    fn find_local(&mut self, name: &str, read: bool) -> Option<&'e VarStore> {
+        let binding = self
+            .environments
+            .iter_mut()
             .rev()
-            .filter_map(|env| env.get(name))
-            .next()
-            .copied()
+            .filter_map(|env| env.get_mut(name))
+            .next()?;
+        binding.used |= read;
+        Some(binding.var)
     }
 
     fn find_function(&self, name: &str) -> Option<FuncRef> {
@@ -203,11 +534,20 @@ impl<'e> ExprCompiler<'e> {
             })
     }
 
-    fn add_to_scope(&mut self, var: &'e VarStore) {
-        self.environments
+    fn add_to_scope(&mut self, var: &'e VarStore, start: Position) {
+        let binding = Binding {
+            var,
+            start,
+            used: false,
+        };
+        let shadowed = self
+            .environments
             .last_mut()
             .unwrap()
-            .insert(var.name.clone(), var);
+            .insert(var.name.clone(), binding);
+        if let Some(shadowed) = shadowed {
+            self.lint_unused(shadowed);
+        }
     }
 
     fn begin_scope(&mut self) {
@@ -215,18 +555,40 @@ impl<'e> ExprCompiler<'e> {
     }
 
     fn end_scope(&mut self) {
-        self.environments.pop();
+        let env = self.environments.pop().unwrap();
+        for (_, binding) in env {
+            self.lint_unused(binding);
+        }
+    }
+
+    /// Warn about a variable going out of scope without being read.
+    /// Names starting with an underscore are exempt.
+    fn lint_unused(&mut self, binding: Binding) {
+        if !binding.used && !binding.var.name.starts_with('_') {
+            self.warn(binding.start, W002(binding.var.name.clone()));
+        }
     }
 
     pub fn new(compiler: &'e ModuleCompiler, function: &'e Function) -> Self {
         ExprCompiler {
             function,
             compiler,
+            // Parameters count as used, they are part of the signature
             environments: vec![function
                 .params
                 .iter()
-                .map(|p| (p.name.clone(), p))
+                .map(|p| {
+                    let binding = Binding {
+                        var: p,
+                        start: function.ast.name.start,
+                        used: true,
+                    };
+                    (p.name.clone(), binding)
+                })
                 .collect()],
+            loops: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
         }
     }
 }
@@ -0,0 +1,171 @@
+use crate::{
+    compiler::{
+        ir::{Class, ClassRef, Expr, FuncRef, Function, Type, TypeArgs},
+        module::ModuleCompiler,
+    },
+    error::{Error, ErrorKind::E510, Position, Res},
+    lexer::Token,
+    smol_str::SmolStr,
+};
+use alloc::{format, vec, vec::Vec};
+use core::cell::RefCell;
+use indexmap::IndexMap;
+use smallvec::SmallVec;
+
+/// An instance of a generic function, with the
+/// signature after substituting type parameters.
+#[derive(Clone)]
+pub struct FnInstance {
+    pub func: FuncRef,
+    pub params: SmallVec<[Type; 4]>,
+    pub ret_type: Type,
+}
+
+/// Instances of generic functions and classes created so far.
+/// Instances are created while the module is still borrowed for compilation,
+/// so they are only added to the module itself once compilation of the
+/// current functions or class is done, see `flush_instances`.
+#[derive(Default)]
+pub struct Instances {
+    funcs: Vec<(usize, Vec<Type>, FnInstance)>,
+    classes: Vec<(usize, Vec<Type>, ClassRef)>,
+    pending_funcs: Vec<Function>,
+    pending_classes: Vec<Class>,
+}
+
+impl ModuleCompiler {
+    pub fn find_generic_fn(&self, name: &str) -> Option<usize> {
+        self.module
+            .borrow()
+            .generic_funcs
+            .iter()
+            .position(|func| func.name.lex == name)
+    }
+
+    pub fn find_generic_class(&self, name: &str) -> Option<usize> {
+        self.module
+            .borrow()
+            .generic_classes
+            .iter()
+            .position(|cls| cls.name.lex == name)
+    }
+
+    /// Infer the type arguments of a call to the given generic function from its arguments.
+    /// Only parameters that are directly of a type parameter are used for inference.
+    pub fn infer_type_args(&self, template: usize, args: &[Expr], pos: Position) -> Res<Vec<Type>> {
+        let module = self.module.borrow();
+        let template = &module.generic_funcs[template];
+
+        let mut inferred = vec![None; template.type_params.len()];
+        for (param, arg) in template.params.iter().zip(args.iter()) {
+            let index = template
+                .type_params
+                .iter()
+                .position(|tp| tp.lex == param.ty.name.lex);
+            match index {
+                Some(index) if param.ty.args.is_empty() && inferred[index].is_none() => {
+                    inferred[index] = Some(arg.typ())
+                }
+                _ => (),
+            }
+        }
+
+        inferred
+            .into_iter()
+            .zip(template.type_params.iter())
+            .map(|(ty, param)| ty.ok_or_else(|| Error::new(pos, E510(param.lex.clone()))))
+            .collect()
+    }
+
+    /// Returns the instance of the given generic function with the given type arguments,
+    /// creating it if it does not exist yet.
+    pub fn instantiate_fn(&self, template: usize, args: Vec<Type>) -> Res<FnInstance> {
+        if let Some((_, _, inst)) = self
+            .instances
+            .borrow()
+            .funcs
+            .iter()
+            .find(|(t, a, _)| *t == template && *a == args)
+        {
+            return Ok(inst.clone());
+        }
+
+        let mut ast = self.module.borrow().generic_funcs[template].clone();
+        let type_args = Self::type_args(&ast.type_params, &args);
+        ast.type_params.clear();
+        let mut func = self.make_function(ast, &type_args)?;
+
+        let mut instances = self.instances.borrow_mut();
+        func.name = SmolStr::new(format!("{}#{}", func.name, instances.funcs.len()));
+        let index = self.module.borrow().funcs.len() + instances.pending_funcs.len();
+        let instance = FnInstance {
+            func: FuncRef {
+                module: self.module.clone(),
+                index,
+            },
+            params: func.params.iter().map(|p| p.ty.clone()).collect(),
+            ret_type: func.ret_type.clone(),
+        };
+        instances.pending_funcs.push(func);
+        instances.funcs.push((template, args, instance.clone()));
+        Ok(instance)
+    }
+
+    /// Returns the instance of the given generic class with the given type arguments,
+    /// creating it if it does not exist yet.
+    pub fn instantiate_class(&self, template: usize, args: Vec<Type>, pos: Position) -> Res<Type> {
+        if let Some((_, _, cls)) = self
+            .instances
+            .borrow()
+            .classes
+            .iter()
+            .find(|(t, a, _)| *t == template && *a == args)
+        {
+            return Ok(Type::Class(cls.clone()));
+        }
+
+        let mut ast = self.module.borrow().generic_classes[template].clone();
+        if ast.type_params.len() != args.len() {
+            return Err(Self::type_arg_count(
+                &ast.name.lex,
+                pos,
+                ast.type_params.len(),
+                args.len(),
+            ));
+        }
+        let type_args = Self::type_args(&ast.type_params, &args);
+        ast.type_params.clear();
+
+        let mut instances = self.instances.borrow_mut();
+        let name = SmolStr::new(format!("{}#{}", ast.name.lex, instances.classes.len()));
+        let cls = ClassRef {
+            module: self.module.clone(),
+            index: self.module.borrow().classes.len() + instances.pending_classes.len(),
+        };
+        instances.pending_classes.push(Class {
+            name,
+            content: RefCell::new(IndexMap::new()),
+            ast: RefCell::new(ast),
+            type_args,
+        });
+        instances.classes.push((template, args, cls.clone()));
+        Ok(Type::Class(cls))
+    }
+
+    /// Add all instances created since the last call to the module.
+    /// The module must not be borrowed.
+    pub fn flush_instances(&self) {
+        let mut instances = self.instances.borrow_mut();
+        let mut module = self.module.borrow_mut();
+        module.funcs.append(&mut instances.pending_funcs);
+        module.classes.append(&mut instances.pending_classes);
+    }
+
+    fn type_args(params: &[Token], args: &[Type]) -> TypeArgs {
+        params
+            .iter()
+            .map(|p| p.lex.clone())
+            .zip(args.iter().cloned())
+            .collect()
+    }
+}
@@ -1,16 +1,21 @@
 mod expr_compiler;
+mod generics;
 mod passes;
 mod resolver;
 
 use crate::{
-    compiler::{ir::Module, MutRc},
+    compiler::{ir::Module, module::generics::Instances, MutRc},
     error::Errors,
 };
 use alloc::vec::Vec;
+use core::cell::RefCell;
 
 pub struct ModuleCompiler {
     pub(super) module: MutRc<Module>,
     pub(super) errors: Errors,
+    instances: RefCell<Instances>,
+    /// If builtin functions are available.
+    pub(super) prelude: bool,
 }
 
 impl ModuleCompiler {
@@ -27,6 +32,14 @@ impl ModuleCompiler {
         Self {
             module,
             errors: Vec::new(),
+            instances: RefCell::new(Instances::default()),
+            prelude: true,
         }
     }
+
+    /// Set if the prelude of builtin functions is available.
+    pub fn with_prelude(mut self, prelude: bool) -> Self {
+        self.prelude = prelude;
+        self
+    }
 }
@@ -3,11 +3,14 @@ use crate::{
         ir::{Class, ClassContent, Expr, FuncRef, Function, Type, VarStore},
         module::{expr_compiler::ExprCompiler, ModuleCompiler},
     },
-    error::Res,
-    parser::ast,
+    error::{Error, ErrorKind::E201, Res},
+    lexer::Token,
+    parser::{ast, SCRIPT_FUNCTION},
+    smol_str::SmolStr,
 };
 use alloc::vec::Vec;
 use core::{cell::RefCell, mem};
+use hashbrown::HashMap;
 use indexmap::IndexMap;
 use smallvec::SmallVec;
 
@@ -17,18 +20,35 @@ impl ModuleCompiler {
     }
 
     pub fn stage_1(&mut self) {
-        self.declare_classes().unwrap();
-        self.declare_functions().unwrap();
-        self.generate_classes().unwrap();
-        self.generate_functions().unwrap();
+        if let Err(err) = self.try_stage_1() {
+            self.errors.push(err);
+        }
+    }
+
+    fn try_stage_1(&mut self) -> Res<()> {
+        self.declare_classes()?;
+        self.declare_functions()?;
+        self.flush_instances();
+        self.generate_classes(0)?;
+        self.generate_functions()
     }
 
     fn declare_classes(&mut self) -> Res<()> {
         let ast_cls = mem::replace(&mut self.module.borrow_mut().ast.classes, Vec::new());
         for cls in ast_cls {
-            self.module
+            let reserved = self
+                .module
                 .borrow_mut()
-                .try_reserve_name(&cls.name.lex, cls.name.start)?;
+                .try_reserve_name(&cls.name.lex, cls.name.start);
+            if let Err(err) = reserved {
+                self.errors.push(err);
+                continue;
+            }
+
+            if !cls.type_params.is_empty() {
+                self.module.borrow_mut().generic_classes.push(cls);
+                continue;
+            }
 
             self.module.borrow_mut().classes.push(Class {
                 name: cls.name.lex.clone(),
@@ -36,6 +56,7 @@ impl ModuleCompiler {
                     cls.methods.len() + cls.members.len() + cls.functions.len() + 2,
                 )),
                 ast: RefCell::new(cls),
+                type_args: Vec::new(),
             })
         }
         Ok(())
@@ -44,23 +65,48 @@ impl ModuleCompiler {
     fn declare_functions(&mut self) -> Res<()> {
         let ast_fns = mem::replace(&mut self.module.borrow_mut().ast.functions, Vec::new());
         for func in ast_fns {
-            self.module
+            let reserved = self
+                .module
                 .borrow_mut()
-                .try_reserve_name(&func.name.lex, func.name.start)?;
+                .try_reserve_name(&func.name.lex, func.name.start);
+            if let Err(err) = reserved {
+                self.errors.push(err);
+                continue;
+            }
 
-            self.declare_function(func)?;
+            if !func.type_params.is_empty() {
+                self.module.borrow_mut().generic_funcs.push(func);
+                continue;
+            }
+
+            self.declare_function(func, &[])?;
         }
         Ok(())
     }
 
-    fn declare_function(&mut self, func: ast::Function) -> Res<FuncRef> {
+    fn declare_function(
+        &mut self,
+        func: ast::Function,
+        type_args: &[(SmolStr, Type)],
+    ) -> Res<FuncRef> {
+        let func = self.make_function(func, type_args)?;
+        self.module.borrow_mut().funcs.push(func);
+        Ok(FuncRef::new_last(&self.module))
+    }
+
+    /// Create the IR function for the given AST, without adding it to the module.
+    pub fn make_function(
+        &self,
+        func: ast::Function,
+        type_args: &[(SmolStr, Type)],
+    ) -> Res<Function> {
         let params = func
             .params
             .iter()
             .enumerate()
             .map(|(index, param)| {
                 Ok(VarStore {
-                    ty: self.resolve_ty(&param.ty)?,
+                    ty: self.resolve_ty_in(&param.ty, type_args)?,
                     name: param.name.clone(),
                     index,
                     mutable: false,
@@ -70,10 +116,10 @@ impl ModuleCompiler {
         let ret_type = func
             .ret_type
             .as_ref()
-            .map(|t| self.resolve_ty(&t))
+            .map(|t| self.resolve_ty_in(&t, type_args))
             .unwrap_or(Ok(Type::Void))?;
 
-        self.module.borrow_mut().funcs.push(Function {
+        Ok(Function {
             name: func.name.lex.clone(),
             body: RefCell::new(Expr::poison()),
             params,
@@ -81,57 +127,129 @@ impl ModuleCompiler {
             ret_type,
             ir: RefCell::new(None),
             ast: func,
-        });
+            type_args: type_args.to_vec(),
+        })
+    }
 
-        Ok(FuncRef::new_last(&self.module))
+    /// Generate all classes starting at the given index,
+    /// including instances of generic classes created while doing so.
+    fn generate_classes(&mut self, mut index: usize) -> Res<()> {
+        while index < self.module.borrow().classes.len() {
+            self.generate_class(index)?;
+            self.flush_instances();
+            index += 1;
+        }
+        Ok(())
     }
 
-    fn generate_classes(&mut self) -> Res<()> {
-        let module = self.module.clone();
-        for cls in module.borrow().classes.iter() {
+    fn generate_class(&mut self, index: usize) -> Res<()> {
+        // Take everything needed out of the class, since resolving types
+        // and declaring methods modifies the module
+        let (members, methods, functions, type_args) = {
+            let module = self.module.borrow();
+            let cls = &module.classes[index];
             let mut ast = cls.ast.borrow_mut();
-            for (index, member) in ast.members.iter().enumerate() {
-                let store = VarStore {
-                    ty: self.resolve_ty(&member.ty)?,
-                    name: member.name.lex.clone(),
-                    index,
-                    mutable: member.mutable,
+            (
+                ast.members.clone(),
+                mem::replace(&mut ast.methods, Vec::new()),
+                mem::replace(&mut ast.functions, Vec::new()),
+                cls.type_args.clone(),
+            )
+        };
+        let insert = |this: &Self, name: SmolStr, content: ClassContent| {
+            this.module.borrow().classes[index]
+                .content
+                .borrow_mut()
+                .insert(name, content);
+        };
+        // Members, methods and functions share one namespace
+        let mut names = HashMap::new();
+        let mut reserve = |this: &mut Self, name: &Token| match names.get(&name.lex) {
+            Some(&first) => {
+                let error = E201 {
+                    name: name.lex.clone(),
+                    first,
                 };
-                cls.content
-                    .borrow_mut()
-                    .insert(member.name.lex.clone(), ClassContent::Member(store.clone()));
+                this.errors.push(Error::new(name.start, error));
+                false
+            }
+            None => {
+                names.insert(name.lex.clone(), name.start);
+                true
             }
+        };
+
+        for (i, member) in members.iter().enumerate() {
+            if !reserve(self, &member.name) {
+                continue;
+            }
+            let store = VarStore {
+                ty: self.resolve_ty_in(&member.ty, &type_args)?,
+                name: member.name.lex.clone(),
+                index: i,
+                mutable: member.mutable,
+            };
+            insert(self, member.name.lex.clone(), ClassContent::Member(store));
+        }
 
-            for method in ast.methods.drain(..) {
-                let name = method.name.lex.clone();
-                let fun = self.declare_function(method)?;
-                cls.content
-                    .borrow_mut()
-                    .insert(name, ClassContent::Method(fun));
+        for method in methods {
+            if !reserve(self, &method.name) {
+                continue;
             }
+            let name = method.name.lex.clone();
+            let fun = self.declare_function(method, &type_args)?;
+            insert(self, name, ClassContent::Method(fun));
+        }
 
-            for function in ast.functions.drain(..) {
-                let name = function.name.lex.clone();
-                let fun = self.declare_function(function)?;
-                cls.content
-                    .borrow_mut()
-                    .insert(name, ClassContent::Function(fun));
+        for function in functions {
+            if !reserve(self, &function.name) {
+                continue;
             }
+            let name = function.name.lex.clone();
+            let fun = self.declare_function(function, &type_args)?;
+            insert(self, name, ClassContent::Function(fun));
         }
         Ok(())
     }
 
-    fn generate_functions(&self) -> Res<()> {
-        for func in self
-            .module
-            .borrow()
-            .funcs
-            .iter()
-            .filter(|f| f.ast.body.is_some())
-        {
-            let mut compiler = ExprCompiler::new(self, func);
-            let body = compiler.expr(&func.ast.body.as_ref().unwrap());
-            *func.body.borrow_mut() = body;
+    /// Generate the bodies of all functions, including instances
+    /// of generic functions created while doing so.
+    fn generate_functions(&mut self) -> Res<()> {
+        let mut start = 0;
+        loop {
+            let (end, classes) = {
+                let module = self.module.borrow();
+                (module.funcs.len(), module.classes.len())
+            };
+            if start == end {
+                break;
+            }
+
+            let mut errors = Vec::new();
+            let mut warnings = Vec::new();
+            for func in self.module.borrow().funcs[start..end]
+                .iter()
+                .filter(|f| f.ast.body.is_some())
+            {
+                let mut compiler = ExprCompiler::new(self, func);
+                let body = compiler.expr(&func.ast.body.as_ref().unwrap());
+                *func.body.borrow_mut() = body;
+                errors.append(&mut compiler.errors);
+                warnings.append(&mut compiler.warnings);
+            }
+            // A script returns the value of its last statement, known only now
+            for func in self.module.borrow_mut().funcs[start..end]
+                .iter_mut()
+                .filter(|f| f.name == SCRIPT_FUNCTION)
+            {
+                func.ret_type = func.body.borrow().typ();
+            }
+            self.errors.append(&mut errors);
+            self.module.borrow_mut().warnings.append(&mut warnings);
+
+            self.flush_instances();
+            self.generate_classes(classes)?;
+            start = end;
         }
         Ok(())
     }
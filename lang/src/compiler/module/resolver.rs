@@ -3,24 +3,60 @@ use crate::{
         ir::{ClassRef, Type},
         module::ModuleCompiler,
     },
-    error::{Error, ErrorKind::E200, Res},
+    error::{
+        Error,
+        ErrorKind::{E200, E202},
+        Position, Res,
+    },
     parser::ast,
     smol_str::SmolStr,
 };
+use alloc::vec::Vec;
 
 impl ModuleCompiler {
     pub fn resolve_ty(&self, ty: &ast::Type) -> Res<Type> {
-        self.resolve_ty_name(&ty.name.lex, ty.name.start)
+        self.resolve_ty_in(ty, &[])
     }
 
-    fn resolve_ty_name(&self, name: &SmolStr, position: usize) -> Res<Type> {
+    /// Resolve a type, replacing type parameters with the given types.
+    pub fn resolve_ty_in(&self, ty: &ast::Type, type_args: &[(SmolStr, Type)]) -> Res<Type> {
+        let name = &ty.name.lex;
+        let args = ty
+            .args
+            .iter()
+            .map(|arg| self.resolve_ty_in(arg, type_args))
+            .collect::<Res<Vec<_>>>()?;
+
+        if let Some((_, param)) = type_args.iter().find(|(param, _)| param == name) {
+            return if args.is_empty() {
+                Ok(param.clone())
+            } else {
+                Err(Self::type_arg_count(
+                    &ty.name.lex,
+                    ty.name.start,
+                    0,
+                    args.len(),
+                ))
+            };
+        }
+
+        match self.find_generic_class(name) {
+            Some(index) => self.instantiate_class(index, args, ty.name.start),
+            None if args.is_empty() => self.resolve_ty_name(name, ty.name.start),
+            None => Err(Self::type_arg_count(name, ty.name.start, 0, args.len())),
+        }
+    }
+
+    fn resolve_ty_name(&self, name: &SmolStr, position: Position) -> Res<Type> {
         match &name[..] {
             "bool" => Ok(Type::Bool),
             "i64" => Ok(Type::I64),
             "f64" => Ok(Type::F64),
+            "str" => Ok(Type::String),
+            "handle" => Ok(Type::Handle),
             _ => self
                 .module
-                .borrow_mut()
+                .borrow()
                 .classes
                 .iter()
                 .position(|cls| cls.name == *name)
@@ -33,4 +69,15 @@ impl ModuleCompiler {
                 .ok_or_else(|| Error::new(position, E200(name.clone()))),
         }
     }
+
+    pub fn type_arg_count(name: &SmolStr, pos: Position, expected: usize, found: usize) -> Error {
+        Error::new(
+            pos,
+            E202 {
+                name: name.clone(),
+                expected,
+                found,
+            },
+        )
+    }
 }
@@ -5,19 +5,72 @@ use core::fmt::Display;
 pub type Res<T> = Result<T, Error>;
 pub type Errors = Vec<Error>;
 
-#[derive(Debug)]
+/// A position inside of source code, shared by all stages from
+/// lexing to runtime errors.
+/// `line` and `col` are 1-based, `offset` is the byte offset into the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct Error {
     kind: ErrorKind,
-    start: usize,
+    start: Position,
+    backtrace: Vec<Frame>,
 }
 
+/// A function call active when a runtime error was raised,
+/// as the name of the function and the line it was at.
+pub type Frame = (SmolStr, usize);
+
 impl Error {
-    pub fn new(start: usize, kind: ErrorKind) -> Self {
-        Self { start, kind }
+    pub fn new(start: Position, kind: ErrorKind) -> Self {
+        Self {
+            start,
+            kind,
+            backtrace: Vec::new(),
+        }
+    }
+
+    pub fn with_backtrace(mut self, backtrace: Vec<Frame>) -> Self {
+        self.backtrace = backtrace;
+        self
+    }
+
+    /// The calls that led to a runtime error, innermost first,
+    /// starting with the function raising it.
+    /// Empty for errors found before running.
+    pub fn backtrace(&self) -> &[Frame] {
+        &self.backtrace
     }
+
+    pub fn position(&self) -> Position {
+        self.start
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            ErrorKind::W001(_) | ErrorKind::W002(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// Warnings do not prevent a program from running.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ErrorKind {
     // Expected '{}', found '{}'.
     E100 {
@@ -28,11 +81,38 @@ pub enum ErrorKind {
     E101,
     // Expected declaration.
     E102,
+    // Expression or type nested too deeply.
+    E103,
+    // Expected ';' or newline between statements.
+    E104,
+    // Integer literal '{}' does not fit into i64.
+    E105(SmolStr),
+    // Comparisons cannot be chained, use 'and' like in 'a < b and b < c'.
+    E106,
+    // Unexpected character '{}'.
+    E107(char),
 
     // Cannot find type '{}'.
     E200(SmolStr),
-    // Name '{}' already used.
-    E201(SmolStr),
+    // Name '{}' already used (first defined at {}).
+    E201 {
+        name: SmolStr,
+        first: Position,
+    },
+    // Type '{}' expects {} type arguments but found {}.
+    E202 {
+        name: SmolStr,
+        expected: usize,
+        found: usize,
+    },
+    // Cannot find entry point function '{}'.
+    E203(SmolStr),
+    // Function '{}' is defined by multiple modules.
+    E204(SmolStr),
+    // Cannot find a definition of extern function '{}'.
+    E205(SmolStr),
+    // Extern function '{}' does not match its definition.
+    E206(SmolStr),
 
     // L/R side of binary expression must have same type (left is '{}', right is '{}').
     E500 {
@@ -71,10 +151,75 @@ pub enum ErrorKind {
         found: String,
         pos: usize,
     },
+    // Panic message must be a string literal.
+    E509,
+    // Cannot infer type parameter '{}'.
+    E510(SmolStr),
+    // Cannot cast '{}' to '{}'.
+    E511 {
+        from: String,
+        to: String,
+    },
+    // Cannot assign to final variable '{}'.
+    E512(SmolStr),
+    // Entry point '{}' must not take parameters.
+    E513(SmolStr),
+    // Expression is not constant.
+    E514,
+    // Division by zero in constant expression.
+    E515,
+    // Type '{}' is not a class.
+    E516(String),
+    // Class '{}' has no member '{}'.
+    E517 {
+        class: SmolStr,
+        member: SmolStr,
+    },
+    // Expected member '{}' to be of type {} but found {}.
+    E518 {
+        member: SmolStr,
+        expected: String,
+        found: String,
+    },
+    // Missing member '{}' in literal of class '{}'.
+    E519 {
+        class: SmolStr,
+        member: SmolStr,
+    },
+    // Cannot find a loop labeled {}.
+    E520(SmolStr),
+    // 'break' and 'continue' must be inside of a loop.
+    E521,
+    // Script result of type '{}' cannot be returned to the host.
+    E522(String),
+    // Code generation failed: {}.
+    E523(String),
+    // Test function '{}' must take no parameters and return nothing.
+    E524(SmolStr),
+
+    // Assertion failed.
+    E600,
+    // Program panicked: '{}'.
+    E601(SmolStr),
+    // Integer overflow.
+    E602,
+    // Index out of bounds.
+    E603,
+    // Negative integer exponent.
+    E604,
+    // Empty range, 'rand_int' needs lo < hi.
+    E605,
+    // String is not valid UTF-8, a substring split a multi-byte character.
+    E606,
+
+    // Variable '{}' shadows an earlier binding.
+    W001(SmolStr),
+    // Variable '{}' is never read.
+    W002(SmolStr),
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}:{}: {:?}", self.start.line, self.start.col, self.kind)
     }
 }
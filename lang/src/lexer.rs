@@ -1,29 +1,152 @@
-use crate::smol_str::SmolStr;
+use crate::{error::Position, smol_str::SmolStr};
+use core::ops::Range;
 pub use logos::{Logos, Span};
 
 pub struct Lexer<'l> {
     logos: logos::Lexer<'l, TKind>,
+    /// Offset up to which lines have been counted.
+    scanned: usize,
+    /// The current line.
+    line: usize,
+    /// The column at `scanned`, counted in characters.
+    col: usize,
+    /// If whitespace and comments are emitted as tokens, see `with_trivia`.
+    trivia: bool,
+    /// Offset up to which tokens have been emitted, when emitting trivia.
+    emitted: usize,
+    /// The next token after the trivia currently being emitted.
+    peeked: Option<(TKind, Span)>,
 }
 
 impl<'l> Lexer<'l> {
+    /// Returns the position at the end of the source.
+    pub fn eof_position(&mut self) -> Position {
+        self.position(self.logos.source().len())
+    }
+
+    /// Compute the position of the given offset.
+    /// Offsets must be monotonically increasing between calls.
+    /// `\r\n`, `\n` and a lone `\r` all count as a single line break.
+    /// Columns count characters, not bytes, so they match what editors show.
+    fn position(&mut self, offset: usize) -> Position {
+        let source = self.logos.source().as_bytes();
+        for i in self.scanned..offset {
+            let crlf = source[i] == b'\n' && i > 0 && source[i - 1] == b'\r';
+            if source[i] == b'\n' || source[i] == b'\r' {
+                self.line += !crlf as usize;
+                self.col = 1;
+            } else if !is_continuation_byte(source[i]) {
+                self.col += 1;
+            }
+        }
+        self.scanned = offset;
+
+        Position {
+            offset,
+            line: self.line,
+            col: self.col,
+        }
+    }
+
+    /// Emit whitespace, newlines and comments as `Whitespace`, `Newline`
+    /// and `Comment` tokens instead of skipping them, for tools like
+    /// formatters that need to reproduce the source exactly.
+    /// Concatenating the lexemes of all tokens yields the source again.
+    #[allow(unused)] // Not used until a formatter exists
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
+
+    /// Returns the next trivia token before the next real token, if any.
+    fn next_trivia(&mut self) -> Option<Token> {
+        if self.peeked.is_none() {
+            let kind = self.logos.next();
+            self.peeked = kind.map(|kind| (kind, self.logos.span()));
+        }
+        let source = self.logos.source();
+        let gap_end = self
+            .peeked
+            .as_ref()
+            .map_or(source.len(), |(_, span)| span.start);
+        if self.emitted >= gap_end {
+            return None;
+        }
+
+        let gap = &source[self.emitted..gap_end];
+        let (kind, len) = trivia_len(gap);
+        let start = self.position(self.emitted);
+        self.emitted += len;
+        Some(Token {
+            kind,
+            lex: SmolStr::new(&gap[..len]),
+            start,
+        })
+    }
+
     pub fn new(input: &'l str) -> Self {
         Self {
             logos: TKind::lexer(input),
+            scanned: 0,
+            line: 1,
+            col: 1,
+            trivia: false,
+            emitted: 0,
+            peeked: None,
         }
     }
 }
 
+/// Returns the kind and length of the trivia at the start of `gap`,
+/// which consists only of text skipped by the lexer.
+fn trivia_len(gap: &str) -> (TKind, usize) {
+    if gap.starts_with("\r\n") {
+        (TKind::Newline, 2)
+    } else if gap.starts_with(is_line_break) {
+        (TKind::Newline, 1)
+    } else if gap.starts_with("//") {
+        (TKind::Comment, gap.find(is_line_break).unwrap_or(gap.len()))
+    } else if gap.starts_with("/*") {
+        let end = gap[2..].find("*/").map_or(gap.len(), |end| end + 4);
+        (TKind::Comment, end)
+    } else {
+        let end = gap
+            .find(|c| is_line_break(c) || c == '/')
+            .unwrap_or(gap.len())
+            .max(1);
+        (TKind::Whitespace, end)
+    }
+}
+
+fn is_line_break(c: char) -> bool {
+    c == '\n' || c == '\r'
+}
+
+/// If the byte continues a multi-byte UTF-8 character instead of starting one.
+fn is_continuation_byte(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
 impl<'l> Iterator for Lexer<'l> {
     type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let kind = self.logos.next()?;
-        let lexeme = self.logos.slice();
-        let span = self.logos.span();
+        if self.trivia {
+            if let Some(trivia) = self.next_trivia() {
+                return Some(trivia);
+            }
+        }
+
+        let (kind, span) = match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => (self.logos.next()?, self.logos.span()),
+        };
+        self.emitted = span.end;
+        let lexeme = &self.logos.source()[span.clone()];
         Some(Token {
             kind,
             lex: SmolStr::new(lexeme),
-            start: span.start,
+            start: self.position(span.start),
         })
     }
 }
@@ -32,7 +155,20 @@ impl<'l> Iterator for Lexer<'l> {
 pub struct Token {
     pub kind: TKind,
     pub lex: SmolStr,
-    pub start: usize,
+    pub start: Position,
+}
+
+impl Token {
+    /// Returns the byte range of the token in its source.
+    pub fn span(&self) -> Range<usize> {
+        self.start.offset..(self.start.offset + self.lex.len())
+    }
+}
+
+/// Returns the source text of the token, which is equal to its lexeme.
+/// Lets tools like formatters work on the source instead of the lexemes.
+pub fn token_to_str<'s>(token: &Token, src: &'s str) -> &'s str {
+    &src[token.span()]
 }
 
 /// A direct token that implements Logos. Most are keywords or special chars.
@@ -71,10 +207,14 @@ pub enum TKind {
     Slash,
     #[token("*")]
     Star,
+    #[token("**")]
+    StarStar,
     #[token("->")]
     Arrow,
     #[token("?")]
     QuestionMark,
+    #[token("@")]
+    At,
 
     #[token("!")]
     Bang,
@@ -95,6 +235,8 @@ pub enum TKind {
 
     #[regex("[a-zA-Z_][a-zA-Z0-9_]*")]
     Identifier,
+    #[regex("'[a-zA-Z_][a-zA-Z0-9_]*")]
+    Label,
     #[regex("\"[^\"]*\"")]
     String,
     #[regex(r"[0-9]+(?:(i|u)(size|8|16|32|64))?")]
@@ -104,10 +246,16 @@ pub enum TKind {
 
     #[token("and")]
     And,
+    #[token("as")]
+    As,
+    #[token("assert")]
+    Assert,
     #[token("break")]
     Break,
     #[token("class")]
     Class,
+    #[token("continue")]
+    Continue,
     #[token("else")]
     Else,
     #[token("enum")]
@@ -134,6 +282,8 @@ pub enum TKind {
     Null,
     #[token("or")]
     Or,
+    #[token("panic")]
+    Panic,
     #[token("return")]
     Return,
     #[token("static")]
@@ -149,13 +299,13 @@ pub enum TKind {
     #[token("while")]
     While,
 
-    #[regex(r"//[^\n]*", logos::skip)]
+    #[regex(r"//[^\r\n]*", logos::skip)]
     #[regex(r"/\*([^*]|\**[^*/])*\*+/", logos::skip)]
     Comment,
 
     #[regex(r"[ \t\f]+", logos::skip)]
     Whitespace,
-    #[token("\n", logos::skip)]
+    #[regex(r"\r\n?|\n", logos::skip)]
     Newline,
 
     #[regex(r"/\*([^*]|\*+[^*/])*\*?")] // https://github.com/maciejhirsz/logos/issues/180
@@ -173,7 +323,8 @@ impl TKind {
             Self::Less | Self::LessEqual | Self::Greater | Self::GreaterEqual => (16, 15),
             Self::Plus | Self::Minus => (16, 15),
             Self::Star | Self::Slash => (18, 17),
-            Self::Is => (20, 19),
+            Self::StarStar => (22, 21),
+            Self::Is | Self::As => (20, 19),
             _ => return None,
         })
     }
@@ -185,6 +336,19 @@ impl TKind {
         })
     }
 
+    /// Comparisons cannot be chained like `a < b < c`.
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            TKind::EqualEqual
+                | TKind::BangEqual
+                | TKind::Less
+                | TKind::LessEqual
+                | TKind::Greater
+                | TKind::GreaterEqual
+        )
+    }
+
     pub fn is_binary_logic(&self) -> bool {
         match self {
             TKind::EqualEqual
@@ -202,8 +366,12 @@ impl TKind {
 
 #[cfg(test)]
 mod test {
-    use crate::lexer::{Lexer, TKind, TKind::*};
-    use alloc::vec::Vec;
+    use crate::{
+        error::Position,
+        lexer::{token_to_str, Lexer, TKind, TKind::*},
+        parser::Parser,
+    };
+    use alloc::{string::String, vec::Vec};
 
     fn lex(input: &str, want: &[TKind]) {
         let lexer = Lexer::new(input);
@@ -216,4 +384,192 @@ mod test {
         lex("{ 5 }", &[LeftBrace, Int, RightBrace]);
         lex("{ 5 \n 5 }", &[LeftBrace, Int, Int, RightBrace]);
     }
+
+    #[test]
+    fn literals() {
+        lex("true false", &[True, False]);
+        lex("0 42 1234567", &[Int, Int, Int]);
+        lex("4.2 0.5", &[Float, Float]);
+        lex("\"\" \"a string\"", &[String, String]);
+        lex("ident _under score2", &[Identifier, Identifier, Identifier]);
+    }
+
+    #[test]
+    fn comments() {
+        lex("5 // comment \n 5", &[Int, Int]);
+        lex("5 /* multi \n line */ 5", &[Int, Int]);
+    }
+
+    #[test]
+    fn trivia() {
+        let src = "fun a() { // comment\n\t5 /* multi \n line */ }\n";
+        let tokens = Lexer::new(src).with_trivia().collect::<Vec<_>>();
+        let kinds = tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            &[
+                Fun, Whitespace, Identifier, LeftParen, RightParen, Whitespace, LeftBrace,
+                Whitespace, Comment, Newline, Whitespace, Int, Whitespace, Comment, Whitespace,
+                RightBrace, Newline
+            ]
+        );
+        assert_eq!(tokens[8].lex, "// comment");
+        assert_eq!(tokens[13].lex, "/* multi \n line */");
+        assert_eq!(tokens[15].start.line, 3);
+
+        let text = tokens.iter().map(|t| t.lex.as_str()).collect::<String>();
+        assert_eq!(text, src);
+    }
+
+    #[test]
+    fn positions() {
+        let tokens = Lexer::new("fun\n  main()\n\n}").collect::<Vec<_>>();
+        assert_eq!(
+            tokens[0].start,
+            Position {
+                offset: 0,
+                line: 1,
+                col: 1
+            }
+        );
+        assert_eq!(
+            tokens[1].start,
+            Position {
+                offset: 6,
+                line: 2,
+                col: 3
+            }
+        );
+        assert_eq!(
+            tokens[4].start,
+            Position {
+                offset: 14,
+                line: 4,
+                col: 1
+            }
+        );
+    }
+
+    #[test]
+    fn utf8_columns() {
+        let tokens = Lexer::new("\"äö€\" x\n  \"😀\" y").collect::<Vec<_>>();
+        let cols = tokens.iter().map(|t| t.start.col).collect::<Vec<_>>();
+        assert_eq!(cols, &[1, 7, 3, 7]);
+        assert_eq!(tokens[1].start.offset, 10);
+        // Errors of later stages use the same columns
+        let errors = Parser::new("fun main() { \"é\" < 2 < 3 }")
+            .parse(Vec::new())
+            .unwrap_err();
+        assert_eq!(errors[0].position().col, 22);
+    }
+
+    #[test]
+    fn line_endings() {
+        for newline in &["\n", "\r\n", "\r"] {
+            let src = ["fun", "  main() // comment", "", "}"].join(newline);
+            let tokens = Lexer::new(&src).collect::<Vec<_>>();
+            let kinds = tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+            assert_eq!(kinds, &[Fun, Identifier, LeftParen, RightParen, RightBrace]);
+            let lines = tokens.iter().map(|t| t.start.line).collect::<Vec<_>>();
+            assert_eq!(lines, &[1, 2, 2, 2, 4]);
+            assert_eq!(tokens[1].start.col, 3);
+            assert_eq!(tokens[4].start.col, 1);
+
+            let trivia = Lexer::new(&src).with_trivia().collect::<Vec<_>>();
+            let text = trivia.iter().map(|t| t.lex.as_str()).collect::<String>();
+            assert_eq!(text, src);
+            assert!(trivia.iter().all(|t| t.kind != Error));
+        }
+
+        let tokens = Lexer::new("a\r\nb\rc\nd").collect::<Vec<_>>();
+        let lines = tokens.iter().map(|t| t.start.line).collect::<Vec<_>>();
+        assert_eq!(lines, &[1, 2, 3, 4]);
+        assert!(tokens.iter().all(|t| t.start.col == 1));
+    }
+
+    /// Lex randomly generated source, checking that the text at every token's
+    /// recorded position is the token itself and that only whitespace is skipped.
+    #[test]
+    fn round_trip() {
+        const TOKENS: &[&str] = &[
+            "(",
+            ")",
+            "[",
+            "]",
+            "{",
+            "}",
+            ",",
+            ".",
+            "-",
+            "+",
+            ";",
+            ":",
+            "::",
+            "/",
+            "*",
+            "**",
+            "->",
+            "!",
+            "!=",
+            "=",
+            "==",
+            ">",
+            ">=",
+            "<",
+            "<=",
+            "and",
+            "fun",
+            "while",
+            "val",
+            "x",
+            "_under",
+            "ident42",
+            "0",
+            "1234",
+            "4.25",
+            "\"\"",
+            "\"a string\"",
+            "\"multi\nline\"",
+        ];
+        const WHITESPACE: &[&str] = &[" ", "\t", "\n", "  \n\t", "\n\n"];
+
+        // Xorshift, to not depend on a random number crate
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = |len: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % len as u64) as usize
+        };
+
+        for _ in 0..200 {
+            let mut src = String::new();
+            let mut want = Vec::new();
+            for _ in 0..next(40) {
+                src.push_str(WHITESPACE[next(WHITESPACE.len())]);
+                want.push(TOKENS[next(TOKENS.len())]);
+                src.push_str(want.last().unwrap());
+            }
+
+            let tokens = Lexer::new(&src).collect::<Vec<_>>();
+            assert_eq!(tokens.len(), want.len(), "{:?}", src);
+            let mut last_end = 0;
+            for (token, want) in tokens.iter().zip(want) {
+                assert_eq!(token_to_str(token, &src), want);
+                assert_eq!(token.lex, want);
+                assert!(src[last_end..token.start.offset].trim().is_empty());
+                last_end = token.span().end;
+
+                let before = &src[..token.start.offset];
+                let line = before.matches('\n').count() + 1;
+                let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+                assert_eq!(token.start.line, line);
+                assert_eq!(token.start.col, before[line_start..].chars().count() + 1);
+            }
+
+            let trivia = Lexer::new(&src).with_trivia().collect::<Vec<_>>();
+            let text = trivia.iter().map(|t| t.lex.as_str()).collect::<String>();
+            assert_eq!(text, src);
+        }
+    }
 }
@@ -3,16 +3,37 @@
 
 extern crate alloc;
 
-use crate::{compiler::Compiler, error::Errors, parser::Parser, vm::JIT};
+use crate::{
+    compiler::{Compiler, MutRc},
+    error::Errors,
+    parser::Parser,
+    vm::JIT,
+};
+pub use crate::{
+    compiler::ir::{SymbolInfo, SymbolKind},
+    error::{Error, Severity},
+    options::{ArithmeticMode, Options},
+};
+pub use compiler::consts::eval_const;
+pub use lexer::{token_to_str, Lexer, Token};
+pub use parser::{builder, ParseResult, Separators};
 
 use crate::{compiler::module::ModuleCompiler, filesystem::Filesystem};
-use alloc::{vec, vec::Vec};
+use alloc::{string::String, vec, vec::Vec};
+use core::ops::Range;
 
-use crate::compiler::ir::Module;
-pub use crate::vm::SymbolTable;
+use crate::{
+    compiler::ir::{Module, Type},
+    error::ErrorKind::{E522, E524, E606},
+    parser::SCRIPT_FUNCTION,
+    vm::StrRet,
+};
+pub use crate::vm::{Handles, SymbolTable, Value};
 #[cfg(feature = "core")]
 pub use cranelift_jit::{set_manager, MemoryManager};
 pub use smol_str::SmolStr;
+#[cfg(feature = "std")]
+pub use stats::{parse_stats, ParseStats};
 
 #[cfg(feature = "std")]
 extern crate std;
@@ -21,34 +42,285 @@ mod compiler;
 mod error;
 pub mod filesystem;
 mod lexer;
+mod options;
 mod parser;
 mod smol_str;
+#[cfg(feature = "std")]
+mod stats;
 mod vm;
 
-pub fn execute_module<T>(program: &str, symbols: SymbolTable) -> Result<T, Errors> {
-    let parse = Parser::new(program).parse(vec![SmolStr::new_inline("script")])?;
-    let ir = ModuleCompiler::new(Module::from_ast(parse)).consume()?;
-    let mut jit = JIT::new(symbols);
-    jit.jit_module(&*ir.borrow());
-    Ok(jit.exec("main"))
+/// A compiled program, which can be run any number of times
+/// without paying for parsing and compilation again.
+pub struct CompiledModule {
+    jit: JIT,
+    warnings: Errors,
+    symbols: Vec<SymbolInfo>,
+    /// Type of the value returned by a script, if this is one.
+    script: Option<Type>,
+}
+
+impl CompiledModule {
+    /// Warnings found during compilation, which did not prevent it.
+    pub fn warnings(&self) -> &[Error] {
+        &self.warnings
+    }
+
+    /// Everything the program defines, like functions and classes
+    /// with their contents, for completion and other tooling.
+    pub fn symbols(&self) -> &[SymbolInfo] {
+        &self.symbols
+    }
+
+    /// Returns everything printed by runs since the last call,
+    /// unless `Options::output` sends it elsewhere.
+    pub fn take_output(&self) -> String {
+        self.jit.take_output()
+    }
+}
+
+/// Parse the given program, which might not be complete yet.
+/// Useful for interactive prompts to decide whether to keep reading input.
+pub fn parse_incomplete(program: &str) -> ParseResult {
+    Parser::new(program).parse_incomplete(vec![SmolStr::new_inline("script")])
+}
+
+/// Returns the byte range of the innermost expression containing `offset`,
+/// for editor features like hover. None if there is no such expression
+/// or the program fails to parse.
+pub fn expr_span_at(program: &str, offset: usize) -> Option<Range<usize>> {
+    let module = Parser::new(program)
+        .parse(vec![SmolStr::new_inline("script")])
+        .ok()?;
+    parser::ast::node_at(&module, offset).map(|expr| expr.start.offset..expr.end)
+}
+
+pub fn execute_module<T>(
+    program: &str,
+    symbols: SymbolTable,
+    options: &Options,
+) -> Result<T, Errors> {
+    run_compiled(&compile_program(program, symbols, options)?)
+}
+
+pub fn compile_program(
+    program: &str,
+    symbols: SymbolTable,
+    options: &Options,
+) -> Result<CompiledModule, Errors> {
+    let parse = Parser::new(program)
+        .with_separators(options.separators)
+        .with_features(&options.features)
+        .parse(vec![SmolStr::new_inline("script")])?;
+    compile_ast(parse, symbols, options)
+}
+
+/// Compile a module built without source text, see `builder::ModuleBuilder`.
+pub fn compile_ast(
+    module: parser::Module,
+    symbols: SymbolTable,
+    options: &Options,
+) -> Result<CompiledModule, Errors> {
+    let ir = compile_parsed(module, options)?;
+    let script = script_type(&ir.borrow())?;
+    let mut jit = JIT::new(symbols, options);
+    jit.jit_module(&*ir.borrow()).map_err(|err| vec![err])?;
+    let warnings = ir.borrow().warnings.clone();
+    let symbols = compiler::ir::symbols(&ir.borrow());
+    Ok(CompiledModule {
+        jit,
+        warnings,
+        symbols,
+        script,
+    })
+}
+
+/// Compile a script, which may contain statements between its declarations,
+/// to be run with `run_script`. Unlike programs, scripts have no entry point.
+pub fn compile_script(
+    program: &str,
+    symbols: SymbolTable,
+    options: &Options,
+) -> Result<CompiledModule, Errors> {
+    let parse = Parser::new(program)
+        .with_separators(options.separators)
+        .with_features(&options.features)
+        .with_script(true)
+        .parse(vec![SmolStr::new_inline("script")])?;
+    compile_ast(parse, symbols, options)
+}
+
+/// Returns the type of the value the module's script returns, if it is a script.
+/// Fails if the value cannot be handed to the host as a `Value`.
+fn script_type(module: &Module) -> Result<Option<Type>, Errors> {
+    let script = match module.funcs.iter().find(|f| f.name == SCRIPT_FUNCTION) {
+        Some(script) => script,
+        None => return Ok(None),
+    };
+    match script.ret_type {
+        Type::Class(_) | Type::Function(_) => {
+            let body = script.ast.body.as_ref().unwrap();
+            let pos = match &*body.ty {
+                parser::ast::EExpr::Block(stmts) => stmts.last().map_or(body.start, |s| s.start),
+                _ => body.start,
+            };
+            let ty = compiler::ir::type_name(&script.ret_type);
+            Err(vec![Error::new(pos, E522(ty))])
+        }
+        ref ty => Ok(Some(ty.clone())),
+    }
+}
+
+fn compile_ir(program: &str, options: &Options) -> Result<MutRc<Module>, Errors> {
+    let parse = Parser::new(program)
+        .with_separators(options.separators)
+        .with_features(&options.features)
+        .parse(vec![SmolStr::new_inline("script")])?;
+    compile_parsed(parse, options)
+}
+
+fn compile_parsed(parse: parser::Module, options: &Options) -> Result<MutRc<Module>, Errors> {
+    let ir = ModuleCompiler::new(Module::from_ast(parse))
+        .with_prelude(!options.no_prelude)
+        .consume()?;
+    check_warnings(&ir.borrow(), options)?;
+    Ok(ir)
+}
+
+/// Fail with the warnings of the module if the options deny them.
+fn check_warnings(module: &Module, options: &Options) -> Result<(), Errors> {
+    if options.deny_warnings && !module.warnings.is_empty() {
+        Err(module.warnings.clone())
+    } else {
+        Ok(())
+    }
+}
+
+/// Dump the program after compilation in a textual three-address form,
+/// for inspecting how it lowers. The format is not stable.
+pub fn program_to_ir(program: &str, options: &Options) -> Result<String, Errors> {
+    let ir = compile_ir(program, options)?;
+    let text = compiler::ir::module_to_ir(&ir.borrow());
+    Ok(text)
+}
+
+/// The outcome of `run_tests`.
+#[derive(Debug, Default)]
+pub struct TestReport {
+    pub passed: usize,
+    /// Name and error of every failed test, in source order.
+    pub failed: Vec<(SmolStr, Error)>,
+}
+
+/// Run every function of the program named `test_*` in source order.
+/// A test fails if it raises a runtime error, like a failed `assert` or
+/// `assert_eq`; the remaining tests still run after a failure.
+/// Tests taking parameters or returning a value are not run but reported as failed.
+pub fn run_tests(
+    program: &str,
+    symbols: SymbolTable,
+    options: &Options,
+) -> Result<TestReport, Errors> {
+    let ir = compile_ir(program, options)?;
+    let mut jit = JIT::new(symbols, options);
+    jit.jit_module(&*ir.borrow()).map_err(|err| vec![err])?;
+
+    let mut report = TestReport::default();
+    let tests = ir
+        .borrow()
+        .funcs
+        .iter()
+        .filter(|func| func.name.starts_with("test_") && func.ast.body.is_some());
+    for func in tests {
+        if !func.params.is_empty() || func.ret_type != Type::Void {
+            let err = Error::new(func.ast.name.start, E524(func.name.clone()));
+            report.failed.push((func.name.clone(), err));
+            continue;
+        }
+        match jit.exec::<()>(&func.name) {
+            Ok(()) => report.passed += 1,
+            Err(err) => report.failed.push((func.name.clone(), err)),
+        }
+    }
+    Ok(report)
+}
+
+/// Run a program, returning its result along with everything it printed.
+/// Output is always captured, regardless of `Options::output`.
+pub fn run_program_capturing<T>(
+    program: &str,
+    symbols: SymbolTable,
+    options: &Options,
+) -> (Result<T, Errors>, String) {
+    let options = Options {
+        output: None,
+        ..options.clone()
+    };
+    match compile_program(program, symbols, &options) {
+        Ok(module) => {
+            let res = run_compiled(&module);
+            (res, module.take_output())
+        }
+        Err(errors) => (Err(errors), String::new()),
+    }
+}
+
+pub fn run_compiled<T>(module: &CompiledModule) -> Result<T, Errors> {
+    run_compiled_with_args(module, &[])
+}
+
+/// Run a compiled program, passing the given arguments to its entry point.
+/// They must match the entry point's parameters in number and type.
+pub fn run_compiled_with_args<T>(module: &CompiledModule, args: &[Value]) -> Result<T, Errors> {
+    module.jit.exec_entry(args).map_err(|err| vec![err])
+}
+
+/// Run a compiled script, returning the value of its last statement
+/// or None if it has no value, like a loop. See `compile_script`.
+/// A returned string borrows the module, as running it again frees the string.
+pub fn run_script(module: &mut CompiledModule) -> Result<Option<Value>, Errors> {
+    let jit = &module.jit;
+    let value = match module.script {
+        Some(Type::I64) => jit.exec(SCRIPT_FUNCTION).map(|i| Some(Value::Int(i))),
+        Some(Type::F64) => jit.exec(SCRIPT_FUNCTION).map(|f| Some(Value::Float(f))),
+        Some(Type::Bool) => jit.exec(SCRIPT_FUNCTION).map(|b| Some(Value::Bool(b))),
+        Some(Type::Handle) => jit.exec(SCRIPT_FUNCTION).map(|h| Some(Value::Handle(h))),
+        Some(Type::String) => jit.exec::<StrRet>(SCRIPT_FUNCTION).and_then(|s| {
+            // Strings returned by compiled code live until the next execution
+            match unsafe { s.as_str() } {
+                Some(s) => Ok(Some(Value::Str(s))),
+                None => Err(Error::new(Default::default(), E606)),
+            }
+        }),
+        // Reports a missing script function if this is not a script
+        _ => jit.exec::<()>(SCRIPT_FUNCTION).map(|()| None),
+    };
+    value.map_err(|err| vec![err])
 }
 
 #[cfg(feature = "std")]
-pub fn execute_with_os_fs<T>(paths: &[&str], symbols: SymbolTable) -> Result<T, Vec<Errors>> {
-    execute_path(filesystem::os_fs::OsFs, paths, symbols)
+pub fn execute_with_os_fs<T>(
+    paths: &[&str],
+    symbols: SymbolTable,
+    options: &Options,
+) -> Result<T, Vec<Errors>> {
+    execute_path(filesystem::os_fs::OsFs, paths, symbols, options)
 }
 
 pub fn execute_path<FS: Filesystem, T>(
     fs: FS,
     paths: &[&str],
     symbols: SymbolTable,
+    options: &Options,
 ) -> Result<T, Vec<Errors>> {
     let mut modules = Vec::with_capacity(20);
     let mut errors = Vec::new();
 
     for path in paths {
         fs.walk_directory(path, |file| {
-            let parse = Parser::new(&file.contents).parse(file.path);
+            let parse = Parser::new(&file.contents)
+                .with_separators(options.separators)
+                .with_features(&options.features)
+                .parse(file.path);
             match parse {
                 Ok(module) => modules.push(module),
                 Err(err) => errors.push(err),
@@ -58,26 +330,81 @@ pub fn execute_path<FS: Filesystem, T>(
     if !errors.is_empty() {
         return Err(errors);
     }
+    execute_parsed(modules, symbols, options)
+}
+
+/// Path of a module, like `["util", "math"]` for `util/math.yacari`.
+pub type ModulePath = Vec<SmolStr>;
+
+/// Run a program made up of the given modules. Extern functions of each module
+/// are linked to their definition in another module or to the host symbols.
+pub fn execute_modules<T>(
+    sources: &[(ModulePath, &str)],
+    symbols: SymbolTable,
+    options: &Options,
+) -> Result<T, Vec<Errors>> {
+    let mut modules = Vec::with_capacity(sources.len());
+    let mut errors = Vec::new();
+
+    for (path, source) in sources {
+        let parse = Parser::new(source)
+            .with_separators(options.separators)
+            .with_features(&options.features)
+            .parse(path.clone());
+        match parse {
+            Ok(module) => modules.push(module),
+            Err(err) => errors.push(err),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    execute_parsed(modules, symbols, options)
+}
 
-    let ir = Compiler::new(modules).consume()?;
-    let mut jit = JIT::new(symbols);
+fn execute_parsed<T>(
+    modules: Vec<parser::Module>,
+    symbols: SymbolTable,
+    options: &Options,
+) -> Result<T, Vec<Errors>> {
+    let ir = Compiler::new(modules)
+        .with_prelude(!options.no_prelude)
+        .consume()?;
+    let warnings = ir
+        .iter()
+        .filter_map(|module| check_warnings(&module.borrow(), options).err())
+        .collect::<Vec<_>>();
+    if !warnings.is_empty() {
+        return Err(warnings);
+    }
+    compiler::linker::link(&ir, symbols)?;
+    let mut jit = JIT::new(symbols, options);
 
     for module in &ir {
-        jit.jit_module(&*module.borrow());
+        jit.jit_module(&*module.borrow())
+            .map_err(|err| vec![vec![err]])?;
     }
-    Ok(jit.exec("main"))
+    jit.exec_entry(&[]).map_err(|err| vec![vec![err]])
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{execute_module, execute_with_os_fs};
+    use crate::{
+        builder::{binary, block, call, if_, int, name, variable, ModuleBuilder},
+        compile_ast, compile_program, compile_script,
+        error::{ErrorKind, Errors},
+        execute_module, execute_modules, execute_with_os_fs, expr_span_at, program_to_ir,
+        run_compiled, run_compiled_with_args, run_program_capturing, run_script, run_tests,
+        ArithmeticMode, Handles, Options, ParseResult, SmolStr, SymbolKind, Value,
+    };
+    use alloc::{vec, vec::Vec};
     extern crate std;
-    use crate::vm::SymbolTable;
-    use core::fmt::Debug;
+    use crate::{compiler::Compiler, parser::Parser, vm::SymbolTable};
+    use core::{cell::RefCell, fmt::Debug};
     use std::format;
 
     fn directory<T: Debug + PartialEq>(dir: &str, expect: T, symbols: SymbolTable) {
-        let res = execute_with_os_fs::<T>(&[dir], symbols).unwrap();
+        let res = execute_with_os_fs::<T>(&[dir], symbols, &Options::default()).unwrap();
         assert_eq!(res, expect)
     }
 
@@ -86,7 +413,7 @@ mod test {
     }
 
     fn file_<T: Debug + PartialEq>(input: &str, expect: T, symbols: SymbolTable) {
-        let res = execute_module::<T>(input, symbols).unwrap();
+        let res = execute_module::<T>(input, symbols, &Options::default()).unwrap();
         assert_eq!(res, expect)
     }
 
@@ -97,6 +424,23 @@ mod test {
         );
     }
 
+    fn file_err(input: &str) {
+        let res = execute_module::<()>(input, &[], &Options::default());
+        assert!(res.is_err())
+    }
+
+    fn expr_err(input: &str) {
+        file_err(&format!("fun main() {{ {} \n }}", input))
+    }
+
+    /// Returns the kind of the first error, checking it points at the first occurrence of `at`.
+    fn expr_err_at(input: &str, at: &str) -> ErrorKind {
+        let program = format!("fun main() {{ {} \n }}", input);
+        let errors = execute_module::<()>(&program, &[], &Options::default()).unwrap_err();
+        assert_eq!(errors[0].position().offset, program.find(at).unwrap());
+        errors[0].kind().clone()
+    }
+
     fn expr_none(input: &str) {
         expr(input, "", ())
     }
@@ -107,6 +451,15 @@ mod test {
         expr(input, "-> i64", expect)
     }
 
+    fn expr_arith(input: &str, arithmetic: ArithmeticMode) -> Result<i64, ()> {
+        let options = Options {
+            arithmetic,
+            ..Options::default()
+        };
+        let program = format!("fun main() -> i64 {{ {} \n }}", input);
+        execute_module::<i64>(&program, &[], &options).map_err(|_| ())
+    }
+
     #[test]
     fn block() {
         expr_i64("5 + 5 \n  2 - 2 \n 1", 1);
@@ -120,6 +473,54 @@ mod test {
         expr_i64("64 / 8", 8);
     }
 
+    #[test]
+    fn arithmetic_modes() {
+        let max = "9223372036854775807";
+        let add = format!("{} + 1", max);
+        let mul = format!("{} * (0 - 2)", max);
+
+        assert_eq!(expr_arith(&add, ArithmeticMode::Wrapping), Ok(i64::MIN));
+        assert_eq!(expr_arith(&add, ArithmeticMode::Saturating), Ok(i64::MAX));
+        assert_eq!(expr_arith(&add, ArithmeticMode::Checked), Err(()));
+
+        assert_eq!(expr_arith(&mul, ArithmeticMode::Wrapping), Ok(2));
+        assert_eq!(expr_arith(&mul, ArithmeticMode::Saturating), Ok(i64::MIN));
+        assert_eq!(expr_arith(&mul, ArithmeticMode::Checked), Err(()));
+
+        let sub = format!("0 - {} - 5", max);
+        assert_eq!(expr_arith(&sub, ArithmeticMode::Saturating), Ok(i64::MIN));
+        assert_eq!(expr_arith(&sub, ArithmeticMode::Checked), Err(()));
+        assert_eq!(expr_arith("5 * 3 - 20", ArithmeticMode::Checked), Ok(-5));
+    }
+
+    #[test]
+    fn power() {
+        expr_i64("2 ** 10", 1024);
+        expr_i64("7 ** 0", 1);
+        expr_i64("2 * 3 ** 2", 18);
+        expr_i64("2 ** 3 ** 2", 512);
+        expr("2.0 ** 0.5", "-> f64", core::f64::consts::SQRT_2);
+        expr_err("2 ** 1.5");
+
+        let big = "3 ** 41";
+        assert_eq!(expr_arith(big, ArithmeticMode::Saturating), Ok(i64::MAX));
+        assert_eq!(expr_arith(big, ArithmeticMode::Checked), Err(()));
+        assert_eq!(
+            expr_arith("2 ** (0 - 1)", ArithmeticMode::Wrapping),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn cast() {
+        expr("5 as f64", "-> f64", 5.0);
+        expr_i64("3.7 as i64", 3);
+        expr_i64("true as i64 + 1", 2);
+        expr_i64("(2 as f64 * 1.5) as i64", 3);
+        expr_err("true as f64");
+        expr_err("5 as bool");
+    }
+
     #[test]
     fn logic() {
         expr_bool("5 == 5", true);
@@ -156,6 +557,48 @@ mod test {
         expr_i64("var a = 3 \n while (a > 10) { a = a + 1 } \n a", 3);
     }
 
+    #[test]
+    fn break_continue() {
+        expr_i64(
+            "var n = 0 \n var k = 0 \n\
+             while (true) { \n\
+             k = k + 1 \n\
+             if (k > 10) break \n\
+             if (k == 3) continue \n\
+             n = n + k \n\
+             } \n\
+             n",
+            52,
+        );
+        expr_i64(
+            "var count = 0 \n var i = 0 \n\
+             'outer: while (i < 10) { \n\
+             i = i + 1 \n\
+             var j = 0 \n\
+             while (true) { \n\
+             j = j + 1 \n\
+             if (j > 3) continue 'outer \n\
+             if (i == 5) break 'outer \n\
+             count = count + 1 \n\
+             } \n\
+             } \n\
+             count",
+            12,
+        );
+
+        let kind = |body: &str| {
+            let program = format!("fun main() {{ {} \n }}", body);
+            let errors = execute_module::<()>(&program, &[], &Options::default()).unwrap_err();
+            errors[0].kind().clone()
+        };
+        assert!(matches!(kind("break"), ErrorKind::E521));
+        assert!(matches!(kind("if (true) continue"), ErrorKind::E521));
+        assert!(matches!(
+            kind("'a: while (true) { while (true) break 'b }"),
+            ErrorKind::E520(label) if label == "'b"
+        ));
+    }
+
     #[test]
     fn var_decl() {
         expr_i64("val a = 44 \n a", 44);
@@ -168,11 +611,713 @@ mod test {
         expr_i64("var c = 24 + 1 \n c = c + 2 \n c", 27);
     }
 
+    #[test]
+    fn assert() {
+        expr_none("assert(5 == 5)");
+        let failed = expr_err_at("assert(5 == 7)", "assert");
+        assert!(matches!(failed, ErrorKind::E600));
+        assert!(matches!(expr_err_at("assert(5)", "5)"), ErrorKind::E502));
+    }
+
+    #[test]
+    fn panic() {
+        let panicked = expr_err_at("panic(\"something went wrong\")", "panic");
+        assert!(matches!(panicked, ErrorKind::E601(msg) if msg == "something went wrong"));
+        assert!(matches!(expr_err_at("panic(5)", "5)"), ErrorKind::E509));
+
+        let program = include_str!("../tests/runtime_error.yacari");
+        let errors = execute_module::<i64>(program, &[], &Options::default()).unwrap_err();
+        assert!(matches!(errors[0].kind(), ErrorKind::E601(msg) if msg == "x is too large"));
+        assert_eq!(errors[0].position().offset, program.find("panic").unwrap());
+    }
+
+    #[test]
+    fn backtrace() {
+        let program = include_str!("../tests/runtime_error.yacari");
+        let expected = [("b", 10), ("a", 6), ("main", 2)];
+        let check = |err: &crate::error::Error| {
+            let frames = err
+                .backtrace()
+                .iter()
+                .map(|(name, line)| (name.as_str(), *line))
+                .collect::<Vec<_>>();
+            assert_eq!(frames, expected);
+        };
+
+        let errors = execute_module::<i64>(program, &[], &Options::default()).unwrap_err();
+        check(&errors[0]);
+        // Each run starts with an empty trace
+        let module = compile_program(program, &[], &Options::default()).unwrap();
+        check(&run_compiled::<i64>(&module).unwrap_err()[0]);
+        check(&run_compiled::<i64>(&module).unwrap_err()[0]);
+
+        let errors = execute_module::<()>("fun main() { x }", &[], &Options::default());
+        assert!(errors.unwrap_err()[0].backtrace().is_empty());
+    }
+
+    #[test]
+    fn compiled() {
+        let program = "fun main() -> i64 { 40 + 2 }";
+        let module = compile_program(program, &[], &Options::default()).unwrap();
+        assert_eq!(run_compiled::<i64>(&module).unwrap(), 42);
+        assert_eq!(run_compiled::<i64>(&module).unwrap(), 42);
+
+        let failing = compile_program("fun main() { panic(\"no\") }", &[], &Options::default());
+        let failing = failing.unwrap();
+        assert!(run_compiled::<()>(&failing).is_err());
+        assert!(run_compiled::<()>(&failing).is_err());
+    }
+
+    #[test]
+    fn lints() {
+        let warnings = |body: &str| {
+            let program = format!("fun main() -> i64 {{ {} \n }}", body);
+            let module = compile_program(&program, &[], &Options::default()).unwrap();
+            module.warnings().len()
+        };
+
+        assert_eq!(warnings("val a = 5 \n a"), 0);
+        assert_eq!(warnings("val a = 5 \n 3"), 1);
+        assert_eq!(warnings("val _a = 5 \n 3"), 0);
+        assert_eq!(warnings("var a = 5 \n a = 3 \n 3"), 1);
+        // Shadowing, and the first binding is never read
+        assert_eq!(warnings("val a = 5 \n val a = 3 \n a"), 2);
+        assert_eq!(warnings("val a = 5 \n { val a = a \n a }"), 1);
+    }
+
+    #[test]
+    fn deny_warnings() {
+        let program = "fun main() -> i64 { val a = 5 \n 3 }";
+        let options = Options {
+            deny_warnings: true,
+            ..Options::default()
+        };
+        assert!(compile_program(program, &[], &Options::default()).is_ok());
+        let errors = compile_program(program, &[], &options).unwrap_err();
+        assert!(matches!(errors[..], [ref err] if err.severity() == crate::Severity::Warning));
+        assert_eq!(
+            execute_module::<i64>("fun main() -> i64 { 3 }", &[], &options).unwrap(),
+            3
+        );
+
+        let sources = [(vec![SmolStr::new("main")], program)];
+        let errors = execute_modules::<i64>(&sources, &[], &options).unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn symbols() {
+        let program = "class Point { val x: i64 \n var y: f64 \n fun len() -> f64 { 0.0 } \n static fun origin(a: bool) {} } \n fun id<T>(x: T) -> T { x } \n fun main() -> i64 { 0 }";
+        let module = compile_program(program, &[], &Options::default()).unwrap();
+        let symbols = module
+            .symbols()
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind, s.ty.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            [
+                ("main", SymbolKind::Function, "fun() -> i64"),
+                ("id", SymbolKind::Function, "fun<T>(T) -> T"),
+                ("Point", SymbolKind::Class, "Point"),
+                ("Point.x", SymbolKind::Member, "i64"),
+                ("Point.y", SymbolKind::Member, "f64"),
+                ("Point.len", SymbolKind::Method, "fun() -> f64"),
+                (
+                    "Point.origin",
+                    SymbolKind::ClassFunction,
+                    "fun(bool) -> void"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn module_builder() {
+        let body = block(vec![
+            variable("b", true, name("a")),
+            if_(
+                binary(name("a"), "<", int(0)),
+                binary(int(0), "-", name("a")),
+                Some(name("b")),
+            ),
+        ]);
+        let module = ModuleBuilder::new(vec![SmolStr::new("built")])
+            .class("Point", &[("x", "i64", false)])
+            .function("abs", &[("a", "i64")], Some("i64"), body)
+            .function("main", &[], Some("i64"), call(name("abs"), vec![int(-5)]))
+            .build();
+        let module = compile_ast(module, &[], &Options::default()).unwrap();
+        assert_eq!(run_compiled::<i64>(&module).unwrap(), 5);
+    }
+
+    #[test]
+    fn features() {
+        let program = "@if(debug) { fun level() -> i64 { 2 } } \n fun main() -> i64 { level() }";
+        let options = Options {
+            features: vec![SmolStr::new("debug")],
+            ..Options::default()
+        };
+        assert_eq!(execute_module::<i64>(program, &[], &options).unwrap(), 2);
+        assert!(execute_module::<i64>(program, &[], &Options::default()).is_err());
+    }
+
+    #[test]
+    fn scripts() {
+        let script = |src: &str, expect: Option<Value>| {
+            let mut module = compile_script(src, &[], &Options::default()).unwrap();
+            assert_eq!(run_script(&mut module).unwrap(), expect);
+        };
+        script("1 + 2", Some(Value::Int(3)));
+        script(
+            "fun double(a: i64) -> i64 { a * 2 } \n val a = double(4) \n a + 1",
+            Some(Value::Int(9)),
+        );
+        script("var a = 1 \n while (a < 5) a = a + 1", None);
+        script("2.5", Some(Value::Float(2.5)));
+        script("1 < 2", Some(Value::Bool(true)));
+        script("\"done\"", Some(Value::Str("done")));
+        script("substring(\"é\", 0, 2)", Some(Value::Str("é")));
+        let mut split = compile_script("substring(\"é\", 0, 1)", &[], &Options::default()).unwrap();
+        let errors = run_script(&mut split).unwrap_err();
+        assert!(matches!(errors[0].kind(), ErrorKind::E606));
+        script("", None);
+
+        // Strings created by a run are freed when the next one starts
+        let mut module = compile_script("str(42)", &[], &Options::default()).unwrap();
+        assert_eq!(run_script(&mut module).unwrap(), Some(Value::Str("42")));
+        assert_eq!(run_script(&mut module).unwrap(), Some(Value::Str("42")));
+
+        let errors = compile_script(
+            "class A { val a: i64 } \n A { a: 1 }",
+            &[],
+            &Options::default(),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(errors[0].kind(), ErrorKind::E522(_)));
+        // Programs are not scripts, and scripts have no entry point
+        let mut program = compile_program("fun main() {}", &[], &Options::default()).unwrap();
+        assert!(run_script(&mut program).is_err());
+        assert!(compile_program("1 + 2", &[], &Options::default()).is_err());
+    }
+
+    #[test]
+    fn final_variables() {
+        expr_i64("var a = 5 \n a = 3 \n a", 3);
+        expr_err("val a = 5 \n a = 3 \n a");
+        expr_err("val a = 5 \n { a = 3 } \n a");
+        file_err("fun f(a: i64) -> i64 { a = 3 \n a } \n fun main() -> i64 { f(1) }");
+    }
+
+    #[test]
+    fn strings() {
+        expr_i64("len(\"hello\")", 5);
+        expr_i64("char_at(\"hello\", 1)", 101);
+        // Bytes of UTF-8, "é" being 0xC3 0xA9
+        expr_i64("char_at(\"é\", 1)", 0xA9);
+        expr_i64(
+            "val s = substring(\"hello\", 1, 4) \n len(s) + char_at(s, 2)",
+            111,
+        );
+        expr_i64("len(substring(\"hello\", 2, 2))", 0);
+        file_(
+            "fun first(s: str) -> i64 char_at(s, 0) \n fun main() -> i64 first(\"a\")",
+            97i64,
+            &[],
+        );
+
+        expr_err("char_at(\"hello\", 5)");
+        expr_err("char_at(\"hello\", 0 - 1)");
+        expr_err("substring(\"hello\", 3, 1)");
+        expr_err("substring(\"hello\", 1, 6)");
+        expr_err("len(5)");
+    }
+
+    #[test]
+    fn prelude() {
+        expr_i64("abs(0 - 4) + min(2, 3) + max(2, 3)", 9);
+        expr_i64("len(str(0 - 1234))", 5);
+        expr("sqrt(2.25)", "-> f64", 1.5);
+        expr_err("abs(1.5)");
+        expr_err("min(1)");
+        expr_err("abs((0 - 9223372036854775807) - 1)");
+
+        let program = "fun main() { println(str(42)) \n print(\"end\") }";
+        let (res, output) = run_program_capturing::<()>(program, &[], &Options::default());
+        res.unwrap();
+        assert_eq!(output, "42\nend");
+
+        let options = Options {
+            no_prelude: true,
+            ..Options::default()
+        };
+        let without = execute_module::<i64>("fun main() -> i64 abs(1)", &[], &options);
+        assert!(matches!(
+            without.unwrap_err()[0].kind(),
+            ErrorKind::E503 { .. }
+        ));
+    }
+
+    #[test]
+    fn random() {
+        expr_i64(
+            "seed(7) \n val a = rand_int(0, 1000000) \n val b = rand_int(0, 1000000) \n\
+             seed(7) \n val c = rand_int(0, 1000000) \n\
+             if (a == c) (if (a == b) 2 else 1) else 0",
+            1,
+        );
+        expr_i64(
+            "val lo = 0 - 3 \n var i = 0 \n\
+             while (i < 1000) { \n\
+             val r = rand_int(lo, 3) \n\
+             assert(r >= lo and r < 3) \n\
+             val f = rand() \n\
+             assert(f >= 0.0 and f < 1.0) \n\
+             i = i + 1 \n\
+             } \n\
+             i",
+            1000,
+        );
+        // Without a seed, every run starts the same
+        let program = "fun main() -> i64 rand_int(0, 1000000000)";
+        let first = execute_module::<i64>(program, &[], &Options::default()).unwrap();
+        let second = execute_module::<i64>(program, &[], &Options::default()).unwrap();
+        assert_eq!(first, second);
+        // Also when running the same compiled program again, even after seeding
+        let program = "fun main() -> i64 { val a = rand_int(0, 1000000000) \n seed(a) \n a }";
+        let module = compile_program(program, &[], &Options::default()).unwrap();
+        let first = run_compiled::<i64>(&module).unwrap();
+        assert_eq!(run_compiled::<i64>(&module).unwrap(), first);
+
+        expr_err("rand_int(3, 3)");
+        expr_err("rand_int(4, 3)");
+        expr_err("rand(1)");
+    }
+
+    #[test]
+    fn output() {
+        let program =
+            "fun main() -> i64 { print(\"hello\") \n print(substring(\" world!\", 0, 6)) \n 5 }";
+        let (res, output) = run_program_capturing::<i64>(program, &[], &Options::default());
+        assert_eq!(res.unwrap(), 5);
+        assert_eq!(output, "hello world");
+
+        // Output before a runtime error is kept
+        let program = "fun main() { print(\"a\") \n assert(false) \n print(\"b\") }";
+        let (res, output) = run_program_capturing::<()>(program, &[], &Options::default());
+        assert!(res.is_err());
+        assert_eq!(output, "a");
+
+        let module = compile_program(program, &[], &Options::default()).unwrap();
+        assert!(run_compiled::<()>(&module).is_err());
+        assert!(run_compiled::<()>(&module).is_err());
+        assert_eq!(module.take_output(), "aa");
+        assert_eq!(module.take_output(), "");
+    }
+
+    #[test]
+    fn stats() {
+        let stats = crate::parse_stats("fun main() -> i64 { 1 + 2 }").unwrap();
+        assert_eq!(stats.tokens, 11);
+        assert_eq!(stats.nodes, 4);
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        expr_i64(&nested(50), 1);
+        expr_err(&nested(10_000));
+        expr_err(&"{ ".repeat(10_000));
+        expr_err(&format!("{}1", "-".repeat(10_000)));
+
+        let ty = format!("{}i64{}", "Box<".repeat(10_000), "> ".repeat(10_000));
+        let program = format!("fun f(a: {}) {{}} \n fun main() {{}}", ty);
+        let errors = execute_module::<()>(&program, &[], &Options::default()).unwrap_err();
+        assert!(matches!(errors[0].kind(), ErrorKind::E103));
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let incomplete = |src| matches!(crate::parse_incomplete(src), ParseResult::Incomplete);
+        let error = |src| matches!(crate::parse_incomplete(src), ParseResult::Error(_));
+
+        assert!(matches!(
+            crate::parse_incomplete("fun main() { 5 }"),
+            ParseResult::Complete(_)
+        ));
+        assert!(incomplete("fun main() {\n val a = 5"));
+        assert!(incomplete("fun main() -> i64 { add(1, "));
+        assert!(incomplete("fun main() { panic(\"unclosed"));
+        assert!(incomplete("fun main() { /* comment"));
+        assert!(error("fun main() { 5 ) }"));
+        assert!(error("5 \n fun main() {"));
+    }
+
+    #[test]
+    fn entry_arguments() {
+        let program = "fun main(a: i64, b: f64, neg: bool, s: str) -> i64 { \n\
+                       val x = a + (b as i64) + len(s) \n\
+                       if (neg) 0 - x else x \n\
+                       }";
+        let module = compile_program(program, &[], &Options::default()).unwrap();
+        let run = |args: &[Value]| run_compiled_with_args::<i64>(&module, args);
+
+        let args = [
+            Value::Int(3),
+            Value::Float(2.5),
+            Value::Bool(true),
+            Value::Str("four"),
+        ];
+        assert_eq!(run(&args).unwrap(), -9);
+        let args = [
+            Value::Int(3),
+            Value::Float(2.5),
+            Value::Bool(false),
+            Value::Str(""),
+        ];
+        assert_eq!(run(&args).unwrap(), 5);
+
+        let errors = run(&[Value::Int(1)]).unwrap_err();
+        assert!(matches!(
+            errors[0].kind(),
+            ErrorKind::E507 {
+                expected: 4,
+                found: 1
+            }
+        ));
+        let args = [
+            Value::Int(3),
+            Value::Int(2),
+            Value::Bool(true),
+            Value::Str(""),
+        ];
+        let errors = run(&args).unwrap_err();
+        assert!(matches!(errors[0].kind(), ErrorKind::E508 { pos: 1, .. }));
+    }
+
+    #[test]
+    fn handles() {
+        std::thread_local! {
+            static COUNTERS: RefCell<Handles<i64>> = RefCell::new(Handles::new());
+        }
+        extern "C" fn counter(start: i64) -> u64 {
+            COUNTERS.with(|c| c.borrow_mut().insert(start))
+        }
+        extern "C" fn bump(handle: u64) -> i64 {
+            COUNTERS.with(|c| {
+                let mut counters = c.borrow_mut();
+                let count = counters.get_mut(handle).unwrap();
+                *count += 1;
+                *count
+            })
+        }
+
+        let program = "extern fun counter(start: i64) -> handle \n\
+                       extern fun bump(c: handle) -> i64 \n\
+                       fun main(c: handle) -> i64 { \n\
+                       val own = counter(10) \n\
+                       bump(c) + bump(c) + bump(own) \n\
+                       }";
+        let symbols = [
+            ("counter", counter as *const u8),
+            ("bump", bump as *const u8),
+        ];
+        let module = compile_program(program, &symbols, &Options::default()).unwrap();
+        let handle = counter(0);
+        let res = run_compiled_with_args::<i64>(&module, &[Value::Handle(handle)]);
+        assert_eq!(res.unwrap(), 1 + 2 + 11);
+        let removed = COUNTERS.with(|c| c.borrow_mut().remove(handle));
+        assert_eq!(removed, Some(2));
+
+        let errors = run_compiled_with_args::<i64>(&module, &[Value::Int(1)]).unwrap_err();
+        assert!(matches!(errors[0].kind(), ErrorKind::E508 { pos: 0, .. }));
+        let program = "fun main(c: handle) -> i64 c + 1";
+        let errors = compile_program(program, &[], &Options::default())
+            .err()
+            .unwrap();
+        assert!(matches!(errors[0].kind(), ErrorKind::E500 { .. }));
+    }
+
+    #[test]
+    fn spans() {
+        let program = "fun main() -> i64 { 1 + foo(23) } \n fun foo(a: i64) -> i64 { a }";
+        let span = |needle: &str| {
+            let offset = program.find(needle).unwrap();
+            expr_span_at(program, offset).map(|span| &program[span])
+        };
+
+        assert_eq!(span("23"), Some("23"));
+        assert_eq!(span("foo("), Some("foo"));
+        assert_eq!(span("+"), Some("1 + foo(23)"));
+        assert_eq!(span("{ a"), Some("{ a }"));
+        assert_eq!(span("fun main"), None);
+        assert_eq!(expr_span_at("fun main() {", 11), None);
+    }
+
     #[test]
     fn basic_funcs() {
         file(include_str!("../tests/basic_funcs.yacari"), 422);
     }
 
+    #[test]
+    fn recursion() {
+        file(
+            "fun main() -> i64 { fib(20) } \n\
+             fun fib(n: i64) -> i64 { if (n < 2) n else fib(n - 1) + fib(n - 2) }",
+            6765,
+        );
+        // Locals of each call must be separate from those of the caller
+        file(
+            "fun main() -> i64 { fact(10) } \n\
+             fun fact(n: i64) -> i64 { val m = n \n if (m < 2) 1 else fact(m - 1) * m }",
+            3628800,
+        );
+        file(
+            "fun main() -> bool { is_even(10) } \n\
+             fun is_even(n: i64) -> bool { if (n == 0) true else is_odd(n - 1) } \n\
+             fun is_odd(n: i64) -> bool { if (n == 0) false else is_even(n - 1) }",
+            true,
+        );
+    }
+
+    #[test]
+    fn call_arguments() {
+        let errors =
+            |program: &str| execute_module::<i64>(program, &[], &Options::default()).unwrap_err();
+        let add = "fun add(a: i64, b: i64) -> i64 a + b \n";
+
+        let count = errors(&format!("{} fun main() -> i64 add(1)", add));
+        assert!(matches!(
+            count[0].kind(),
+            ErrorKind::E507 {
+                expected: 2,
+                found: 1
+            }
+        ));
+        let program = format!("{} fun main() -> i64 add(1, true)", add);
+        let ty = errors(&program);
+        assert!(matches!(ty[0].kind(), ErrorKind::E508 { pos: 1, .. }));
+        assert_eq!(ty[0].position().offset, program.find("true").unwrap());
+        // Arguments that are already errors are not reported twice
+        let unknown = errors(&format!("{} fun main() -> i64 add(1, b)", add));
+        assert_eq!(unknown.len(), 1);
+
+        file(
+            "fun id<T>(x: T) -> T x \n fun add(a: i64, b: i64) -> i64 a + b \n\
+             fun main() -> i64 add(id(40), id(2))",
+            42,
+        );
+    }
+
+    #[test]
+    fn generics() {
+        file(include_str!("../tests/generics.yacari"), 42);
+        file_err("fun make<T>() -> i64 { 0 } \n fun main() { make() }");
+        file_err("fun id<T>(x: T) -> T { x } \n fun main() { id(1) + 1.5 }");
+    }
+
+    #[test]
+    fn entry_point() {
+        let run = |program: &str, entry_point: &str| {
+            let options = Options {
+                entry_point: Some(entry_point.into()),
+                ..Options::default()
+            };
+            execute_module::<i64>(program, &[], &options)
+        };
+
+        assert_eq!(run("fun start() -> i64 { 4 }", "start").unwrap(), 4);
+        let missing = run("fun main() -> i64 { 4 }", "start").unwrap_err();
+        assert!(matches!(missing[0].kind(), ErrorKind::E203(name) if name == "start"));
+        let params = run("fun start(a: i64) -> i64 { a }", "start").unwrap_err();
+        assert!(matches!(
+            params[0].kind(),
+            ErrorKind::E507 {
+                expected: 1,
+                found: 0
+            }
+        ));
+    }
+
+    #[test]
+    fn tests() {
+        let program = "fun test_pass() { assert(1 + 1 == 2) } \n\
+                       fun test_fail() { assert(false) } \n\
+                       fun test_panic() { panic(\"oops\") } \n\
+                       fun test_params(a: i64) { assert(false) } \n\
+                       fun helper() { assert(false) } \n\
+                       fun test_equal() { assert_eq(2 * 3, 6); assert_eq(0.5, 1.0 / 2.0) } \n\
+                       fun test_unequal() { assert_eq(len(\"ab\"), 3) } \n\
+                       fun test_after() -> i64 { 5 }";
+        let report = run_tests(program, &[], &Options::default()).unwrap();
+        assert_eq!(report.passed, 2);
+        let failed = report
+            .failed
+            .iter()
+            .map(|(name, err)| (name.as_str(), err.kind().clone()))
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            &failed[..],
+            [
+                ("test_fail", ErrorKind::E600),
+                ("test_panic", ErrorKind::E601(msg)),
+                ("test_params", ErrorKind::E524(_)),
+                ("test_unequal", ErrorKind::E600),
+                ("test_after", ErrorKind::E524(_)),
+            ] if msg == "oops"
+        ));
+        let (_, not_run) = &report.failed[2];
+        assert_eq!(
+            not_run.position().offset,
+            program.find("test_params").unwrap()
+        );
+
+        let errors = run_tests("fun test_a() { x }", &[], &Options::default()).unwrap_err();
+        assert!(matches!(errors[0].kind(), ErrorKind::E503 { .. }));
+        let errors = run_tests(
+            "fun test_a() { assert_eq(1, 1.0); assert_eq(\"a\", \"a\"); assert_eq(1) }",
+            &[],
+            &Options::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            errors.iter().map(|e| e.kind()).collect::<Vec<_>>()[..],
+            [
+                ErrorKind::E500 { .. },
+                ErrorKind::E501 { .. },
+                ErrorKind::E507 {
+                    expected: 2,
+                    found: 1
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn locals_after_params() {
+        // Locals used to share their slots with the parameters
+        let program = "fun main() -> i64 { f(1, 2) } \n\
+                       fun f(a: i64, b: i64) -> i64 { val c = 10 \n a + b + c }";
+        file_::<i64>(program, 13, &[]);
+
+        // Strings take two slots in `local_offsets`, so any overlap shifts the other values
+        let program = "fun main() -> i64 { f(\"abc\", 4) } \n\
+                       fun f(s: str, n: i64) -> i64 { val a = 10 \n val t = \"de\" \n \
+                       len(s) + n + a + len(t) }";
+        file_::<i64>(program, 19, &[]);
+        let ir = compile_ir(program, &Options::default()).unwrap();
+        let ir = ir.borrow();
+        let f = ir.funcs.iter().find(|func| func.name == "f").unwrap();
+        let indices = f
+            .params
+            .iter()
+            .chain(f.locals.iter())
+            .map(|var| var.index)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn ir_dump() {
+        let program = "fun main(a: i64) -> i64 { \n\
+                       var b = a + 1 \n\
+                       while (b < 10) b = b * 2 \n\
+                       if (b == 16) b else 0 \n\
+                       }";
+        let ir = program_to_ir(program, &Options::default()).unwrap();
+        let expected = "\
+fun main($a.0: i64) -> i64 {
+    %0: i64 = $a.0 + 1
+    $b.1 = %0
+    jump .L0
+.L0:
+    %1: bool = $b.1 < 10
+    brz %1, .L1
+    %2: i64 = $b.1 * 2
+    $b.1 = %2
+    jump .L0
+.L1:
+    %3: bool = $b.1 == 16
+    brz %3, .L2
+    jump .L3($b.1)
+.L2:
+    jump .L3(0)
+.L3(%4):
+    return %4
+}
+";
+        assert_eq!(ir, expected);
+        assert!(program_to_ir("fun main() { x }", &Options::default()).is_err());
+    }
+
+    #[test]
+    fn duplicate_definitions() {
+        let errors = |program: &str| execute_module::<()>(program, &[], &Options::default());
+
+        let funcs = errors("fun a() {} \n fun a() {} \n fun main() {}").unwrap_err();
+        assert!(matches!(
+            funcs[0].kind(),
+            ErrorKind::E201 { name, first } if name == "a" && first.line == 1
+        ));
+        assert_eq!(funcs[0].position().line, 2);
+
+        let members = errors("class A { val a: i64 \n val a: i64 } \n fun main() {}").unwrap_err();
+        assert!(matches!(members[0].kind(), ErrorKind::E201 { first, .. } if first.line == 1));
+        assert_eq!(members[0].position().line, 2);
+    }
+
+    #[test]
+    fn linking() {
+        let run = |sources: &[(&str, &str)]| {
+            let sources = sources
+                .iter()
+                .map(|(path, source)| (vec![SmolStr::new(path)], *source))
+                .collect::<Vec<_>>();
+            execute_modules::<i64>(&sources, &[], &Options::default())
+        };
+        let main = "extern fun answer(a: i64) -> i64 \n fun main() -> i64 answer(40)";
+
+        let linked = run(&[("main", main), ("lib", "fun answer(a: i64) -> i64 a + 2")]);
+        assert_eq!(linked.unwrap(), 42);
+
+        let kind = |errors: Vec<Errors>| errors[0][0].kind().clone();
+        let missing = run(&[("main", main)]).unwrap_err();
+        assert!(matches!(kind(missing), ErrorKind::E205(name) if name == "answer"));
+        let mismatch = run(&[("main", main), ("lib", "fun answer() -> i64 2")]);
+        assert!(matches!(kind(mismatch.unwrap_err()), ErrorKind::E206(_)));
+        let twice = run(&[
+            ("main", main),
+            ("a", "fun answer(a: i64) -> i64 a"),
+            ("b", "fun answer(a: i64) -> i64 a"),
+        ]);
+        assert!(matches!(kind(twice.unwrap_err()), ErrorKind::E204(_)));
+
+        // Instances of generic functions are named after their template
+        let generic = "fun id<T>(x: T) -> T x";
+        let templates = run(&[
+            ("main", "fun main() -> i64 0"),
+            ("a", generic),
+            ("b", generic),
+        ]);
+        assert!(matches!(kind(templates.unwrap_err()), ErrorKind::E204(name) if name == "id"));
+
+        // Methods belong to their class, only checking the link itself
+        let link = |sources: &[&str]| {
+            let modules = sources
+                .iter()
+                .map(|source| Parser::new(source).parse(vec![SmolStr::new("m")]).unwrap())
+                .collect::<Vec<_>>();
+            let ir = Compiler::new(modules).consume().unwrap();
+            crate::compiler::linker::link(&ir, &[])
+        };
+        let methods = link(&[
+            "class A { fun get() -> i64 1 } \n fun main() -> i64 0",
+            "class B { fun get() -> i64 2 }",
+        ]);
+        assert!(methods.is_ok());
+    }
+
     #[test]
     fn basic_modules() {
         directory(
@@ -182,6 +1327,43 @@ mod test {
         );
     }
 
+    #[test]
+    fn struct_literals() {
+        #[repr(C)]
+        #[derive(Debug, PartialEq)]
+        struct Outer {
+            a: i64,
+            b: i64,
+        }
+
+        let classes =
+            "class Inner { val b: i64 } \n class Outer { val a: i64 \n val inner: Inner } \n";
+        file(
+            &format!(
+                "{} fun main() -> Outer {{ val o = Outer {{ inner: Inner {{ b: 2 }}, a: 1 }} \n o }}",
+                classes
+            ),
+            Outer { a: 1, b: 2 },
+        );
+
+        let err = |literal: &str| {
+            let program = format!("{} fun main() {{ {} \n }}", classes, literal);
+            let errors = execute_module::<()>(&program, &[], &Options::default()).unwrap_err();
+            errors[0].kind().clone()
+        };
+        assert!(matches!(err("Inner {}"), ErrorKind::E519 { .. }));
+        assert!(matches!(
+            err("Inner { b: 1, c: 2 }"),
+            ErrorKind::E517 { .. }
+        ));
+        assert!(matches!(
+            err("Inner { b: 1, b: 2 }"),
+            ErrorKind::E201 { .. }
+        ));
+        assert!(matches!(err("Inner { b: true }"), ErrorKind::E518 { .. }));
+        assert!(matches!(err("i64 { b: 1 }"), ErrorKind::E516(_)));
+    }
+
     #[test]
     fn basic_ffi() {
         #[repr(C)]
@@ -0,0 +1,66 @@
+use crate::{parser::Separators, smol_str::SmolStr};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Options controlling how programs are compiled and run.
+#[derive(Clone, Default)]
+pub struct Options {
+    /// Behavior of integer arithmetic on overflow.
+    pub arithmetic: ArithmeticMode,
+    /// Where output of `print` goes. If not set, it is collected
+    /// and can be retrieved with `CompiledModule::take_output`.
+    pub output: Option<fn(&str)>,
+    /// How statements inside blocks must be separated.
+    pub separators: Separators,
+    /// Name of the function to run, `main` if not set.
+    pub entry_point: Option<SmolStr>,
+    /// Do not provide the prelude of builtin functions like `print`,
+    /// leaving only functions defined by the program and the host.
+    pub no_prelude: bool,
+    /// Fail compilation on warnings like unused variables,
+    /// returning them as errors.
+    pub deny_warnings: bool,
+    /// Features enabled for the program, which keep the declarations
+    /// inside `@if(feature) { ... }`. Declarations of other features are removed.
+    pub features: Vec<SmolStr>,
+}
+
+impl Options {
+    /// Returns the name of the function to run.
+    pub fn entry_point(&self) -> &str {
+        self.entry_point.as_deref().unwrap_or("main")
+    }
+}
+
+// Higher-ranked function pointers do not implement Debug
+impl fmt::Debug for Options {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Options")
+            .field("arithmetic", &self.arithmetic)
+            .field("output", &self.output.map(|_| "fn(&str)"))
+            .field("separators", &self.separators)
+            .field("entry_point", &self.entry_point)
+            .field("no_prelude", &self.no_prelude)
+            .field("deny_warnings", &self.deny_warnings)
+            .field("features", &self.features)
+            .finish()
+    }
+}
+
+/// How integer `+`, `-` and `*` behave when the result
+/// does not fit into the type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArithmeticMode {
+    /// Wrap around on overflow.
+    Wrapping,
+    /// Clamp the result to the bounds of the type.
+    Saturating,
+    /// Raise a runtime error on overflow.
+    Checked,
+}
+
+impl Default for ArithmeticMode {
+    fn default() -> Self {
+        ArithmeticMode::Checked
+    }
+}
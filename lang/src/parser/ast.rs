@@ -1,5 +1,5 @@
-use crate::{lexer::Token, smol_str::SmolStr};
-use alloc::{boxed::Box, vec::Vec};
+use crate::{error::Position, lexer::Token, smol_str::SmolStr};
+use alloc::{boxed::Box, vec, vec::Vec};
 
 #[derive(Debug)]
 pub struct Module {
@@ -8,47 +8,104 @@ pub struct Module {
     pub classes: Vec<Class>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Class {
     pub name: Token,
+    pub type_params: Vec<Token>,
     pub members: Vec<Member>,
     pub methods: Vec<Function>,
     pub functions: Vec<Function>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Member {
     pub name: Token,
     pub ty: Type,
     pub mutable: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Function {
     pub name: Token,
+    pub type_params: Vec<Token>,
     pub params: Vec<Parameter>,
     pub ret_type: Option<Type>,
     pub body: Option<Expr>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Parameter {
     pub name: SmolStr,
     pub ty: Type,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Type {
     pub name: Token,
+    pub args: Vec<Type>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Expr {
     pub ty: Box<EExpr>, // TODO use a bump allocator ideally
-    pub start: usize,
+    pub start: Position,
+    /// Byte offset just past the end of the expression.
+    pub end: usize,
 }
 
-#[derive(Debug)]
+impl Expr {
+    /// Does the source of this expression contain the given byte offset?
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start.offset <= offset && offset < self.end
+    }
+
+    /// Returns all direct subexpressions.
+    pub fn children(&self) -> Vec<&Expr> {
+        match &*self.ty {
+            EExpr::Literal(_) | EExpr::Identifier(_) | EExpr::Break(_) | EExpr::Continue(_) => {
+                Vec::new()
+            }
+            EExpr::Variable { value, .. } => vec![value],
+            EExpr::Block(exprs) => exprs.iter().collect(),
+            EExpr::If { cond, then, els } => {
+                let mut children = vec![cond, then];
+                children.extend(els);
+                children
+            }
+            EExpr::While { cond, body, .. } => vec![cond, body],
+            EExpr::Binary { left, right, .. } => vec![left, right],
+            EExpr::Unary { right, .. } => vec![right],
+            EExpr::Cast { expr, .. } => vec![expr],
+            EExpr::Call { callee, args } => {
+                let mut children = vec![callee];
+                children.extend(args);
+                children
+            }
+            EExpr::StructLit { fields, .. } => fields.iter().map(|(_, value)| value).collect(),
+            EExpr::Assert(expr) | EExpr::Panic(expr) => vec![expr],
+        }
+    }
+}
+
+/// Find the innermost expression containing the given byte offset,
+/// searching all function and method bodies of the module.
+pub fn node_at(module: &Module, offset: usize) -> Option<&Expr> {
+    let functions = module.functions.iter().chain(
+        module
+            .classes
+            .iter()
+            .flat_map(|cls| cls.methods.iter().chain(cls.functions.iter())),
+    );
+    let mut node = functions
+        .filter_map(|func| func.body.as_ref())
+        .find(|body| body.contains(offset))?;
+    while let Some(child) = node.children().into_iter().find(|c| c.contains(offset)) {
+        node = child;
+    }
+    Some(node)
+}
+
+#[derive(Debug, Clone)]
 pub enum EExpr {
     Literal(Literal),
 
@@ -69,6 +126,8 @@ pub enum EExpr {
     },
 
     While {
+        /// Label like `'outer` to break out of or continue the loop from within nested loops.
+        label: Option<Token>,
         cond: Expr,
         body: Expr,
     },
@@ -84,10 +143,31 @@ pub enum EExpr {
         right: Expr,
     },
 
+    Cast {
+        expr: Expr,
+        ty: Type,
+    },
+
     Call {
         callee: Expr,
         args: Vec<Expr>,
     },
+
+    /// A class literal like `Point { x: 1, y: 2 }`, giving the value of each member.
+    StructLit {
+        ty: Type,
+        fields: Vec<(Token, Expr)>,
+    },
+
+    Assert(Expr),
+
+    Panic(Expr),
+
+    /// Leave the innermost loop, or the one with the given label.
+    Break(Option<Token>),
+
+    /// Jump to the condition of the innermost loop, or the one with the given label.
+    Continue(Option<Token>),
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,220 @@
+//! Construction of modules without source text, for generated programs
+//! and tests needing specific syntax trees.
+//! Names, types and operators are given as source text and lexed
+//! into tokens. Positions are left at their default, since there is
+//! no source to point into.
+
+use crate::{
+    error::Position,
+    lexer::{Lexer, TKind, Token},
+    parser::ast::{Class, EExpr, Expr, Function, Literal, Member, Module, Parameter, Type},
+    smol_str::SmolStr,
+};
+use alloc::{boxed::Box, vec::Vec};
+
+/// Builds a module from functions and classes, resulting in the same
+/// syntax tree the parser would produce for the equivalent source.
+pub struct ModuleBuilder {
+    module: Module,
+}
+
+impl ModuleBuilder {
+    /// Add a function. `params` are given as `(name, type)`,
+    /// a missing return type makes the function return nothing.
+    pub fn function(
+        mut self,
+        name: &str,
+        params: &[(&str, &str)],
+        ret: Option<&str>,
+        body: Expr,
+    ) -> Self {
+        let func = function(name, params, ret, Some(body));
+        self.module.functions.push(func);
+        self
+    }
+
+    /// Add an extern function, which must be provided by the symbol table.
+    pub fn extern_function(
+        mut self,
+        name: &str,
+        params: &[(&str, &str)],
+        ret: Option<&str>,
+    ) -> Self {
+        let func = function(name, params, ret, None);
+        self.module.functions.push(func);
+        self
+    }
+
+    /// Add a class with the given members as `(name, type, mutable)`.
+    pub fn class(mut self, name: &str, members: &[(&str, &str, bool)]) -> Self {
+        let members = members
+            .iter()
+            .map(|&(name, ty, mutable)| Member {
+                name: ident(name),
+                ty: typ(ty),
+                mutable,
+            })
+            .collect();
+        self.module.classes.push(Class {
+            name: ident(name),
+            type_params: Vec::new(),
+            members,
+            methods: Vec::new(),
+            functions: Vec::new(),
+        });
+        self
+    }
+
+    /// Add a method to the class with the given name, which must have been added before.
+    pub fn method(
+        mut self,
+        class: &str,
+        name: &str,
+        params: &[(&str, &str)],
+        ret: Option<&str>,
+        body: Expr,
+    ) -> Self {
+        let func = function(name, params, ret, Some(body));
+        self.class_mut(class).methods.push(func);
+        self
+    }
+
+    fn class_mut(&mut self, name: &str) -> &mut Class {
+        self.module
+            .classes
+            .iter_mut()
+            .find(|cls| cls.name.lex == name)
+            .unwrap_or_else(|| panic!("class '{}' was not added to the module", name))
+    }
+
+    pub fn build(self) -> Module {
+        self.module
+    }
+
+    pub fn new(path: Vec<SmolStr>) -> Self {
+        ModuleBuilder {
+            module: Module {
+                path,
+                functions: Vec::new(),
+                classes: Vec::new(),
+            },
+        }
+    }
+}
+
+fn function(
+    name: &str,
+    params: &[(&str, &str)],
+    ret: Option<&str>,
+    body: Option<Expr>,
+) -> Function {
+    Function {
+        name: ident(name),
+        type_params: Vec::new(),
+        params: params
+            .iter()
+            .map(|&(name, ty)| Parameter {
+                name: SmolStr::new(name),
+                ty: typ(ty),
+            })
+            .collect(),
+        ret_type: ret.map(typ),
+        body,
+    }
+}
+
+/// Lex the given text, which must be exactly one token.
+fn token(lex: &str) -> Token {
+    let mut tokens = Lexer::new(lex);
+    match (tokens.next(), tokens.next()) {
+        (Some(token), None) if token.kind != TKind::Error => token,
+        _ => panic!("'{}' is not a single token", lex),
+    }
+}
+
+fn ident(name: &str) -> Token {
+    let token = token(name);
+    assert_eq!(token.kind, TKind::Identifier, "'{}' is not a name", name);
+    token
+}
+
+fn typ(name: &str) -> Type {
+    Type {
+        name: ident(name),
+        args: Vec::new(),
+    }
+}
+
+fn expr(ty: EExpr) -> Expr {
+    Expr {
+        ty: Box::new(ty),
+        start: Position::default(),
+        end: 0,
+    }
+}
+
+pub fn int(value: i64) -> Expr {
+    expr(EExpr::Literal(Literal::Int(value)))
+}
+
+pub fn float(value: f64) -> Expr {
+    expr(EExpr::Literal(Literal::Float(value)))
+}
+
+pub fn boolean(value: bool) -> Expr {
+    expr(EExpr::Literal(Literal::Bool(value)))
+}
+
+pub fn string(value: &str) -> Expr {
+    expr(EExpr::Literal(Literal::String(SmolStr::new(value))))
+}
+
+/// A variable, function or class referenced by name.
+pub fn name(name: &str) -> Expr {
+    expr(EExpr::Identifier(ident(name)))
+}
+
+/// Declare a variable, which is `val` if `final_` is set and `var` otherwise.
+pub fn variable(name: &str, final_: bool, value: Expr) -> Expr {
+    expr(EExpr::Variable {
+        final_,
+        name: ident(name),
+        value,
+    })
+}
+
+pub fn block(exprs: Vec<Expr>) -> Expr {
+    expr(EExpr::Block(exprs))
+}
+
+pub fn if_(cond: Expr, then: Expr, els: Option<Expr>) -> Expr {
+    expr(EExpr::If { cond, then, els })
+}
+
+pub fn while_(cond: Expr, body: Expr) -> Expr {
+    expr(EExpr::While {
+        label: None,
+        cond,
+        body,
+    })
+}
+
+/// A binary operation like `+` or `==`, including assignment with `=`.
+pub fn binary(left: Expr, op: &str, right: Expr) -> Expr {
+    expr(EExpr::Binary {
+        left,
+        op: token(op),
+        right,
+    })
+}
+
+pub fn unary(op: &str, right: Expr) -> Expr {
+    expr(EExpr::Unary {
+        op: token(op),
+        right,
+    })
+}
+
+pub fn call(callee: Expr, args: Vec<Expr>) -> Expr {
+    expr(EExpr::Call { callee, args })
+}
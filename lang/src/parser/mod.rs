@@ -1,10 +1,11 @@
 pub mod ast;
+pub mod builder;
 
 use crate::{
     error::{
         Error,
-        ErrorKind::{E100, E101, E102},
-        Errors, Res,
+        ErrorKind::{E100, E101, E102, E103, E104, E105, E106, E107, E200},
+        Errors, Position, Res,
     },
     lexer::{Lexer, TKind, TKind::*, Token},
     parser::ast::{EExpr, Expr, Function, Literal, Member, Parameter, Type},
@@ -14,28 +15,99 @@ use alloc::{boxed::Box, vec::Vec};
 pub use ast::Module;
 use core::{mem, str::FromStr};
 
+/// Default limit for how deeply expressions can be nested.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Name of the function holding the top-level statements of a script.
+/// It cannot be written in source, so it never clashes with declarations.
+pub const SCRIPT_FUNCTION: &str = "<script>";
+
 pub struct Parser<'src> {
+    src: &'src str,
     lexer: Lexer<'src>,
     current: Token,
     errors: Errors,
+    /// Current and maximum nesting depth of expressions and types,
+    /// limited to prevent overflowing the stack on malicious input.
+    depth: usize,
+    max_depth: usize,
+    /// How statements inside blocks are separated.
+    separators: Separators,
+    /// Features enabling declarations inside `@if(feature) { ... }`.
+    features: Vec<SmolStr>,
+    /// If statements are allowed between declarations, see `with_script`.
+    script: bool,
+    /// Line of the last consumed token.
+    previous_line: usize,
+    /// Offset just past the last consumed token, used as end of expressions.
+    previous_end: usize,
+}
+
+/// Rule for separating statements inside a block.
+/// A `;` may always be used to end a statement; a closing brace
+/// also ends the last statement of a block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Separators {
+    /// Statements may follow each other directly, as in `{ a b }`.
+    Optional,
+    /// Statements must be ended by a `;` or a newline, making `{ a b }` an error.
+    Required,
+}
+
+impl Default for Separators {
+    fn default() -> Self {
+        Separators::Optional
+    }
+}
+
+/// Result of parsing input that might not be complete yet.
+pub enum ParseResult {
+    Complete(Module),
+    /// The input ended inside an unclosed construct; more input might complete it.
+    Incomplete,
+    Error(Errors),
 }
 
 impl<'src> Parser<'src> {
+    /// Parse input that might still be incomplete, like multi-line input
+    /// typed into a prompt, telling apart errors caused by the input
+    /// ending too early from real syntax errors.
+    pub fn parse_incomplete(self, path: Vec<SmolStr>) -> ParseResult {
+        let src = self.src;
+        match self.parse(path) {
+            Ok(module) => ParseResult::Complete(module),
+            Err(errors) if errors.iter().all(|e| Self::is_premature_end(src, e)) => {
+                ParseResult::Incomplete
+            }
+            Err(errors) => ParseResult::Error(errors),
+        }
+    }
+
+    /// Is the error caused by the end of input, or by a string or
+    /// comment that is not closed before it?
+    fn is_premature_end(src: &str, error: &Error) -> bool {
+        let rest = &src[error.position().offset.min(src.len())..];
+        rest.trim().is_empty()
+            || (rest.starts_with('"') && !rest[1..].contains('"'))
+            || (rest.starts_with("/*") && !rest.contains("*/"))
+    }
+
     pub fn parse(mut self, path: Vec<SmolStr>) -> Result<Module, Errors> {
         let mut functions = Vec::new();
         let mut classes = Vec::new();
+        let mut statements = Vec::new();
+        let start = self.current.start;
 
         while !self.is_at_end() {
-            match self.advance().kind {
-                TKind::Class => self.make_cls(&mut classes),
-                TKind::Fun => self.make_fn(&mut functions, false),
-                TKind::Extern if self.matches(Fun) => self.make_fn(&mut functions, true),
-                _ => {
-                    self.errors.push(Error::new(self.current.start, E102));
-                    self.synchronize()
-                }
+            if self.script && !self.check_(&[TKind::Class, Fun, Extern, At]) {
+                self.make_statement(&mut statements);
+            } else {
+                self.declaration(&mut functions, &mut classes);
             }
         }
+        if self.script {
+            functions.push(self.script_function(start, statements));
+        }
         if self.errors.is_empty() {
             Ok(Module {
                 functions,
@@ -47,6 +119,93 @@ impl<'src> Parser<'src> {
         }
     }
 
+    fn declaration(&mut self, functions: &mut Vec<Function>, classes: &mut Vec<ast::Class>) {
+        match self.advance().kind {
+            TKind::Class => self.make_cls(classes),
+            TKind::Fun => self.make_fn(functions, false),
+            TKind::Extern if self.matches(Fun) => self.make_fn(functions, true),
+            TKind::At => {
+                if let Err(e) = self.conditional(functions, classes) {
+                    self.errors.push(e);
+                    self.synchronize()
+                }
+            }
+            _ => {
+                self.errors.push(Error::new(self.current.start, E102));
+                self.synchronize()
+            }
+        }
+    }
+
+    /// Parse declarations inside `@if(feature) { ... }`, which are only kept
+    /// if the feature is enabled. Excluded declarations must still parse.
+    fn conditional(
+        &mut self,
+        functions: &mut Vec<Function>,
+        classes: &mut Vec<ast::Class>,
+    ) -> Res<()> {
+        self.consume(If)?;
+        self.consume(LeftParen)?;
+        let feature = self.consume(Identifier)?;
+        self.consume(RightParen)?;
+        self.consume(LeftBrace)?;
+
+        let (mut inner_functions, mut inner_classes) = (Vec::new(), Vec::new());
+        while !self.check(RightBrace) && !self.is_at_end() {
+            self.declaration(&mut inner_functions, &mut inner_classes);
+        }
+        self.consume(RightBrace)?;
+
+        if self.features.contains(&feature.lex) {
+            functions.append(&mut inner_functions);
+            classes.append(&mut inner_classes);
+        }
+        Ok(())
+    }
+
+    fn make_statement(&mut self, statements: &mut Vec<Expr>) {
+        match self.statement() {
+            Ok(stmt) => statements.push(stmt),
+            Err(e) => {
+                self.errors.push(e);
+                self.synchronize()
+            }
+        }
+    }
+
+    /// A top-level statement of a script, separated like statements in blocks.
+    fn statement(&mut self) -> Res<Expr> {
+        let stmt = self.higher_expr()?;
+        if !self.matches(Semicolon)
+            && !self.is_at_end()
+            && self.separators == Separators::Required
+            && !self.on_new_line()
+        {
+            return Err(Error::new(self.current.start, E104));
+        }
+        Ok(stmt)
+    }
+
+    fn script_function(&self, start: Position, statements: Vec<Expr>) -> Function {
+        let name = Token {
+            kind: Identifier,
+            lex: SmolStr::new_inline(SCRIPT_FUNCTION),
+            start,
+        };
+        let body = Expr {
+            ty: Box::new(EExpr::Block(statements)),
+            start,
+            end: self.previous_end,
+        };
+        Function {
+            name,
+            type_params: Vec::new(),
+            params: Vec::new(),
+            ret_type: None,
+            body: Some(body),
+        }
+    }
+
     fn make_cls(&mut self, cls: &mut Vec<ast::Class>) {
         match self.class() {
             Ok(f) => cls.push(f),
@@ -69,6 +228,7 @@ impl<'src> Parser<'src> {
 
     fn class(&mut self) -> Res<ast::Class> {
         let name = self.consume(Identifier)?;
+        let type_params = self.type_params()?;
         self.consume(LeftBrace)?;
 
         let mut members = Vec::new();
@@ -87,6 +247,7 @@ impl<'src> Parser<'src> {
 
         Ok(ast::Class {
             name,
+            type_params,
             members,
             methods,
             functions,
@@ -102,6 +263,7 @@ impl<'src> Parser<'src> {
 
     fn function(&mut self, is_ext: bool) -> Res<Function> {
         let name = self.consume(Identifier)?;
+        let type_params = self.type_params()?;
 
         self.consume(LeftParen)?;
         let mut params = Vec::new();
@@ -131,6 +293,7 @@ impl<'src> Parser<'src> {
         };
         Ok(Function {
             name,
+            type_params,
             params,
             ret_type,
             body,
@@ -157,15 +320,34 @@ impl<'src> Parser<'src> {
                 name,
                 value,
             }),
+            end: self.previous_end,
         })
     }
 
     fn expression(&mut self) -> Res<Expr> {
-        match self.current.kind {
+        self.enter()?;
+        let expr = match self.current.kind {
             LeftBrace => self.block(),
             If => self.if_expr(),
-            While => self.while_stmt(),
+            While => self.while_stmt(None),
+            Label => self.labeled_loop(),
+            Break | Continue => self.jump_expr(),
+            Assert => self.assert_expr(),
+            Panic => self.panic_expr(),
             _ => self.binary(0),
+        };
+        self.depth -= 1;
+        expr
+    }
+
+    /// Enter a nested expression or type, erroring if nested too deeply.
+    /// The depth is only increased if entering succeeds.
+    fn enter(&mut self) -> Res<()> {
+        if self.depth >= self.max_depth {
+            Err(Error::new(self.current.start, E103))
+        } else {
+            self.depth += 1;
+            Ok(())
         }
     }
 
@@ -173,12 +355,20 @@ impl<'src> Parser<'src> {
         let brace = self.advance();
         let mut exprs = Vec::new();
         while !self.is_at_end() && !self.check(RightBrace) {
-            exprs.push(self.higher_expr()?)
+            exprs.push(self.higher_expr()?);
+            if !self.matches(Semicolon)
+                && !self.check(RightBrace)
+                && self.separators == Separators::Required
+                && !self.on_new_line()
+            {
+                return Err(Error::new(self.current.start, E104));
+            }
         }
         self.consume(RightBrace)?;
         Ok(Expr {
             ty: Box::new(EExpr::Block(exprs)),
             start: brace.start,
+            end: self.previous_end,
         })
     }
 
@@ -196,23 +386,78 @@ impl<'src> Parser<'src> {
         Ok(Expr {
             ty: Box::new(EExpr::If { cond, then, els }),
             start,
+            end: self.previous_end,
         })
     }
 
-    fn while_stmt(&mut self) -> Res<Expr> {
-        let start = self.advance().start;
+    fn while_stmt(&mut self, label: Option<Token>) -> Res<Expr> {
+        let start = label.as_ref().map_or(self.current.start, |l| l.start);
+        self.consume(While)?;
         self.consume(LeftParen)?;
         let cond = self.expression()?;
         self.consume(RightParen)?;
         let body = self.expression()?;
         Ok(Expr {
-            ty: Box::new(EExpr::While { cond, body }),
+            ty: Box::new(EExpr::While { label, cond, body }),
+            start,
+            end: self.previous_end,
+        })
+    }
+
+    /// Parse a loop with a label like `'outer: while (...) ...`.
+    fn labeled_loop(&mut self) -> Res<Expr> {
+        let label = self.advance();
+        self.consume(Colon)?;
+        self.while_stmt(Some(label))
+    }
+
+    /// Parse `break` or `continue`, followed by an optional label.
+    fn jump_expr(&mut self) -> Res<Expr> {
+        let keyword = self.advance();
+        // A label on the next line starts the next loop instead
+        let label = if self.check(Label) && !self.on_new_line() {
+            Some(self.advance())
+        } else {
+            None
+        };
+        let ty = match keyword.kind {
+            Break => EExpr::Break(label),
+            _ => EExpr::Continue(label),
+        };
+        Ok(Expr {
+            ty: Box::new(ty),
+            start: keyword.start,
+            end: self.previous_end,
+        })
+    }
+
+    fn assert_expr(&mut self) -> Res<Expr> {
+        let start = self.advance().start;
+        self.consume(LeftParen)?;
+        let cond = self.expression()?;
+        self.consume(RightParen)?;
+        Ok(Expr {
+            ty: Box::new(EExpr::Assert(cond)),
+            start,
+            end: self.previous_end,
+        })
+    }
+
+    fn panic_expr(&mut self) -> Res<Expr> {
+        let start = self.advance().start;
+        self.consume(LeftParen)?;
+        let msg = self.expression()?;
+        self.consume(RightParen)?;
+        Ok(Expr {
+            ty: Box::new(EExpr::Panic(msg)),
             start,
+            end: self.previous_end,
         })
     }
 
     fn binary(&mut self, minimum_binding_power: u8) -> Res<Expr> {
         let mut expr = self.unary()?;
+        let mut grouped = self.is_grouped(&expr);
 
         while let Some((lbp, rbp)) = self.current.kind.infix_binding_power() {
             if lbp < minimum_binding_power {
@@ -220,7 +465,23 @@ impl<'src> Parser<'src> {
             }
 
             let op = self.advance();
+            if op.kind == As {
+                let ty = self.typ()?;
+                expr = Expr {
+                    start: expr.start,
+                    ty: Box::new(EExpr::Cast { expr, ty }),
+                    end: self.previous_end,
+                };
+                grouped = false;
+                continue;
+            }
+
             let right = self.binary(rbp)?;
+            if op.kind.is_comparison() {
+                Self::check_unchained(&expr, grouped)?;
+                Self::check_unchained(&right, self.is_grouped(&right))?;
+            }
+            grouped = false;
             expr = Expr {
                 start: expr.start,
                 ty: Box::new(EExpr::Binary {
@@ -228,19 +489,41 @@ impl<'src> Parser<'src> {
                     op,
                     right,
                 }),
+                end: self.previous_end,
             };
         }
 
         Ok(expr)
     }
 
+    /// Was the expression just parsed wrapped in parentheses?
+    /// They are not part of its span.
+    fn is_grouped(&self, expr: &Expr) -> bool {
+        self.previous_end > expr.end
+    }
+
+    /// Reject an operand of a comparison which is a comparison itself,
+    /// like in `a < b < c`, unless it is parenthesized.
+    fn check_unchained(operand: &Expr, grouped: bool) -> Res<()> {
+        match &*operand.ty {
+            EExpr::Binary { op, .. } if op.kind.is_comparison() && !grouped => {
+                Err(Error::new(op.start, E106))
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn unary(&mut self) -> Res<Expr> {
         if let Some(rbp) = self.current.kind.prefix_binding_power() {
             let op = self.advance();
-            let right = self.binary(rbp)?;
+            self.enter()?;
+            let right = self.binary(rbp);
+            self.depth -= 1;
+            let right = right?;
             Ok(Expr {
                 start: op.start,
                 ty: Box::new(EExpr::Unary { op, right }),
+                end: self.previous_end,
             })
         } else {
             self.call()
@@ -266,6 +549,7 @@ impl<'src> Parser<'src> {
                     expr = Expr {
                         start: expr.start,
                         ty: Box::new(EExpr::Call { callee: expr, args }),
+                        end: self.previous_end,
                     }
                 }
 
@@ -280,32 +564,62 @@ impl<'src> Parser<'src> {
             False => Ok(Expr {
                 ty: Box::new(EExpr::Literal(Literal::Bool(false))),
                 start: self.advance().start,
+                end: self.previous_end,
             }),
             True => Ok(Expr {
                 ty: Box::new(EExpr::Literal(Literal::Bool(true))),
                 start: self.advance().start,
+                end: self.previous_end,
             }),
-            String => Ok(Expr {
-                start: self.current.start,
-                ty: Box::new(EExpr::Literal(Literal::String(self.advance().lex))),
-            }),
-            Int => Ok(Expr {
-                ty: Box::new(EExpr::Literal(Literal::Int(
-                    i64::from_str(&self.current.lex).unwrap(),
-                ))),
-                start: self.advance().start,
-            }),
+            String => {
+                let token = self.advance();
+                // Strip the surrounding quotes
+                let content = &token.lex[1..token.lex.len() - 1];
+                Ok(Expr {
+                    start: token.start,
+                    ty: Box::new(EExpr::Literal(Literal::String(SmolStr::new(content)))),
+                    end: self.previous_end,
+                })
+            }
+            Int => {
+                let token = self.advance();
+                let digits_end = token
+                    .lex
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(token.lex.len());
+                let (digits, suffix) = token.lex.split_at(digits_end);
+                // Only i64 exists so far
+                if !suffix.is_empty() && suffix != "i64" {
+                    return Err(Error::new(token.start, E200(SmolStr::new(suffix))));
+                }
+                let int = i64::from_str(digits)
+                    .map_err(|_| Error::new(token.start, E105(token.lex.clone())))?;
+                Ok(Expr {
+                    start: token.start,
+                    ty: Box::new(EExpr::Literal(Literal::Int(int))),
+                    end: self.previous_end,
+                })
+            }
             Float => Ok(Expr {
                 ty: Box::new(EExpr::Literal(Literal::Float(
                     f64::from_str(&self.current.lex).unwrap(),
                 ))),
                 start: self.advance().start,
+                end: self.previous_end,
             }),
 
-            Identifier => Ok(Expr {
-                start: self.current.start,
-                ty: Box::new(EExpr::Identifier(self.advance())),
-            }),
+            Identifier => {
+                let name = self.advance();
+                // A brace on the next line starts a block instead
+                if self.check(LeftBrace) && !self.on_new_line() {
+                    return self.struct_lit(name);
+                }
+                Ok(Expr {
+                    start: name.start,
+                    ty: Box::new(EExpr::Identifier(name)),
+                    end: self.previous_end,
+                })
+            }
             LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
@@ -317,9 +631,63 @@ impl<'src> Parser<'src> {
         }
     }
 
+    /// Parse the fields of a class literal like `Point { x: 1, y: 2 }`,
+    /// after its name.
+    fn struct_lit(&mut self, name: Token) -> Res<Expr> {
+        self.consume(LeftBrace)?;
+        let mut fields = Vec::new();
+        while !self.check(RightBrace) {
+            let field = self.consume(Identifier)?;
+            self.consume(Colon)?;
+            fields.push((field, self.expression()?));
+            if !self.matches(Comma) {
+                break;
+            }
+        }
+        self.consume(RightBrace)?;
+        Ok(Expr {
+            start: name.start,
+            ty: Box::new(EExpr::StructLit {
+                ty: Type {
+                    name,
+                    args: Vec::new(),
+                },
+                fields,
+            }),
+            end: self.previous_end,
+        })
+    }
+
+    fn type_params(&mut self) -> Res<Vec<Token>> {
+        let mut params = Vec::new();
+        if self.matches(Less) {
+            loop {
+                params.push(self.consume(Identifier)?);
+                if !self.matches(Comma) {
+                    break;
+                }
+            }
+            self.consume(Greater)?;
+        }
+        Ok(params)
+    }
+
     fn typ(&mut self) -> Res<Type> {
         let name = self.consume(Identifier)?;
-        Ok(Type { name })
+        let mut args = Vec::new();
+        if self.matches(Less) {
+            loop {
+                self.enter()?;
+                let arg = self.typ();
+                self.depth -= 1;
+                args.push(arg?);
+                if !self.matches(Comma) {
+                    break;
+                }
+            }
+            self.consume(Greater)?;
+        }
+        Ok(Type { name, args })
     }
 
     fn matches(&mut self, kind: TKind) -> bool {
@@ -346,14 +714,45 @@ impl<'src> Parser<'src> {
     }
 
     fn advance(&mut self) -> Token {
-        let next = self.lexer.next().unwrap_or_else(|| Token {
-            kind: TKind::Error,
-            lex: SmolStr::new_inline("\0"),
-            start: self.current.start + 1,
-        });
+        let next = Self::next_token(&mut self.lexer, &mut self.errors);
+        self.previous_line = self.current.start.line;
+        self.previous_end = self.current.span().end;
         mem::replace(&mut self.current, next)
     }
 
+    /// Returns the next token, or an `Error` token at the end of input.
+    /// Characters the lexer does not recognize are reported and skipped.
+    fn next_token(lexer: &mut Lexer, errors: &mut Errors) -> Token {
+        loop {
+            match lexer.next() {
+                Some(token) if Self::is_stray(&token) => {
+                    let ch = token.lex.chars().next().unwrap();
+                    errors.push(Error::new(token.start, E107(ch)));
+                }
+                Some(token) => return token,
+                None => {
+                    return Token {
+                        kind: TKind::Error,
+                        lex: SmolStr::new_inline("\0"),
+                        start: lexer.eof_position(),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Is the token a character the lexer does not recognize?
+    /// Unclosed strings and comments are errors as well,
+    /// but left to `is_premature_end` to tell apart.
+    fn is_stray(token: &Token) -> bool {
+        token.kind == TKind::Error && !token.lex.starts_with('"') && !token.lex.starts_with("/*")
+    }
+
+    /// Is the current token on a later line than the previous one?
+    fn on_new_line(&self) -> bool {
+        self.current.start.line > self.previous_line
+    }
+
     fn check(&mut self, kind: TKind) -> bool {
         self.current.kind == kind
     }
@@ -372,6 +771,7 @@ impl<'src> Parser<'src> {
     }
 
     fn synchronize(&mut self) {
+        self.depth = 0;
         while !self.is_at_end() {
             match self.advance().kind {
                 Fun => return,
@@ -382,11 +782,264 @@ impl<'src> Parser<'src> {
 
     pub fn new(src: &'src str) -> Self {
         let mut lexer = Lexer::new(src);
-        let current = lexer.next().unwrap();
+        let mut errors = Vec::new();
+        let current = Self::next_token(&mut lexer, &mut errors);
         Self {
+            src,
             lexer,
             current,
-            errors: Vec::new(),
+            errors,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            separators: Separators::Optional,
+            features: Vec::new(),
+            script: false,
+            previous_line: 1,
+            previous_end: 0,
+        }
+    }
+
+    /// Set the limit for how deeply expressions can be nested.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set the rule for separating statements in blocks.
+    pub fn with_separators(mut self, separators: Separators) -> Self {
+        self.separators = separators;
+        self
+    }
+
+    /// Set the features enabling conditional declarations.
+    pub fn with_features(mut self, features: &[SmolStr]) -> Self {
+        self.features = features.to_vec();
+        self
+    }
+
+    /// Parse a script, which may contain statements between its declarations.
+    /// They are collected into a function named `SCRIPT_FUNCTION`,
+    /// which runs them in order and returns the value of the last one.
+    pub fn with_script(mut self, script: bool) -> Self {
+        self.script = script;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        error::{ErrorKind, Errors},
+        parser::{
+            ast::{EExpr, Literal},
+            Module, Parser, Separators,
+        },
+        smol_str::SmolStr,
+    };
+    use alloc::vec::Vec;
+    extern crate std;
+    use std::format;
+
+    fn parse(src: &str) -> Result<Module, Errors> {
+        Parser::new(src).parse(Vec::new())
+    }
+
+    /// Parse a single expression in a function body.
+    fn expr(src: &str) -> EExpr {
+        let module = parse(&format!("fun main() {{ {} }}", src)).unwrap();
+        let body = module.functions[0].body.as_ref().unwrap();
+        match &*body.ty {
+            EExpr::Block(exprs) => (*exprs[0].ty).clone(),
+            _ => panic!("function body is not a block"),
+        }
+    }
+
+    fn err(src: &str) -> ErrorKind {
+        parse(src).unwrap_err().remove(0).kind().clone()
+    }
+
+    #[test]
+    fn literals() {
+        assert!(matches!(expr("true"), EExpr::Literal(Literal::Bool(true))));
+        assert!(matches!(
+            expr("false"),
+            EExpr::Literal(Literal::Bool(false))
+        ));
+        assert!(matches!(expr("42"), EExpr::Literal(Literal::Int(42))));
+        assert!(matches!(expr("42i64"), EExpr::Literal(Literal::Int(42))));
+        assert!(matches!(
+            expr("9223372036854775807"),
+            EExpr::Literal(Literal::Int(i64::MAX))
+        ));
+        assert!(matches!(expr("4.5"), EExpr::Literal(Literal::Float(f)) if f == 4.5));
+        assert!(matches!(expr("\"hi\""), EExpr::Literal(Literal::String(s)) if s == "hi"));
+    }
+
+    #[test]
+    fn expressions() {
+        assert!(matches!(expr("a"), EExpr::Identifier(_)));
+        assert!(matches!(
+            expr("val a = 5"),
+            EExpr::Variable { final_: true, .. }
+        ));
+        assert!(matches!(
+            expr("var a = 5"),
+            EExpr::Variable { final_: false, .. }
+        ));
+        assert!(matches!(expr("{ 5 }"), EExpr::Block(exprs) if exprs.len() == 1));
+        assert!(matches!(
+            expr("if (true) 1 else 2"),
+            EExpr::If { els: Some(_), .. }
+        ));
+        assert!(matches!(expr("if (true) 1"), EExpr::If { els: None, .. }));
+        assert!(matches!(
+            expr("while (false) {}"),
+            EExpr::While { label: None, .. }
+        ));
+        assert!(matches!(
+            expr("'outer: while (true) { break 'outer }"),
+            EExpr::While { label: Some(label), .. } if label.lex == "'outer"
+        ));
+        assert!(matches!(expr("break"), EExpr::Break(None)));
+        assert!(matches!(expr("continue 'a"), EExpr::Continue(Some(_))));
+        assert!(matches!(expr("1 + 2"), EExpr::Binary { .. }));
+        assert!(matches!(expr("-1"), EExpr::Unary { .. }));
+        assert!(matches!(expr("1 as f64"), EExpr::Cast { .. }));
+        assert!(matches!(expr("f(1, 2)"), EExpr::Call { args, .. } if args.len() == 2));
+        assert!(matches!(
+            expr("P { x: 1, y: Q { z: 2 }, }"),
+            EExpr::StructLit { fields, .. } if fields.len() == 2
+        ));
+        assert!(matches!(expr("P {}"), EExpr::StructLit { fields, .. } if fields.is_empty()));
+        assert!(matches!(expr("a \n { 1 }"), EExpr::Identifier(_)));
+        assert!(matches!(expr("assert(true)"), EExpr::Assert(_)));
+        assert!(matches!(expr("panic(\"no\")"), EExpr::Panic(_)));
+    }
+
+    #[test]
+    fn precedence() {
+        match expr("1 + 2 * 3") {
+            EExpr::Binary { right, .. } => assert!(matches!(*right.ty, EExpr::Binary { .. })),
+            _ => panic!("expected binary expression"),
+        }
+    }
+
+    #[test]
+    fn declarations() {
+        let module = parse(
+            "class A<T> { val a: T \n var b: i64 \n fun get() -> T a } \n extern fun ext(a: i64)",
+        )
+        .unwrap();
+        let class = &module.classes[0];
+        assert_eq!(class.type_params.len(), 1);
+        assert_eq!(class.members.len(), 2);
+        assert_eq!(class.methods.len(), 1);
+        assert!(module.functions[0].body.is_none());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(err("fun main() {"), ErrorKind::E100 { .. }));
+        assert!(matches!(err("fun main() { ) }"), ErrorKind::E101));
+        assert!(matches!(err("5"), ErrorKind::E102));
+        let overflow = err("fun main() { 9223372036854775808 }");
+        assert!(matches!(overflow, ErrorKind::E105(lex) if lex == "9223372036854775808"));
+        let huge = err("fun main() { 99999999999999999999999i64 }");
+        assert!(matches!(huge, ErrorKind::E105(_)));
+        assert!(matches!(err("fun main() { 5u8 }"), ErrorKind::E200(ty) if ty == "u8"));
+        // Errors in multiple declarations are all reported
+        assert_eq!(
+            parse("fun a() { ) } \n fun b() { ) }").unwrap_err().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn chained_comparisons() {
+        assert!(matches!(err("fun main() { 1 < 2 < 3 }"), ErrorKind::E106));
+        assert!(matches!(
+            err("fun main() { 1 == 2 == true }"),
+            ErrorKind::E106
+        ));
+        assert!(matches!(
+            err("fun main() { 1 < 2 == true }"),
+            ErrorKind::E106
+        ));
+        assert!(matches!(
+            err("fun main() { (1) < 2 >= (3) }"),
+            ErrorKind::E106
+        ));
+        let pos = parse("fun main() { 1 < 2 < 3 }").unwrap_err()[0].position();
+        assert_eq!(pos.col, 20);
+
+        assert!(matches!(expr("(1 < 2) == true"), EExpr::Binary { .. }));
+        assert!(matches!(expr("true == (1 < 2)"), EExpr::Binary { .. }));
+        assert!(matches!(expr("1 < 2 and 2 < 3"), EExpr::Binary { .. }));
+    }
+
+    #[test]
+    fn stray_characters() {
+        let errors = parse("fun main() { 1 $ 2 } \n fun b() { ` }").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0].kind(), ErrorKind::E107('$')));
+        assert_eq!(errors[0].position().col, 16);
+        assert!(matches!(errors[1].kind(), ErrorKind::E107('`')));
+        // Unclosed strings and comments are not stray characters
+        assert!(!matches!(err("fun main() { \"a }"), ErrorKind::E107(_)));
+        assert!(!matches!(err("fun main() { /* }"), ErrorKind::E107(_)));
+    }
+
+    #[test]
+    fn separators() {
+        let block_len = |src: &str, separators: Separators| -> Result<usize, Errors> {
+            let src = format!("fun main() {}", src);
+            let module = Parser::new(&src)
+                .with_separators(separators)
+                .parse(Vec::new())?;
+            match &*module.functions[0].body.as_ref().unwrap().ty {
+                EExpr::Block(exprs) => Ok(exprs.len()),
+                _ => panic!("function body is not a block"),
+            }
+        };
+
+        for &rule in &[Separators::Optional, Separators::Required] {
+            assert_eq!(block_len("{ a; b }", rule).unwrap(), 2);
+            assert_eq!(block_len("{ a; b; }", rule).unwrap(), 2);
+            assert_eq!(block_len("{ a \n b }", rule).unwrap(), 2);
+            assert_eq!(block_len("{ a \n\n b \n }", rule).unwrap(), 2);
         }
+
+        assert_eq!(block_len("{ a b }", Separators::Optional).unwrap(), 2);
+        let errors = block_len("{ a b }", Separators::Required).unwrap_err();
+        assert!(matches!(errors[0].kind(), ErrorKind::E104));
+    }
+
+    #[test]
+    fn conditional_declarations() {
+        let src = "@if(debug) { fun dump() {} \n class Trace {} \n @if(verbose) { fun log() {} } } \n fun main() {}";
+        let names = |features: &[&str]| {
+            let features = features.iter().map(SmolStr::new).collect::<Vec<_>>();
+            let module = Parser::new(src)
+                .with_features(&features)
+                .parse(Vec::new())
+                .unwrap();
+            let functions = module.functions.iter().map(|f| f.name.lex.clone());
+            let classes = module.classes.iter().map(|c| c.name.lex.clone());
+            functions.chain(classes).collect::<Vec<_>>()
+        };
+        assert_eq!(names(&[]), ["main"]);
+        assert_eq!(names(&["verbose"]), ["main"]);
+        assert_eq!(names(&["debug"]), ["dump", "main", "Trace"]);
+        assert_eq!(
+            names(&["debug", "verbose"]),
+            ["dump", "log", "main", "Trace"]
+        );
+
+        // Excluded declarations must still be valid
+        assert!(parse("@if(debug) { fun a() { ) } }").is_err());
+        assert!(matches!(
+            err("@when(debug) { fun a() {} }"),
+            ErrorKind::E100 { .. }
+        ));
     }
 }
@@ -0,0 +1,80 @@
+use crate::{
+    error::Errors,
+    lexer::Lexer,
+    parser::{
+        ast::{EExpr, Expr, Function, Module},
+        Parser,
+    },
+    smol_str::SmolStr,
+};
+use alloc::vec;
+use std::time::{Duration, Instant};
+
+/// Statistics about lexing and parsing a source file.
+#[derive(Debug, Clone)]
+pub struct ParseStats {
+    /// Amount of tokens in the source.
+    pub tokens: usize,
+    /// Amount of expression nodes in the resulting AST.
+    pub nodes: usize,
+    /// Time taken to lex the source on its own.
+    pub lex_duration: Duration,
+    /// Time taken to parse the source, including lexing.
+    pub duration: Duration,
+}
+
+/// Lex and parse the given source, measuring the time taken.
+pub fn parse_stats(src: &str) -> Result<ParseStats, Errors> {
+    let lex_start = Instant::now();
+    let tokens = Lexer::new(src).count();
+    let lex_duration = lex_start.elapsed();
+
+    let start = Instant::now();
+    let module = Parser::new(src).parse(vec![SmolStr::new_inline("stats")])?;
+    let duration = start.elapsed();
+
+    Ok(ParseStats {
+        tokens,
+        nodes: module_nodes(&module),
+        lex_duration,
+        duration,
+    })
+}
+
+fn module_nodes(module: &Module) -> usize {
+    let methods = module
+        .classes
+        .iter()
+        .flat_map(|cls| cls.methods.iter().chain(cls.functions.iter()));
+    module
+        .functions
+        .iter()
+        .chain(methods)
+        .map(function_nodes)
+        .sum()
+}
+
+fn function_nodes(func: &Function) -> usize {
+    func.body.as_ref().map(expr_nodes).unwrap_or(0)
+}
+
+fn expr_nodes(expr: &Expr) -> usize {
+    let children = match &*expr.ty {
+        EExpr::Literal(_) | EExpr::Identifier(_) | EExpr::Break(_) | EExpr::Continue(_) => 0,
+        EExpr::Variable { value, .. } => expr_nodes(value),
+        EExpr::Block(exprs) => exprs.iter().map(expr_nodes).sum(),
+        EExpr::If { cond, then, els } => {
+            expr_nodes(cond) + expr_nodes(then) + els.as_ref().map(expr_nodes).unwrap_or(0)
+        }
+        EExpr::While { cond, body, .. } => expr_nodes(cond) + expr_nodes(body),
+        EExpr::Binary { left, right, .. } => expr_nodes(left) + expr_nodes(right),
+        EExpr::Unary { right, .. } => expr_nodes(right),
+        EExpr::Cast { expr, .. } => expr_nodes(expr),
+        EExpr::Call { callee, args } => {
+            expr_nodes(callee) + args.iter().map(expr_nodes).sum::<usize>()
+        }
+        EExpr::StructLit { fields, .. } => fields.iter().map(|(_, f)| expr_nodes(f)).sum(),
+        EExpr::Assert(expr) | EExpr::Panic(expr) => expr_nodes(expr),
+    };
+    children + 1
+}
@@ -1,24 +1,32 @@
 use crate::{
     compiler::{
         ir,
-        ir::{Constant, Expr, IExpr},
+        ir::{Builtin, Constant, Expr, IExpr},
     },
-    lexer::TKind,
+    error::{
+        Error,
+        ErrorKind::{E600, E601, E602, E603, E604, E605},
+        Position,
+    },
+    lexer::{TKind, Token},
+    options::ArithmeticMode,
     vm::{
         function::FnTranslator,
-        get_or_declare_ir_fn, typesys,
-        typesys::{value, values, CValue},
+        get_or_declare_ir_fn, runtime, typesys,
+        typesys::{value, values, CValue, CLIF_PTR},
     },
 };
 use alloc::vec::Vec;
 use cranelift::prelude::*;
-use cranelift_module::Module;
+use cranelift_module::{Linkage, Module};
 use smallvec::SmallVec;
 
 impl<'b> FnTranslator<'b> {
     pub fn trans_expr(&mut self, expr: &ir::Expr) -> CValue {
         match &*expr.inner {
-            IExpr::Binary { left, op, right } => value(self.binary(left, op.kind, right)),
+            IExpr::Binary { left, op, right } => value(self.binary(left, op, right)),
+
+            IExpr::Constant(Constant::String(string)) => self.string(string),
 
             IExpr::Constant(constant) => value(self.constant(constant)),
 
@@ -39,6 +47,18 @@ impl<'b> FnTranslator<'b> {
 
             IExpr::While { cond, body } => self.while_expr(cond, body),
 
+            IExpr::Break { depth } => {
+                let (_, exit) = self.loops[self.loops.len() - 1 - depth];
+                self.jump_out(exit);
+                values(&[])
+            }
+
+            IExpr::Continue { depth } => {
+                let (head, _) = self.loops[self.loops.len() - 1 - depth];
+                self.jump_out(head);
+                values(&[])
+            }
+
             IExpr::Variable { index, typ } => self.variable_expr(*index, typ),
 
             IExpr::Assign { store, value } => match &*store.inner {
@@ -46,7 +66,20 @@ impl<'b> FnTranslator<'b> {
                 _ => panic!("Unknown assignment target!"),
             },
 
-            IExpr::Call { callee, args } => self.call(callee, args),
+            IExpr::Call { callee, args, pos } => self.call(callee, args, *pos),
+
+            IExpr::Assert { cond, pos } => self.assert(cond, *pos),
+
+            IExpr::Panic { msg, pos } => {
+                self.raise(Error::new(*pos, E601(msg.clone())));
+                values(&[])
+            }
+
+            IExpr::Cast { value } => self.cast(value, &expr.typ()),
+
+            IExpr::Builtin { builtin, args, pos } => self.builtin(*builtin, args, *pos),
+
+            IExpr::StructLit { fields } => self.struct_lit(fields),
 
             IExpr::Poison => panic!("Cannot translate poison values!"),
         }
@@ -77,35 +110,187 @@ impl<'b> FnTranslator<'b> {
         }
     }
 
-    fn binary(&mut self, left: &ir::Expr, op: TKind, right: &ir::Expr) -> Value {
+    fn binary(&mut self, left: &ir::Expr, op: &Token, right: &ir::Expr) -> Value {
         let l = self.trans_expr(left)[0];
         let r = self.trans_expr(right)[0];
 
         if left.typ().is_int() {
-            match op {
-                TKind::Plus => self.cl.ins().iadd(l, r),
-                TKind::Minus => self.cl.ins().isub(l, r),
-                TKind::Star => self.cl.ins().imul(l, r),
+            match op.kind {
+                TKind::Plus | TKind::Minus | TKind::Star => self.int_arith(l, op, r),
+                TKind::StarStar => self.int_pow(l, op, r),
                 TKind::Slash => self.cl.ins().udiv(l, r),
-                _ => self.cl.ins().icmp(intcmp(op), l, r),
+                _ => self.cl.ins().icmp(intcmp(op.kind), l, r),
             }
         } else {
-            match op {
+            match op.kind {
                 TKind::Plus => self.cl.ins().fadd(l, r),
                 TKind::Minus => self.cl.ins().fsub(l, r),
                 TKind::Star => self.cl.ins().fmul(l, r),
                 TKind::Slash => self.cl.ins().fdiv(l, r),
-                _ => self.cl.ins().fcmp(floatcmp(op), l, r),
+                TKind::StarStar => self.float_pow(l, r),
+                _ => self.cl.ins().fcmp(floatcmp(op.kind), l, r),
             }
         }
     }
 
+    /// Integer `+`, `-` or `*` (also used by `**`), handling overflow according to the arithmetic mode.
+    fn int_arith(&mut self, l: Value, op: &Token, r: Value) -> Value {
+        let res = match op.kind {
+            TKind::Plus => self.cl.ins().iadd(l, r),
+            TKind::Minus => self.cl.ins().isub(l, r),
+            _ => self.cl.ins().imul(l, r),
+        };
+        let mode = self.options.arithmetic;
+        if mode == ArithmeticMode::Wrapping {
+            return res;
+        }
+
+        // Sign bit of `sign` is the sign of the actual result when overflowing
+        let (overflow, sign) = match op.kind {
+            // Overflow if both operands have a different sign than the result
+            TKind::Plus => {
+                let a = self.cl.ins().bxor(l, res);
+                let b = self.cl.ins().bxor(r, res);
+                (self.sign_set(a, b), l)
+            }
+            // Overflow if operands differ in sign and the result's sign is not the left one's
+            TKind::Minus => {
+                let a = self.cl.ins().bxor(l, r);
+                let b = self.cl.ins().bxor(l, res);
+                (self.sign_set(a, b), l)
+            }
+            // Overflow if the high half is not the sign extension of the result
+            _ => {
+                let high = self.cl.ins().smulhi(l, r);
+                let extension = self.cl.ins().sshr_imm(res, 63);
+                let overflow = self.cl.ins().icmp(IntCC::NotEqual, high, extension);
+                (overflow, self.cl.ins().bxor(l, r))
+            }
+        };
+
+        if mode == ArithmeticMode::Checked {
+            let fail_b = self.new_block();
+            let cont_b = self.new_block();
+            self.br(overflow, fail_b, cont_b);
+
+            self.switch_block(fail_b);
+            self.cl.seal_block(fail_b);
+            self.raise(Error::new(op.start, E602));
+            self.cl.ins().jump(cont_b, &[]);
+
+            self.switch_block(cont_b);
+            self.cl.seal_block(cont_b);
+            res
+        } else {
+            // MAX if the result should be positive, MIN if negative
+            let sign = self.cl.ins().sshr_imm(sign, 63);
+            let saturated = self.cl.ins().bxor_imm(sign, i64::MAX);
+            self.cl.ins().select(overflow, saturated, res)
+        }
+    }
+
+    /// Integer `**` using exponentiation by squaring, with each multiplication
+    /// handling overflow like `*`. Negative exponents raise an error.
+    fn int_pow(&mut self, base: Value, op: &Token, exp: Value) -> Value {
+        let non_negative = self
+            .cl
+            .ins()
+            .icmp_imm(IntCC::SignedGreaterThanOrEqual, exp, 0);
+        self.check(non_negative, Error::new(op.start, E604));
+
+        let header_b = self.new_block();
+        let step_b = self.new_block();
+        let mul_b = self.new_block();
+        let shift_b = self.new_block();
+        let square_b = self.new_block();
+        let exit_b = self.new_block();
+        for _ in 0..3 {
+            self.cl.append_block_param(header_b, types::I64);
+        }
+        self.cl.append_block_param(shift_b, types::I64);
+        self.cl.append_block_param(exit_b, types::I64);
+
+        let one = self.cl.ins().iconst(types::I64, 1);
+        self.cl.ins().jump(header_b, &[one, base, exp]);
+
+        // Loop while the exponent is not zero
+        self.switch_block(header_b);
+        let params = self.cl.block_params(header_b);
+        let (result, base, exp) = (params[0], params[1], params[2]);
+        self.cl.ins().brz(exp, exit_b, &[result]);
+        self.cl.ins().jump(step_b, &[]);
+
+        // Multiply the result by the base for each set bit
+        self.switch_block(step_b);
+        self.cl.seal_block(step_b);
+        let odd = self.cl.ins().band_imm(exp, 1);
+        self.cl.ins().brz(odd, shift_b, &[result]);
+        self.cl.ins().jump(mul_b, &[]);
+
+        self.switch_block(mul_b);
+        self.cl.seal_block(mul_b);
+        let multiplied = self.int_arith(result, op, base);
+        self.cl.ins().jump(shift_b, &[multiplied]);
+
+        // Only square the base if it is still needed, to not overflow needlessly
+        self.switch_block(shift_b);
+        self.cl.seal_block(shift_b);
+        let result = self.cl.block_params(shift_b)[0];
+        let exp = self.cl.ins().ushr_imm(exp, 1);
+        self.cl.ins().brz(exp, exit_b, &[result]);
+        self.cl.ins().jump(square_b, &[]);
+
+        self.switch_block(square_b);
+        self.cl.seal_block(square_b);
+        let base = self.int_arith(base, op, base);
+        self.cl.ins().jump(header_b, &[result, base, exp]);
+        self.cl.seal_block(header_b);
+
+        self.switch_block(exit_b);
+        self.cl.seal_block(exit_b);
+        self.cl.block_params(exit_b)[0]
+    }
+
+    /// Float `**`, calling into the runtime.
+    fn float_pow(&mut self, base: Value, exp: Value) -> Value {
+        let float = &[types::F64, types::F64];
+        self.call_runtime(runtime::POW_SYMBOL, float, &float[..1], &[base, exp])[0]
+    }
+
+    /// Call a function of the runtime with the given signature.
+    pub(super) fn call_runtime(
+        &mut self,
+        symbol: &str,
+        params: &[Type],
+        returns: &[Type],
+        args: &[Value],
+    ) -> CValue {
+        let mut sig = self.ir_module.make_signature();
+        sig.params
+            .extend(params.iter().map(|ty| AbiParam::new(*ty)));
+        sig.returns
+            .extend(returns.iter().map(|ty| AbiParam::new(*ty)));
+        let id = self
+            .ir_module
+            .declare_function(symbol, Linkage::Import, &sig)
+            .unwrap();
+        let func = self.ir_module.declare_func_in_func(id, &mut self.cl.func);
+        let call = self.cl.ins().call(func, args);
+        values(self.cl.inst_results(call))
+    }
+
+    /// Returns if the sign bit is set in both values.
+    fn sign_set(&mut self, a: Value, b: Value) -> Value {
+        let both = self.cl.ins().band(a, b);
+        self.cl.ins().icmp_imm(IntCC::SignedLessThan, both, 0)
+    }
+
     fn constant(&mut self, constant: &Constant) -> Value {
         match constant {
             Constant::Bool(val) => self.cl.ins().bconst(types::B1, *val),
             Constant::Int(int) => self.cl.ins().iconst(types::I64, *int),
             Constant::Float(float) => self.cl.ins().f64const(*float),
-            Constant::String(_) => unimplemented!(),
+            Constant::String(_) => unreachable!(),
 
             // Functions/Classes are always their own types, so their values are essentially zero-sized.
             // However, cranelift of course does not have zero-sized values,
@@ -115,6 +300,13 @@ impl<'b> FnTranslator<'b> {
         }
     }
 
+    fn string(&mut self, string: &str) -> CValue {
+        let (ptr, len) = self.runtime.add_string(string);
+        let ptr = self.cl.ins().iconst(CLIF_PTR, ptr);
+        let len = self.cl.ins().iconst(types::I64, len);
+        values(&[ptr, len])
+    }
+
     fn if_(&mut self, cond: &ir::Expr, phi: bool, then: &ir::Expr, els: &ir::Expr) -> CValue {
         let condition = self.trans_expr(cond);
         let then_b = self.new_block();
@@ -150,7 +342,9 @@ impl<'b> FnTranslator<'b> {
         self.cl.ins().jump(body_b, &[]);
         self.switch_block(body_b);
         self.cl.seal_block(body_b);
+        self.loops.push((head_b, cont_b));
         self.trans_expr(body);
+        self.loops.pop();
         self.cl.ins().jump(head_b, &[]);
         self.cl.switch_to_block(cont_b);
         self.cl.seal_block(head_b);
@@ -176,11 +370,177 @@ impl<'b> FnTranslator<'b> {
         value
     }
 
-    fn call(&mut self, callee: &Expr, args: &SmallVec<[Expr; 4]>) -> CValue {
-        let func_id = {
+    /// Evaluate the fields in source order, then lay them out by member index.
+    fn struct_lit(&mut self, fields: &[(usize, Expr)]) -> CValue {
+        let mut members = fields
+            .iter()
+            .map(|(index, field)| (*index, self.trans_expr(field)))
+            .collect::<Vec<_>>();
+        members.sort_by_key(|(index, _)| *index);
+        members.into_iter().flat_map(|(_, value)| value).collect()
+    }
+
+    fn assert(&mut self, cond: &Expr, pos: Position) -> CValue {
+        let condition = self.trans_expr(cond)[0];
+        self.check(condition, Error::new(pos, E600));
+        values(&[])
+    }
+
+    /// Raise the given error if `condition` is false.
+    fn check(&mut self, condition: Value, error: Error) {
+        let fail_b = self.new_block();
+        let cont_b = self.new_block();
+        self.br(condition, cont_b, fail_b);
+
+        self.switch_block(fail_b);
+        self.cl.seal_block(fail_b);
+        self.raise(error);
+        self.cl.ins().jump(cont_b, &[]);
+
+        self.switch_block(cont_b);
+        self.cl.seal_block(cont_b);
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[Expr], pos: Position) -> CValue {
+        let mut vals = CValue::new();
+        for arg in args {
+            vals.extend(self.trans_expr(arg));
+        }
+
+        // Unsigned comparisons also catch negative indices
+        match builtin {
+            Builtin::Len => value(vals[1]),
+
+            Builtin::CharAt => {
+                let (ptr, len, index) = (vals[0], vals[1], vals[2]);
+                let in_bounds = self.cl.ins().icmp(IntCC::UnsignedLessThan, index, len);
+                self.check(in_bounds, Error::new(pos, E603));
+                let addr = self.cl.ins().iadd(ptr, index);
+                let byte = self.cl.ins().load(types::I8, MemFlags::trusted(), addr, 0);
+                value(self.cl.ins().uextend(types::I64, byte))
+            }
+
+            Builtin::Substring => {
+                let (ptr, len, start, end) = (vals[0], vals[1], vals[2], vals[3]);
+                let ordered = self
+                    .cl
+                    .ins()
+                    .icmp(IntCC::UnsignedLessThanOrEqual, start, end);
+                let in_bounds = self.cl.ins().icmp(IntCC::UnsignedLessThanOrEqual, end, len);
+                let valid = self.cl.ins().band(ordered, in_bounds);
+                self.check(valid, Error::new(pos, E603));
+                let ptr = self.cl.ins().iadd(ptr, start);
+                let len = self.cl.ins().isub(end, start);
+                values(&[ptr, len])
+            }
+
+            Builtin::Print | Builtin::Println => {
+                let symbol = if builtin == Builtin::Print {
+                    runtime::PRINT_SYMBOL
+                } else {
+                    runtime::PRINTLN_SYMBOL
+                };
+                let state = self.cl.ins().iconst(CLIF_PTR, self.runtime.state_ptr());
+                let params = &[CLIF_PTR, CLIF_PTR, types::I64];
+                self.call_runtime(symbol, params, &[], &[state, vals[0], vals[1]])
+            }
+
+            Builtin::Str => {
+                let state = self.cl.ins().iconst(CLIF_PTR, self.runtime.state_ptr());
+                let params = &[CLIF_PTR, types::I64];
+                let returns = &[CLIF_PTR, types::I64];
+                self.call_runtime(
+                    runtime::INT_TO_STR_SYMBOL,
+                    params,
+                    returns,
+                    &[state, vals[0]],
+                )
+            }
+
+            Builtin::Abs => value(self.abs(vals[0], pos)),
+
+            Builtin::Min | Builtin::Max => {
+                let cc = if builtin == Builtin::Min {
+                    IntCC::SignedLessThan
+                } else {
+                    IntCC::SignedGreaterThan
+                };
+                let (a, b) = (vals[0], vals[1]);
+                let first = self.cl.ins().icmp(cc, a, b);
+                value(self.cl.ins().select(first, a, b))
+            }
+
+            Builtin::Sqrt => value(self.cl.ins().sqrt(vals[0])),
+
+            Builtin::Seed => {
+                let state = self.cl.ins().iconst(CLIF_PTR, self.runtime.state_ptr());
+                let params = &[CLIF_PTR, types::I64];
+                self.call_runtime(runtime::SEED_SYMBOL, params, &[], &[state, vals[0]])
+            }
+
+            Builtin::Rand => {
+                let state = self.cl.ins().iconst(CLIF_PTR, self.runtime.state_ptr());
+                self.call_runtime(runtime::RAND_SYMBOL, &[CLIF_PTR], &[types::F64], &[state])
+            }
+
+            Builtin::RandInt => {
+                let (lo, hi) = (vals[0], vals[1]);
+                let non_empty = self.cl.ins().icmp(IntCC::SignedLessThan, lo, hi);
+                self.check(non_empty, Error::new(pos, E605));
+                let state = self.cl.ins().iconst(CLIF_PTR, self.runtime.state_ptr());
+                let params = &[CLIF_PTR, types::I64, types::I64];
+                let returns = &[types::I64];
+                self.call_runtime(runtime::RAND_INT_SYMBOL, params, returns, &[state, lo, hi])
+            }
+
+            Builtin::AssertEq => unreachable!("assert_eq is lowered by the compiler"),
+        }
+    }
+
+    /// Absolute value of an integer; the minimum value overflows
+    /// according to the arithmetic mode.
+    fn abs(&mut self, int: Value, pos: Position) -> Value {
+        let negated = self.cl.ins().ineg(int);
+        let negative = self.cl.ins().icmp_imm(IntCC::SignedLessThan, int, 0);
+        let abs = self.cl.ins().select(negative, negated, int);
+        match self.options.arithmetic {
+            ArithmeticMode::Wrapping => abs,
+            ArithmeticMode::Saturating => {
+                let overflow = self.cl.ins().icmp_imm(IntCC::SignedLessThan, abs, 0);
+                let max = self.cl.ins().iconst(types::I64, i64::MAX);
+                self.cl.ins().select(overflow, max, abs)
+            }
+            ArithmeticMode::Checked => {
+                let valid = self
+                    .cl
+                    .ins()
+                    .icmp_imm(IntCC::SignedGreaterThanOrEqual, abs, 0);
+                self.check(valid, Error::new(pos, E602));
+                abs
+            }
+        }
+    }
+
+    fn cast(&mut self, expr: &Expr, to: &ir::Type) -> CValue {
+        let val = self.trans_expr(expr);
+        let ins = self.cl.ins();
+        match (expr.typ(), to) {
+            (ir::Type::I64, ir::Type::F64) => value(ins.fcvt_from_sint(types::F64, val[0])),
+            // Saturates instead of trapping on NaN and out-of-range values
+            (ir::Type::F64, ir::Type::I64) => value(ins.fcvt_to_sint_sat(types::I64, val[0])),
+            (ir::Type::Bool, ir::Type::I64) => value(ins.bint(types::I64, val[0])),
+            _ => val,
+        }
+    }
+
+    fn call(&mut self, callee: &Expr, args: &SmallVec<[Expr; 4]>, pos: Position) -> CValue {
+        let (func_id, is_extern) = {
             let func = callee.typ().into_fn();
             let func = func.resolve();
-            get_or_declare_ir_fn(&mut self.ir_module, &*func)
+            (
+                get_or_declare_ir_fn(&mut self.ir_module, &*func),
+                func.ast.body.is_none(),
+            )
         };
 
         let local_callee = self
@@ -195,7 +555,13 @@ impl<'b> FnTranslator<'b> {
             }
         }
         let call = self.cl.ins().call(local_callee, &call_args);
-        values(self.cl.inst_results(call))
+        let results = values(self.cl.inst_results(call));
+
+        // Extern functions are not able to raise errors
+        if !is_extern {
+            self.check_unwind(pos);
+        }
+        results
     }
 }
 
@@ -1,7 +1,14 @@
 use super::clif;
 use crate::{
     compiler::{ir, ir::Module},
-    vm::typesys,
+    error::{Error, Position},
+    options::Options,
+    vm::{
+        runtime,
+        runtime::Runtime,
+        typesys,
+        typesys::{CValue, CLIF_PTR},
+    },
 };
 use alloc::vec::Vec;
 use cranelift::{
@@ -20,8 +27,12 @@ pub struct FnTranslator<'b> {
     local_offsets: SmallVec<[usize; 6]>,
     blocks: SmallVec<[Block; 5]>,
     current_block: Block,
+    /// Condition and exit block of the enclosing loops, innermost last.
+    loops: Vec<(Block, Block)>,
     ir_module: &'b mut JITModule,
     ya_module: &'b Module,
+    runtime: &'b mut Runtime,
+    options: &'b Options,
 }
 
 impl<'b> FnTranslator<'b> {
@@ -74,6 +85,67 @@ impl<'b> FnTranslator<'b> {
         });
     }
 
+    /// Raise the given runtime error, returning from the function.
+    /// Translation continues in a new, unreachable block.
+    fn raise(&mut self, error: Error) {
+        let site = self.runtime.add_site(error, self.func.name.clone());
+        let state = self.cl.ins().iconst(CLIF_PTR, self.runtime.state_ptr());
+        let site = self.cl.ins().iconst(CLIF_PTR, site as i64);
+        self.cl.ins().store(MemFlags::trusted(), site, state, 0);
+        self.unwind();
+    }
+
+    /// Check if the runtime error state is set by the callee of
+    /// the call at `pos`, recording the call and unwinding if so.
+    fn check_unwind(&mut self, pos: Position) {
+        let state = self.cl.ins().iconst(CLIF_PTR, self.runtime.state_ptr());
+        let error = self.cl.ins().load(CLIF_PTR, MemFlags::trusted(), state, 0);
+        let unwind_b = self.new_block();
+        let cont_b = self.new_block();
+        self.cl.ins().brnz(error, unwind_b, &[]);
+        self.cl.ins().jump(cont_b, &[]);
+
+        self.switch_block(unwind_b);
+        self.cl.seal_block(unwind_b);
+        let frame = self.runtime.add_frame(self.func.name.clone(), pos.line);
+        let frame = self.cl.ins().iconst(CLIF_PTR, frame as i64);
+        self.call_runtime(
+            runtime::TRACE_SYMBOL,
+            &[CLIF_PTR, CLIF_PTR],
+            &[],
+            &[state, frame],
+        );
+        self.unwind();
+        self.cl.ins().jump(cont_b, &[]);
+        self.switch_block(cont_b);
+        self.cl.seal_block(cont_b);
+    }
+
+    /// Return from the function with zeroed values, used after
+    /// a runtime error occurred.
+    /// Translation continues in a new, unreachable block.
+    fn unwind(&mut self) {
+        let mut ret = CValue::new();
+        let cl = &mut self.cl;
+        typesys::translate_type(&self.func.ret_type, |_, ty| {
+            ret.push(typesys::zero_value(cl, ty))
+        });
+        self.cl.ins().return_(&ret);
+
+        let dead_b = self.new_block();
+        self.switch_block(dead_b);
+        self.cl.seal_block(dead_b);
+    }
+
+    /// Jump to the given block, for leaving a loop early.
+    /// Translation continues in a new, unreachable block.
+    fn jump_out(&mut self, target: Block) {
+        self.cl.ins().jump(target, &[]);
+        let dead_b = self.new_block();
+        self.switch_block(dead_b);
+        self.cl.seal_block(dead_b);
+    }
+
     fn new_block(&mut self) -> Block {
         let block = self.cl.create_block();
         self.blocks.push(block);
@@ -97,6 +169,8 @@ impl<'b> FnTranslator<'b> {
         ctx: &'b mut FunctionBuilderContext,
         ir_module: &'b mut JITModule,
         ya_module: &'b Module,
+        runtime: &'b mut Runtime,
+        options: &'b Options,
     ) -> Self {
         Self {
             func,
@@ -104,8 +178,11 @@ impl<'b> FnTranslator<'b> {
             local_offsets: SmallVec::from_slice(&[0]),
             blocks: SmallVec::new(),
             current_block: Block::with_number(0).unwrap(),
+            loops: Vec::new(),
             ir_module,
             ya_module,
+            runtime,
+            options,
         }
     }
 }
@@ -0,0 +1,57 @@
+use alloc::vec::Vec;
+
+/// A table of host resources, such as open files or graphics contexts,
+/// that scripts refer to by opaque `handle` values.
+/// Scripts can only store handles and pass them to extern functions,
+/// which look up the resource with `get`.
+pub struct Handles<T> {
+    slots: Vec<Option<T>>,
+}
+
+impl<T> Handles<T> {
+    /// Store a resource, returning the handle to pass to scripts.
+    /// Handles are never 0, so it can be used as an invalid handle.
+    pub fn insert(&mut self, value: T) -> u64 {
+        let index = match self.slots.iter().position(Option::is_none) {
+            Some(index) => {
+                self.slots[index] = Some(value);
+                index
+            }
+            None => {
+                self.slots.push(Some(value));
+                self.slots.len() - 1
+            }
+        };
+        index as u64 + 1
+    }
+
+    pub fn get(&self, handle: u64) -> Option<&T> {
+        let index = Self::index(handle)?;
+        self.slots.get(index)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: u64) -> Option<&mut T> {
+        let index = Self::index(handle)?;
+        self.slots.get_mut(index)?.as_mut()
+    }
+
+    /// Remove a resource, after which its handle may be reused.
+    pub fn remove(&mut self, handle: u64) -> Option<T> {
+        let index = Self::index(handle)?;
+        self.slots.get_mut(index)?.take()
+    }
+
+    fn index(handle: u64) -> Option<usize> {
+        (handle as usize).checked_sub(1)
+    }
+
+    pub const fn new() -> Self {
+        Self { slots: Vec::new() }
+    }
+}
+
+impl<T> Default for Handles<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
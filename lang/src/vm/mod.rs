@@ -1,7 +1,20 @@
 mod function;
+mod handles;
+mod runtime;
 mod typesys;
 
-use crate::{compiler::ir, vm::function::FnTranslator};
+use crate::{
+    compiler::ir,
+    error::{Error, ErrorKind::*},
+    options::Options,
+    smol_str::SmolStr,
+    vm::{function::FnTranslator, runtime::Runtime, typesys::CLIF_PTR},
+};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
 use core::mem;
 use cranelift::{
     codegen::{
@@ -11,20 +24,37 @@ use cranelift::{
     prelude::*,
 };
 use cranelift_jit::{JITBuilder, JITModule};
-use cranelift_module::{DataContext, FuncId, FuncOrDataId, Linkage, Module};
+use cranelift_module::{DataContext, FuncId, FuncOrDataId, Linkage, Module, ModuleError};
+
+pub use handles::Handles;
+pub use runtime::{StrRet, Value};
 
 pub type SymbolTable<'t> = &'t [(&'t str, *const u8)];
 
+/// Prefix of the symbol of the generated function used to call an entry
+/// point with arguments, followed by the ID of the entry point.
+const TRAMPOLINE_SYMBOL: &str = "yacari_entry_trampoline";
+
+/// An entry point taking parameters, called through a trampoline
+/// that loads its arguments from an array of words.
+struct EntryPoint {
+    trampoline: FuncId,
+    params: Vec<ir::Type>,
+}
+
 #[allow(unused)]
 pub struct JIT {
     builder_context: FunctionBuilderContext,
     ctx: codegen::Context,
     data_ctx: DataContext,
     module: JITModule,
+    runtime: Runtime,
+    options: Options,
+    entry: Option<EntryPoint>,
 }
 
 impl JIT {
-    pub(crate) fn jit_module(&mut self, module: &ir::Module) {
+    pub(crate) fn jit_module(&mut self, module: &ir::Module) -> Result<(), Error> {
         for func in module.funcs.iter().filter(|f| f.ast.body.is_some()) {
             make_fn_sig(&mut self.ctx.func.signature, func);
             let id = declare_ir_function(&mut self.module, func, &self.ctx.func.signature);
@@ -34,41 +64,191 @@ impl JIT {
                 &mut self.builder_context,
                 &mut self.module,
                 &module,
+                &mut self.runtime,
+                &self.options,
             );
             translator.build();
 
-            self.module
-                .define_function(
-                    id,
-                    &mut self.ctx,
-                    &mut NullTrapSink {},
-                    &mut NullStackMapSink {},
-                )
-                .unwrap();
+            let defined = self.module.define_function(
+                id,
+                &mut self.ctx,
+                &mut NullTrapSink {},
+                &mut NullStackMapSink {},
+            );
             self.module.clear_context(&mut self.ctx);
+            defined.map_err(codegen_error)?;
+        }
+
+        let entry_point = SmolStr::new(self.options.entry_point());
+        let entry = module
+            .funcs
+            .iter()
+            .find(|f| f.ast.body.is_some() && f.name == entry_point && !f.params.is_empty());
+        if let Some(func) = entry {
+            let entry = self.make_trampoline(func).map_err(codegen_error)?;
+            self.entry = Some(entry);
         }
 
         self.module.finalize_definitions();
+        Ok(())
+    }
+
+    /// Generate a function taking a pointer to one word per
+    /// parameter value, which calls `func` with them.
+    fn make_trampoline(&mut self, func: &ir::Function) -> Result<EntryPoint, ModuleError> {
+        let callee_id = get_or_declare_ir_fn(&mut self.module, func);
+        let mut callee_sig = self.module.make_signature();
+        make_fn_sig(&mut callee_sig, func);
+
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(CLIF_PTR));
+        sig.returns = callee_sig.returns.clone();
+        // Every module JITted may have its own entry point
+        let name = format!("{}_{}", TRAMPOLINE_SYMBOL, callee_id);
+        let id = self.module.declare_function(&name, Linkage::Local, &sig)?;
+
+        self.ctx.func.signature = sig;
+        let mut cl = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+        let block = cl.create_block();
+        cl.append_block_params_for_function_params(block);
+        cl.switch_to_block(block);
+        cl.seal_block(block);
+
+        let words = cl.block_params(block)[0];
+        let mut args = Vec::with_capacity(callee_sig.params.len());
+        for (i, param) in callee_sig.params.iter().enumerate() {
+            let offset = (i * 8) as i32;
+            let arg = if param.value_type == types::B1 {
+                let byte = cl.ins().load(types::I8, MemFlags::trusted(), words, offset);
+                cl.ins().icmp_imm(IntCC::NotEqual, byte, 0)
+            } else {
+                cl.ins()
+                    .load(param.value_type, MemFlags::trusted(), words, offset)
+            };
+            args.push(arg);
+        }
+        let callee = self.module.declare_func_in_func(callee_id, &mut cl.func);
+        let call = cl.ins().call(callee, &args);
+        let results = cl.inst_results(call).to_vec();
+        cl.ins().return_(&results);
+        cl.finalize();
+
+        let defined = self.module.define_function(
+            id,
+            &mut self.ctx,
+            &mut NullTrapSink {},
+            &mut NullStackMapSink {},
+        );
+        self.module.clear_context(&mut self.ctx);
+        defined?;
+
+        Ok(EntryPoint {
+            trampoline: id,
+            params: func.params.iter().map(|p| p.ty.clone()).collect(),
+        })
     }
 
-    pub fn exec<T>(&mut self, name: &str) -> T {
-        let id = self.module.get_name(name).unwrap();
-        let id = if let FuncOrDataId::Func(id) = id {
-            id
-        } else {
-            panic!()
+    /// Run the entry point function set in the options with the given arguments,
+    /// which must match its parameters.
+    pub fn exec_entry<T>(&self, args: &[Value]) -> Result<T, Error> {
+        let name = self.options.entry_point();
+        let entry = match &self.entry {
+            Some(entry) => entry,
+            None if args.is_empty() => return self.exec(name),
+            None => {
+                // Report a missing entry point first
+                self.find_function(name)?;
+                return Err(Error::new(
+                    Default::default(),
+                    E507 {
+                        expected: 0,
+                        found: args.len(),
+                    },
+                ));
+            }
         };
 
+        if entry.params.len() != args.len() {
+            return Err(Error::new(
+                Default::default(),
+                E507 {
+                    expected: entry.params.len(),
+                    found: args.len(),
+                },
+            ));
+        }
+        let mut words = Vec::with_capacity(args.len() * 2);
+        for (pos, (ty, arg)) in entry.params.iter().zip(args).enumerate() {
+            if !arg.push_words(ty, &mut words) {
+                return Err(Error::new(
+                    Default::default(),
+                    E508 {
+                        expected: ty.to_string(),
+                        found: arg.type_name().to_string(),
+                        pos,
+                    },
+                ));
+            }
+        }
+
+        let ptr = self.module.get_finalized_function(entry.trampoline);
+        let func = unsafe { mem::transmute::<_, fn(*const u64) -> T>(ptr) };
+        self.runtime.start();
+        self.finish(func(words.as_ptr()))
+    }
+
+    fn find_function(&self, name: &str) -> Result<FuncId, Error> {
+        match self.module.get_name(name) {
+            Some(FuncOrDataId::Func(id)) => Ok(id),
+            _ => Err(Error::new(Default::default(), E203(name.into()))),
+        }
+    }
+
+    /// Run the function with the given name, which must not take parameters.
+    pub fn exec<T>(&self, name: &str) -> Result<T, Error> {
+        let id = self.find_function(name)?;
+        let decl = self.module.declarations().get_function_decl(id);
+        if !decl.signature.params.is_empty() {
+            return Err(Error::new(Default::default(), E513(name.into())));
+        }
+
         let ptr = self.module.get_finalized_function(id);
         let func = unsafe { mem::transmute::<_, fn() -> T>(ptr) };
-        func()
+        self.runtime.start();
+        self.finish(func())
+    }
+
+    /// Check for a runtime error after compiled code returned `ret`.
+    fn finish<T>(&self, ret: T) -> Result<T, Error> {
+        match self.runtime.take_error() {
+            Some(err) => {
+                // The return value was zeroed during unwinding and might not be valid
+                mem::forget(ret);
+                Err(err)
+            }
+            None => Ok(ret),
+        }
     }
 
-    pub fn new(symbols: SymbolTable) -> Self {
+    /// Returns everything printed since the last call,
+    /// if output is not sent elsewhere by the options.
+    pub fn take_output(&self) -> String {
+        self.runtime.take_output()
+    }
+
+    pub fn new(symbols: SymbolTable, options: &Options) -> Self {
         let mut builder = JITBuilder::new(cranelift_module::default_libcall_names());
         for (name, ptr) in symbols {
             builder.symbol(*name, *ptr);
         }
+        builder.symbol(runtime::PRINT_SYMBOL, runtime::print as *const u8);
+        builder.symbol(runtime::PRINTLN_SYMBOL, runtime::println as *const u8);
+        builder.symbol(runtime::INT_TO_STR_SYMBOL, runtime::int_to_str as *const u8);
+        builder.symbol(runtime::POW_SYMBOL, runtime::pow as *const u8);
+        builder.symbol(runtime::TRACE_SYMBOL, runtime::trace as *const u8);
+        builder.symbol(runtime::SEED_SYMBOL, runtime::seed as *const u8);
+        builder.symbol(runtime::RAND_SYMBOL, runtime::rand as *const u8);
+        builder.symbol(runtime::RAND_INT_SYMBOL, runtime::rand_int as *const u8);
 
         let module = JITModule::new(builder);
         Self {
@@ -76,10 +256,19 @@ impl JIT {
             ctx: module.make_context(),
             data_ctx: DataContext::new(),
             module,
+            runtime: Runtime::new(options.output),
+            options: options.clone(),
+            entry: None,
         }
     }
 }
 
+/// Cranelift rejecting generated code is a bug in the compiler,
+/// but reported instead of panicking to not take down the host.
+fn codegen_error(err: ModuleError) -> Error {
+    Error::new(Default::default(), E523(format!("{:?}", err)))
+}
+
 fn get_or_declare_ir_fn(module: &mut JITModule, func: &ir::Function) -> FuncId {
     let mut ir = func.ir.borrow_mut();
     if let Some(ir) = *ir {
@@ -0,0 +1,285 @@
+use crate::{
+    compiler::ir,
+    error::{Error, Frame},
+    smol_str::SmolStr,
+};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    cell::{Cell, RefCell},
+    slice, str,
+};
+
+/// A value passed by the host as argument to the entry point,
+/// or returned to it by a script.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Value<'v> {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(&'v str),
+    /// A handle to a host resource, usually from `Handles`.
+    Handle(u64),
+}
+
+impl Value<'_> {
+    /// Returns the name of the type this value has in the language.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "i64",
+            Value::Float(_) => "f64",
+            Value::Bool(_) => "bool",
+            Value::Str(_) => "str",
+            Value::Handle(_) => "handle",
+        }
+    }
+
+    /// Append the words compiled code expects for this value to `words`,
+    /// if it is of the given type.
+    pub fn push_words(&self, ty: &ir::Type, words: &mut Vec<u64>) -> bool {
+        match (self, ty) {
+            (Value::Int(int), ir::Type::I64) => words.push(*int as u64),
+            (Value::Float(float), ir::Type::F64) => words.push(float.to_bits()),
+            (Value::Bool(b), ir::Type::Bool) => words.push(*b as u64),
+            (Value::Handle(handle), ir::Type::Handle) => words.push(*handle),
+            (Value::Str(s), ir::Type::String) => {
+                words.push(s.as_ptr() as u64);
+                words.push(s.len() as u64);
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Symbol of the function called by compiled code for `print`.
+pub const PRINT_SYMBOL: &str = "yacari_print";
+
+/// Symbol of the function called by compiled code for `println`.
+pub const PRINTLN_SYMBOL: &str = "yacari_println";
+
+/// Symbol of the function called by compiled code for `str`.
+pub const INT_TO_STR_SYMBOL: &str = "yacari_int_to_str";
+
+/// Symbol of the function called by compiled code for float `**`.
+pub const POW_SYMBOL: &str = "yacari_pow";
+
+/// Symbol of the function called by compiled code for `seed`.
+pub const SEED_SYMBOL: &str = "yacari_seed";
+
+/// Symbol of the function called by compiled code for `rand`.
+pub const RAND_SYMBOL: &str = "yacari_rand";
+
+/// Symbol of the function called by compiled code for `rand_int`.
+pub const RAND_INT_SYMBOL: &str = "yacari_rand_int";
+
+/// Seed of the random numbers if the program does not set one.
+const DEFAULT_SEED: u64 = 0x853C_49E6_748F_EA9B;
+
+/// Symbol of the function called by compiled code when a runtime error
+/// passes through a call, to record it in the backtrace.
+pub const TRACE_SYMBOL: &str = "yacari_trace";
+
+/// State shared between the host and compiled code.
+/// Compiled code writes to it to signal runtime errors;
+/// the layout is relied upon by the code generator.
+#[repr(C)]
+pub struct RuntimeState {
+    /// The site that raised an error (index + 1), 0 if none did.
+    error: Cell<usize>,
+    /// Where printed text goes; collected into `output` if not set.
+    sink: Option<fn(&str)>,
+    output: RefCell<String>,
+    /// Strings created at runtime, kept alive until the next execution starts.
+    strings: RefCell<Vec<Box<str>>>,
+    /// Calls the current error passed through (frame index + 1), innermost first.
+    trace: RefCell<Vec<usize>>,
+    /// State of the random number generator.
+    rng: Cell<u64>,
+}
+
+impl RuntimeState {
+    /// Returns the next pseudo-random number, using SplitMix64.
+    fn next_random(&self) -> u64 {
+        let state = self.rng.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+        self.rng.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn output(&self, text: &str) {
+        match self.sink {
+            Some(sink) => sink(text),
+            None => self.output.borrow_mut().push_str(text),
+        }
+    }
+}
+
+/// Called by compiled code for `print`, with the runtime state
+/// and the string to print.
+pub extern "C" fn print(state: &RuntimeState, ptr: *const u8, len: usize) {
+    let bytes = unsafe { slice::from_raw_parts(ptr, len) };
+    // Substrings can split multi-byte characters
+    state.output(&String::from_utf8_lossy(bytes));
+}
+
+/// Called by compiled code for `println`, like `print`.
+pub extern "C" fn println(state: &RuntimeState, ptr: *const u8, len: usize) {
+    print(state, ptr, len);
+    state.output("\n");
+}
+
+/// A string returned to compiled code.
+#[repr(C)]
+pub struct StrRet {
+    ptr: *const u8,
+    len: usize,
+}
+
+impl StrRet {
+    /// Returns the string, or None if it is not valid UTF-8,
+    /// as substrings can split multi-byte characters.
+    ///
+    /// # Safety
+    /// The string must have been returned by compiled code
+    /// of a JIT that outlives the returned reference,
+    /// and that does not execute anything else before it is dropped.
+    pub unsafe fn as_str<'s>(&self) -> Option<&'s str> {
+        str::from_utf8(slice::from_raw_parts(self.ptr, self.len)).ok()
+    }
+}
+
+/// Called by compiled code for `str`, with the runtime state
+/// and the integer to format.
+pub extern "C" fn int_to_str(state: &RuntimeState, int: i64) -> StrRet {
+    let string = int.to_string().into_boxed_str();
+    let ret = StrRet {
+        ptr: string.as_ptr(),
+        len: string.len(),
+    };
+    state.strings.borrow_mut().push(string);
+    ret
+}
+
+/// Called by compiled code for float `**`.
+pub extern "C" fn pow(base: f64, exp: f64) -> f64 {
+    libm::pow(base, exp)
+}
+
+/// Called by compiled code for `seed`.
+pub extern "C" fn seed(state: &RuntimeState, seed: i64) {
+    state.rng.set(seed as u64);
+}
+
+/// Called by compiled code for `rand`.
+pub extern "C" fn rand(state: &RuntimeState) -> f64 {
+    // The top 53 bits fill the mantissa exactly
+    (state.next_random() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Called by compiled code for `rand_int`, which checked that `lo < hi`.
+pub extern "C" fn rand_int(state: &RuntimeState, lo: i64, hi: i64) -> i64 {
+    let range = hi.wrapping_sub(lo) as u64;
+    // Scaling instead of taking the remainder avoids favoring small values
+    let offset = ((state.next_random() as u128 * range as u128) >> 64) as u64;
+    lo.wrapping_add(offset as i64)
+}
+
+/// Called by compiled code when a callee raised a runtime error,
+/// with the runtime state and the frame of the call.
+pub extern "C" fn trace(state: &RuntimeState, frame: usize) {
+    state.trace.borrow_mut().push(frame);
+}
+
+/// Keeps track of all places in compiled code that
+/// can raise a runtime error, as well as the shared state
+/// used by compiled code to report them.
+pub struct Runtime {
+    state: Box<RuntimeState>,
+    /// Errors and the function raising them.
+    sites: Vec<(Error, SmolStr)>,
+    /// Calls to functions able to raise errors, for backtraces.
+    frames: Vec<Frame>,
+    /// String constants used by compiled code, which must stay alive as long as it.
+    strings: Vec<Box<str>>,
+}
+
+impl Runtime {
+    /// Register a new site in the given function that can raise the given error.
+    /// Returns the value compiled code should write to
+    /// `RuntimeState.error` to raise it.
+    pub fn add_site(&mut self, error: Error, function: SmolStr) -> usize {
+        self.sites.push((error, function));
+        self.sites.len()
+    }
+
+    /// Register a call in the given function and line.
+    /// Returns the value compiled code should pass to `trace`
+    /// when an error passes through the call.
+    pub fn add_frame(&mut self, function: SmolStr, line: usize) -> usize {
+        self.frames.push((function, line));
+        self.frames.len()
+    }
+
+    /// Store a string constant, returning its address and length.
+    pub fn add_string(&mut self, string: &str) -> (i64, i64) {
+        let string: Box<str> = String::from(string).into_boxed_str();
+        let res = (string.as_ptr() as i64, string.len() as i64);
+        self.strings.push(string);
+        res
+    }
+
+    /// Prepare for a new execution, freeing the strings
+    /// created by the previous one.
+    pub fn start(&self) {
+        self.state.strings.borrow_mut().clear();
+    }
+
+    /// Returns the error raised by the last execution, if any,
+    /// resetting the state for the next one.
+    pub fn take_error(&self) -> Option<Error> {
+        let trace = self.state.trace.take();
+        self.state.rng.set(DEFAULT_SEED);
+        match self.state.error.replace(0) {
+            0 => None,
+            site => {
+                let (error, function) = &self.sites[site - 1];
+                let mut backtrace = Vec::with_capacity(trace.len() + 1);
+                backtrace.push((function.clone(), error.position().line));
+                backtrace.extend(trace.iter().map(|frame| self.frames[frame - 1].clone()));
+                Some(error.clone().with_backtrace(backtrace))
+            }
+        }
+    }
+
+    /// Returns all output collected since the last call.
+    pub fn take_output(&self) -> String {
+        self.state.output.take()
+    }
+
+    /// The address of the runtime state, for use in compiled code.
+    pub fn state_ptr(&self) -> i64 {
+        self.state.error.as_ptr() as i64
+    }
+
+    pub fn new(sink: Option<fn(&str)>) -> Self {
+        Self {
+            state: Box::new(RuntimeState {
+                error: Cell::new(0),
+                sink,
+                output: RefCell::new(String::new()),
+                strings: RefCell::new(Vec::new()),
+                trace: RefCell::new(Vec::new()),
+                rng: Cell::new(DEFAULT_SEED),
+            }),
+            sites: Vec::new(),
+            frames: Vec::new(),
+            strings: Vec::new(),
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use super::clif;
 use crate::compiler::{ir, ir::ClassContent};
-use cranelift::prelude::*;
+use cranelift::{frontend::FunctionBuilder, prelude::*};
 use smallvec::SmallVec;
 
 pub type CValue = SmallVec<[Value; 3]>;
@@ -13,23 +13,41 @@ pub fn values(clif: &[Value]) -> CValue {
     SmallVec::from_slice(clif)
 }
 
+/// Returns a zero value of the given type, used when there is no meaningful value to return.
+pub fn zero_value(cl: &mut FunctionBuilder, ty: clif::Type) -> Value {
+    match ty {
+        types::B1 => cl.ins().bconst(types::B1, false),
+        types::F64 => cl.ins().f64const(0.0),
+        _ => cl.ins().iconst(ty, 0),
+    }
+}
+
 pub fn translate_type<T: FnMut(usize, clif::Type)>(typ: &ir::Type, mut adder: T) -> usize {
     translate_type_ref(typ, &mut adder)
 }
 
-fn translate_type_ref<T: FnMut(usize, clif::Type)>(typ: &ir::Type, adder: &mut T) -> usize {
+fn translate_type_ref(typ: &ir::Type, adder: &mut dyn FnMut(usize, clif::Type)) -> usize {
     match typ {
         ir::Type::Void | ir::Type::Poison => return 0,
         ir::Type::Bool => adder(0, types::B1),
         ir::Type::F64 => adder(0, types::F64),
-        ir::Type::I64 => adder(0, types::I64),
+        ir::Type::I64 | ir::Type::Handle => adder(0, types::I64),
+        ir::Type::String => {
+            adder(0, CLIF_PTR);
+            adder(1, types::I64);
+            return 2;
+        }
         ir::Type::Function(_) => adder(0, CLIF_PTR),
         ir::Type::Class(cls_ref) => {
             let mut count = 0;
             let cls = cls_ref.resolve();
             for mem in cls.content.borrow().values() {
                 match mem {
-                    ClassContent::Member(mem) => count += translate_type_ref(&mem.ty, adder),
+                    ClassContent::Member(mem) => {
+                        // Members are laid out one after another
+                        let base = count;
+                        count += translate_type_ref(&mem.ty, &mut |i, ty| adder(base + i, ty))
+                    }
                     _ => break,
                 }
             }